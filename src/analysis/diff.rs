@@ -0,0 +1,189 @@
+//! Word-level comparison of partial transcriptions against the final one.
+
+use serde::Serialize;
+
+/// One step of a word-level diff.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "op", content = "word", rename_all = "snake_case")]
+pub enum WordChange {
+    /// The word is present in both transcriptions.
+    Equal(String),
+    /// The word only appears in the newer transcription.
+    Insert(String),
+    /// The word only appears in the older transcription.
+    Delete(String),
+}
+
+/// Computes the word-level diff turning `old` into `new`.
+pub fn word_diff(old: &str, new: &str) -> Vec<WordChange> {
+    let old: Vec<&str> = old.split_whitespace().collect();
+    let new: Vec<&str> = new.split_whitespace().collect();
+
+    // Longest common subsequence table, filled from the end.
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut changes = Vec::with_capacity(old.len().max(new.len()));
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            changes.push(WordChange::Equal(old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            changes.push(WordChange::Delete(old[i].to_string()));
+            i += 1;
+        } else {
+            changes.push(WordChange::Insert(new[j].to_string()));
+            j += 1;
+        }
+    }
+    changes.extend(old[i..].iter().map(|w| WordChange::Delete(w.to_string())));
+    changes.extend(new[j..].iter().map(|w| WordChange::Insert(w.to_string())));
+    changes
+}
+
+/// How stable the partial transcriptions of a segment were.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StabilityReport {
+    /// The number of partial transcriptions received.
+    pub partials: usize,
+    /// The number of words in the final transcription.
+    pub final_words: usize,
+    /// How many times a word was removed or replaced from one hypothesis to the next.
+    pub flips: usize,
+    /// The average number of flips per hypothesis update.
+    pub flips_per_update: f64,
+    /// The share of final words already present, in order, in the last partial.
+    pub last_partial_accuracy: f64,
+    /// The diff between the last partial transcription and the final one.
+    pub final_diff: Vec<WordChange>,
+}
+
+/// Compares the partial transcriptions of a segment, in the order they were
+/// received, with its final transcription.
+pub fn analyse_segment<S: AsRef<str>>(partials: &[S], final_text: &str) -> StabilityReport {
+    let hypotheses: Vec<&str> = partials
+        .iter()
+        .map(AsRef::as_ref)
+        .chain(std::iter::once(final_text))
+        .collect();
+
+    let flips: usize = hypotheses
+        .windows(2)
+        .map(|pair| {
+            word_diff(pair[0], pair[1])
+                .iter()
+                .filter(|change| matches!(change, WordChange::Delete(_)))
+                .count()
+        })
+        .sum();
+    let updates = hypotheses.len() - 1;
+
+    let final_words = final_text.split_whitespace().count();
+    let final_diff = word_diff(partials.last().map_or("", AsRef::as_ref), final_text);
+    let kept = final_diff
+        .iter()
+        .filter(|change| matches!(change, WordChange::Equal(_)))
+        .count();
+
+    StabilityReport {
+        partials: partials.len(),
+        final_words,
+        flips,
+        flips_per_update: if updates == 0 {
+            0.0
+        } else {
+            flips as f64 / updates as f64
+        },
+        last_partial_accuracy: if final_words == 0 {
+            1.0
+        } else {
+            kept as f64 / final_words as f64
+        },
+        final_diff,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use WordChange::*;
+
+    fn word(word: &str) -> String {
+        word.to_string()
+    }
+
+    #[test]
+    fn diffs_keep_common_words_in_order() {
+        assert_eq!(
+            word_diff("what is the whether", "what's the weather today"),
+            [
+                Delete(word("what")),
+                Delete(word("is")),
+                Insert(word("what's")),
+                Equal(word("the")),
+                Delete(word("whether")),
+                Insert(word("weather")),
+                Insert(word("today")),
+            ]
+        );
+    }
+
+    #[test]
+    fn diffs_against_nothing_only_insert_or_delete() {
+        assert_eq!(
+            word_diff("", "hello  world"),
+            [Insert(word("hello")), Insert(word("world"))]
+        );
+        assert_eq!(word_diff("hello", " "), [Delete(word("hello"))]);
+        assert!(word_diff("", "").is_empty());
+    }
+
+    #[test]
+    fn diffs_serialize_as_tagged_ops() {
+        let json = serde_json::to_value(word_diff("a", "b")).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                { "op": "delete", "word": "a" },
+                { "op": "insert", "word": "b" },
+            ])
+        );
+    }
+
+    #[test]
+    fn segments_count_flips_across_updates() {
+        let report = analyse_segment(
+            &["turn of", "turn off the", "turn off the like"],
+            "turn off the light",
+        );
+        assert_eq!(report.partials, 3);
+        assert_eq!(report.final_words, 4);
+        // "of" then "like" were replaced, over three updates.
+        assert_eq!(report.flips, 2);
+        assert_eq!(report.flips_per_update, 2.0 / 3.0);
+        assert_eq!(report.last_partial_accuracy, 0.75);
+        assert_eq!(report.final_diff.last(), Some(&Insert(word("light"))));
+    }
+
+    #[test]
+    fn segments_without_partials_or_words() {
+        let report = analyse_segment::<&str>(&[], "");
+        assert_eq!(report.flips_per_update, 0.0);
+        assert_eq!(report.last_partial_accuracy, 1.0);
+
+        let report = analyse_segment::<&str>(&[], "hello");
+        assert_eq!(report.flips, 0);
+        assert_eq!(report.last_partial_accuracy, 0.0);
+        assert_eq!(report.final_diff, [Insert(word("hello"))]);
+    }
+}
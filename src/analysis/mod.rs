@@ -0,0 +1,3 @@
+//! Tools to evaluate the behaviour of the recogniser from streamed results.
+
+pub mod diff;
@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+/// The root of every Wit.ai API endpoint.
+pub const BASE_URL: &str = "https://api.wit.ai/";
+
+/// The API version sent with every request through the `v` query parameter.
+pub const API_VERSION: &str = "20240304";
+
+/// The longest audio accepted by the `/speech` endpoint.
+pub const SPEECH_MAX_DURATION: Duration = Duration::from_secs(20);
+
+/// The longest audio accepted by the `/dictation` endpoint.
+pub const DICTATION_MAX_DURATION: Duration = Duration::from_secs(300);
+
+/// The longest serialised dynamic entities a query sends by default, which
+/// keeps its percent-encoded URL within the 8 KiB most servers accept.
+pub const DYNAMIC_ENTITIES_MAX_BYTES: usize = 6 * 1024;
+
+/// The environment variable [`WitClient::from_env`](crate::model::client::WitClient::from_env)
+/// reads the access token from.
+pub const TOKEN_ENV: &str = "WIT_API_TOKEN";
+
+/// The header carrying the correlation identifier of a request.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
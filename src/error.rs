@@ -0,0 +1,359 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::model::batch::BatchFailure;
+use crate::model::locale::Locale;
+use crate::model::server::apps::TrainingStatus;
+
+/// Everything that can go wrong while talking to Wit.ai.
+///
+/// New variants may be added in minor releases, so matches need a wildcard
+/// arm. [`ApiError::kind`] gives a field-less category that is cheaper to
+/// match on, and [`std::error::Error::source`] reaches the underlying error.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ApiError {
+    /// The HTTP request could not be sent or its body could not be read.
+    #[error("request failed: {0}")]
+    RequestError(#[from] reqwest::Error),
+    /// Reading or writing data failed.
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+    /// A payload could not be serialised or deserialised.
+    #[error("serialisation failed: {0}")]
+    SerializationError(#[from] serde_json::Error),
+    /// A query could not be turned into a URL.
+    #[error("invalid URL: {0}")]
+    UrlError(#[from] url::ParseError),
+    /// The audio parameters of a query could not be turned into a content type.
+    #[error("invalid audio parameters")]
+    ContentTypeError(#[from] std::fmt::Error),
+    /// A query was found invalid before being sent, such as raw audio
+    /// missing its sample rate.
+    #[error("invalid query: {0}")]
+    Validation(String),
+    /// Wit.ai answered with an error of its own.
+    #[error("Wit.ai returned an error ({code}): {error}")]
+    WitError {
+        /// The human readable message sent by Wit.ai.
+        error: String,
+        /// The machine readable error code sent by Wit.ai, or the HTTP status
+        /// when the response body was not a Wit.ai error.
+        code: String,
+        /// The `X-Request-Id` the failed request was sent with, if any.
+        request_id: Option<String>,
+        /// How long Wit.ai asked to wait before retrying, from the
+        /// `Retry-After` header of rate-limited responses.
+        retry_after: Option<Duration>,
+    },
+    /// The uploaded audio was longer than the endpoint accepts, or buffered
+    /// audio was estimated to be before it was sent.
+    ///
+    /// Split the audio in chunks shorter than `max_duration` and retry.
+    #[error("audio is too long, the limit is {max_duration:?}: {message}")]
+    AudioTooLong {
+        /// The longest audio the endpoint accepts.
+        max_duration: Duration,
+        /// The message sent by Wit.ai, or the estimated duration when the
+        /// audio was rejected before being uploaded.
+        message: String,
+    },
+    /// The uploaded audio was in a format Wit.ai could not decode.
+    ///
+    /// Transcode the audio into one of the supported encodings and retry.
+    #[error("unsupported audio format {content_type}: {message}")]
+    UnsupportedAudioFormat {
+        /// The `Content-Type` the audio was sent with.
+        content_type: String,
+        /// The message sent by Wit.ai.
+        message: String,
+    },
+    /// A streaming endpoint sent an object that is not an event, such as one
+    /// without a `type`, or ended in the middle of an object.
+    #[error("unexpected object in the event stream: {payload}")]
+    ProtocolError {
+        /// The object that was received, as sent.
+        payload: String,
+    },
+    /// No event arrived on a stream within the configured inactivity timeout.
+    #[error("no event received for {0:?}")]
+    Timeout(Duration),
+    /// An app was still training when the wait for it to finish timed out.
+    #[error("the app is still training after {waited:?}, its status is {status:?}")]
+    TrainingTimeout {
+        /// The training status of the last poll.
+        status: TrainingStatus,
+        /// How long the wait took.
+        waited: Duration,
+    },
+    /// None of the locales detected in a text belongs to the allowed set.
+    #[error(
+        "none of the detected locales are allowed: [{}]",
+        detected.iter().map(Locale::to_string).collect::<Vec<_>>().join(", ")
+    )]
+    NoAllowedLocale {
+        /// The locales that were detected, most confident first.
+        detected: Vec<Locale>,
+    },
+    /// The dynamic entities of a query are too large to fit in its URL.
+    ///
+    /// Move the keywords that do not change between queries to the app's
+    /// entities, or raise the limit if the server in front of Wit.ai accepts
+    /// longer URLs.
+    #[error(
+        "dynamic entities take {bytes} bytes once serialised, over the {limit} bytes limit; \
+         move static keywords to the app's entities or send fewer of them"
+    )]
+    DynamicEntitiesTooLarge {
+        /// The length of the serialised dynamic entities.
+        bytes: usize,
+        /// The limit of the query.
+        limit: usize,
+    },
+    /// A resource was modified remotely since it was last read.
+    #[error(transparent)]
+    Conflict(#[from] ConflictError),
+    /// An audio file could not be parsed.
+    #[error(transparent)]
+    InvalidWav(#[from] WavError),
+    /// A configuration file could not be used to build a client.
+    #[error(transparent)]
+    InvalidConfig(#[from] ConfigError),
+    /// A realtime session could not be opened or its connection failed.
+    #[cfg(feature = "websocket")]
+    #[error("WebSocket error: {0}")]
+    WebSocketError(#[source] Box<tokio_tungstenite::tungstenite::Error>),
+}
+
+#[cfg(feature = "websocket")]
+impl From<tokio_tungstenite::tungstenite::Error> for ApiError {
+    fn from(value: tokio_tungstenite::tungstenite::Error) -> Self {
+        ApiError::WebSocketError(Box::new(value))
+    }
+}
+
+/// The category of an [`ApiError`], stable across releases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// See [`ApiError::RequestError`].
+    Request,
+    /// See [`ApiError::IoError`].
+    Io,
+    /// See [`ApiError::SerializationError`].
+    Serialization,
+    /// See [`ApiError::UrlError`].
+    Url,
+    /// See [`ApiError::ContentTypeError`].
+    ContentType,
+    /// See [`ApiError::Validation`].
+    Validation,
+    /// See [`ApiError::WitError`].
+    Wit,
+    /// See [`ApiError::AudioTooLong`].
+    AudioTooLong,
+    /// See [`ApiError::UnsupportedAudioFormat`].
+    UnsupportedAudioFormat,
+    /// See [`ApiError::ProtocolError`].
+    Protocol,
+    /// See [`ApiError::Timeout`] and [`ApiError::TrainingTimeout`].
+    Timeout,
+    /// See [`ApiError::NoAllowedLocale`].
+    NoAllowedLocale,
+    /// See [`ApiError::DynamicEntitiesTooLarge`].
+    DynamicEntitiesTooLarge,
+    /// See [`ApiError::Conflict`].
+    Conflict,
+    /// See [`ApiError::InvalidWav`].
+    InvalidWav,
+    /// See [`ApiError::InvalidConfig`].
+    InvalidConfig,
+    /// A realtime session failed, only raised with the `websocket` feature.
+    WebSocket,
+}
+
+/// Wit.ai error codes reporting audio longer than allowed.
+const AUDIO_TOO_LONG_CODES: &[&str] = &["audio-too-long", "speech-too-long"];
+
+/// Wit.ai error codes reporting audio that could not be decoded.
+const AUDIO_FORMAT_CODES: &[&str] = &[
+    "invalid-content-type",
+    "unsupported-content-type",
+    "bad-content-type",
+    "audio-decoding-failed",
+];
+
+impl ApiError {
+    /// The category of this error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ApiError::RequestError(_) => ErrorKind::Request,
+            ApiError::IoError(_) => ErrorKind::Io,
+            ApiError::SerializationError(_) => ErrorKind::Serialization,
+            ApiError::UrlError(_) => ErrorKind::Url,
+            ApiError::ContentTypeError(_) => ErrorKind::ContentType,
+            ApiError::Validation(_) => ErrorKind::Validation,
+            ApiError::WitError { .. } => ErrorKind::Wit,
+            ApiError::AudioTooLong { .. } => ErrorKind::AudioTooLong,
+            ApiError::UnsupportedAudioFormat { .. } => ErrorKind::UnsupportedAudioFormat,
+            ApiError::ProtocolError { .. } => ErrorKind::Protocol,
+            ApiError::Timeout(_) | ApiError::TrainingTimeout { .. } => ErrorKind::Timeout,
+            ApiError::NoAllowedLocale { .. } => ErrorKind::NoAllowedLocale,
+            ApiError::DynamicEntitiesTooLarge { .. } => ErrorKind::DynamicEntitiesTooLarge,
+            ApiError::Conflict(_) => ErrorKind::Conflict,
+            ApiError::InvalidWav(_) => ErrorKind::InvalidWav,
+            ApiError::InvalidConfig(_) => ErrorKind::InvalidConfig,
+            #[cfg(feature = "websocket")]
+            ApiError::WebSocketError(_) => ErrorKind::WebSocket,
+        }
+    }
+
+    /// The `X-Request-Id` the failed request was sent with, when it got an
+    /// error response.
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            ApiError::WitError { request_id, .. } => request_id.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// How long Wit.ai asked to wait before retrying, when it rate-limited
+    /// the request and sent a `Retry-After` header.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ApiError::WitError { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Like [`ApiError::retry_after`], falling back to `default` when Wit.ai
+    /// did not say how long to wait, for use in retry loops.
+    pub fn retry_after_or(&self, default: Duration) -> Duration {
+        self.retry_after().unwrap_or(default)
+    }
+
+    /// Records the `X-Request-Id` of the request that failed.
+    pub(crate) fn with_request_id(mut self, id: Option<String>) -> Self {
+        if let ApiError::WitError { request_id, .. } = &mut self {
+            *request_id = id;
+        }
+        self
+    }
+
+    /// Records the `Retry-After` delay of the response that failed.
+    pub(crate) fn with_retry_after(mut self, delay: Option<Duration>) -> Self {
+        if let ApiError::WitError { retry_after, .. } = &mut self {
+            *retry_after = delay;
+        }
+        self
+    }
+
+    /// Turns the Wit.ai errors specific to audio uploads into their dedicated
+    /// variants, leaving any other error untouched.
+    pub(crate) fn for_audio(self, content_type: &str, max_duration: Duration) -> Self {
+        match self {
+            ApiError::WitError { error, code, .. }
+                if AUDIO_TOO_LONG_CODES.contains(&code.as_str()) =>
+            {
+                ApiError::AudioTooLong {
+                    max_duration,
+                    message: error,
+                }
+            }
+            ApiError::WitError { error, code, .. }
+                if AUDIO_FORMAT_CODES.contains(&code.as_str()) =>
+            {
+                ApiError::UnsupportedAudioFormat {
+                    content_type: content_type.to_string(),
+                    message: error,
+                }
+            }
+            other => other,
+        }
+    }
+}
+
+/// Raised when a guarded update finds that the remote resource changed since it was read.
+#[derive(Debug, Error)]
+#[error("{resource} was modified remotely (expected hash {expected:016x}, found {found:016x})")]
+pub struct ConflictError {
+    /// The resource that was being updated, such as `entities/color`.
+    pub resource: String,
+    /// The content hash captured when the resource was read.
+    pub expected: u64,
+    /// The content hash of the resource as it is now.
+    pub found: u64,
+}
+
+/// Raised by [`BatchOutcome::into_result`](crate::model::batch::BatchOutcome::into_result)
+/// when some items of a batch failed.
+#[derive(Debug, Error)]
+#[error("{} of {} batch items failed", failures.len(), failures.len() + succeeded)]
+pub struct BatchError {
+    /// How many items succeeded.
+    pub succeeded: usize,
+    /// The items that failed, in order.
+    pub failures: Vec<BatchFailure>,
+}
+
+/// Raised when a string is not a valid [`Locale`](crate::model::locale::Locale).
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid locale {0:?}, expected a form such as en_US")]
+pub struct ParseLocaleError(pub String);
+
+/// Raised when a string is not a [`Grain`](crate::model::values::datetime::Grain)
+/// Wit.ai knows of.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error(
+    "invalid grain {0:?}, expected one of second, minute, hour, day, week, month, quarter or year"
+)]
+pub struct ParseGrainError(pub String);
+
+/// Raised when audio is not a WAV file that can be described to Wit.ai.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid WAV file: {0}")]
+pub struct WavError(pub String);
+
+/// Raised when a configuration file cannot be used to build a client.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid configuration: {0}")]
+pub struct ConfigError(pub String);
+
+/// The body Wit.ai sends alongside a non-successful status code.
+#[derive(Debug, Deserialize)]
+pub(crate) struct WitErrorResponse {
+    pub error: String,
+    #[serde(default)]
+    pub code: String,
+}
+
+impl WitErrorResponse {
+    /// The error of a response that failed with `status`. Bodies that are
+    /// not Wit.ai errors, such as the HTML page of a proxy, are kept as the
+    /// message, with the status as the code.
+    pub(crate) fn into_error(status: StatusCode, body: String) -> ApiError {
+        match serde_json::from_str::<WitErrorResponse>(&body) {
+            Ok(response) => response.into(),
+            Err(_) => ApiError::WitError {
+                error: body,
+                code: status.as_str().to_string(),
+                request_id: None,
+                retry_after: None,
+            },
+        }
+    }
+}
+
+impl From<WitErrorResponse> for ApiError {
+    fn from(value: WitErrorResponse) -> Self {
+        ApiError::WitError {
+            error: value.error,
+            code: value.code,
+            request_id: None,
+            retry_after: None,
+        }
+    }
+}
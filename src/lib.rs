@@ -1,7 +1,13 @@
 #![doc = include_str!("../README.md")]
 
+pub mod analysis;
 pub mod constants;
 pub mod error;
+pub mod migrations;
 pub mod model;
 pub mod prelude;
+#[cfg(feature = "test-util")]
+pub mod test_util;
 pub mod utils;
+
+pub use migrations::MIGRATIONS;
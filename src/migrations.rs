@@ -0,0 +1,141 @@
+//! A machine-readable list of the changes in behaviour between releases.
+//!
+//! Applications depending on a specific behaviour can assert at startup that
+//! the migration introducing it is present, or that no migration they have
+//! not reviewed was added, for instance:
+//!
+//! `assert!(wit_owo::migrations::since("1.1.0").all(|m| REVIEWED.contains(&m.id)))`
+//!
+//! An entry is added alongside every change that can alter the behaviour of
+//! existing code. Identifiers are stable and never reused.
+
+/// A change in behaviour introduced by a release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migration {
+    /// The first version with the new behaviour, such as `1.2.0`.
+    pub version: &'static str,
+    /// A stable identifier, such as `audio-errors-mapped`.
+    pub id: &'static str,
+    /// What changed, and what to do about it.
+    pub summary: &'static str,
+}
+
+/// Every change in behaviour, oldest first.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: "1.2.0",
+        id: "blocking-off-runtime",
+        summary: "Blocking calls made from within a tokio runtime run on a thread of their own instead of panicking.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "json-strings-aware",
+        summary: "Braces inside JSON strings no longer split streamed events.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "audio-errors-mapped",
+        summary: "Audio length and format errors are returned as ApiError::AudioTooLong and ApiError::UnsupportedAudioFormat instead of ApiError::WitError.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "api-error-non-exhaustive",
+        summary: "ApiError is non-exhaustive, matches need a wildcard arm or ApiError::kind.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "typed-locales",
+        summary: "Context::with_locale takes a parsed Locale, invalid locales are rejected when parsing instead of by Wit.ai.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "typed-ids",
+        summary: "Intent, entity, trait and role identifiers are Id values instead of strings.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "wit-error-request-id",
+        summary: "ApiError::WitError carries the X-Request-Id of the failed request.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "dynamic-entity-role",
+        summary: "DynamicEntity has a role field, struct literals need `role: None` or DynamicEntity::new.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "opus-flac-encodings",
+        summary: "Encoding has OggOpus and Flac variants, exhaustive matches on it need new arms.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "wit-error-retry-after",
+        summary: "ApiError::WitError carries the Retry-After delay of rate-limited responses.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "speech-low-confidence-event",
+        summary: "SpeechResponse has a LowConfidence variant, exhaustive matches on it need a new arm.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "dynamic-entities-size-limit",
+        summary: "Queries with dynamic entities over 6 KiB of JSON fail with DynamicEntitiesTooLarge, see with_max_dynamic_entities_bytes.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "stream-protocol-error",
+        summary: "Speech and synthesis streams report events without a type as ApiError::ProtocolError rather than SerializationError.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "dictation-alternatives",
+        summary: "Dictation has an alternatives field, struct literals building one need to set it.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "legacy-speech-aliases",
+        summary: "SpeechRequest, AudioContentType and DictationResponse are deprecated aliases of SpeechQuery, Encoding and Dictation in model::legacy.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "understanding-extra-fields",
+        summary: "Message, Entity and SpeechUnderstanding have an extra field, struct literals building one need to set it.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "keyword-unification",
+        summary: "EntityValue is now an alias of model::entities::Keyword, which server::entities::Keyword re-exports.",
+    },
+    Migration {
+        version: "1.2.0",
+        id: "legacy-client-alias",
+        summary: "prelude::Client is a deprecated alias of WitClient, and blocking_speech of post_blocking_speech.",
+    },
+];
+
+/// The migration with the given identifier, if this version of the crate has it.
+pub fn find(id: &str) -> Option<&'static Migration> {
+    MIGRATIONS.iter().find(|migration| migration.id == id)
+}
+
+/// The migrations introduced after `version`, such as the version an
+/// application was last reviewed against.
+pub fn since(version: &str) -> impl Iterator<Item = &'static Migration> {
+    let version = parse_version(version);
+    MIGRATIONS
+        .iter()
+        .filter(move |migration| parse_version(migration.version) > version)
+}
+
+/// Parses a `major.minor.patch` version, missing or invalid parts counting as 0.
+fn parse_version(version: &str) -> (u64, u64, u64) {
+    let mut parts = version
+        .split('.')
+        .map(|part| part.parse().unwrap_or_default());
+    (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+    )
+}
@@ -0,0 +1,504 @@
+//! Helpers that drive a whole request to completion and collect its result,
+//! optionally within an overall deadline.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::time::Instant;
+
+use crate::error::ApiError;
+use crate::model::client::WitClient;
+use crate::model::dictation::DictationQuery;
+use crate::model::message::Trait;
+use crate::model::speech::{SpeechQuery, SpeechResponse, SpeechUnderstanding};
+use crate::model::synthesize::{MAX_SYNTHESIZE_CHARACTERS, SynthesizeQuery, SynthesizedAudio};
+
+/// Whether an aggregated helper ran to completion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Completion {
+    /// Every request completed.
+    Complete,
+    /// The deadline passed first, the pending request was cancelled.
+    DeadlineExceeded,
+}
+
+/// The result of an aggregated helper, which may be partial when a deadline
+/// was given.
+#[derive(Debug, Clone)]
+pub struct Partial<T> {
+    /// What was collected before the helper completed or ran out of time.
+    pub value: T,
+    /// Whether the helper completed.
+    pub completion: Completion,
+}
+
+impl<T> Partial<T> {
+    /// Whether the helper ran to completion.
+    pub fn is_complete(&self) -> bool {
+        self.completion == Completion::Complete
+    }
+}
+
+/// How [`WitClient::speech_to_understanding_with`] picks the traits of the
+/// understanding it returns, as they change from one event to the next.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TraitMergePolicy {
+    /// Keeps the traits of the last final understanding as they are.
+    #[default]
+    LastFinal,
+    /// Keeps, for each trait, every value seen in any partial or final
+    /// understanding at the highest confidence it was given, most confident
+    /// first.
+    HighestConfidence,
+}
+
+impl TraitMergePolicy {
+    /// Folds the traits of `update` into `merged`.
+    fn merge(self, merged: &mut HashMap<String, Vec<Trait>>, update: &HashMap<String, Vec<Trait>>) {
+        if self == TraitMergePolicy::LastFinal {
+            return;
+        }
+        for (name, values) in update {
+            let known = merged.entry(name.clone()).or_default();
+            for value in values {
+                match known.iter_mut().find(|known| known.value == value.value) {
+                    Some(known) if known.confidence >= value.confidence => {}
+                    Some(known) => *known = value.clone(),
+                    None => known.push(value.clone()),
+                }
+            }
+            known.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        }
+    }
+}
+
+/// Runs `future` unless `deadline` passes first, in which case it is dropped,
+/// cancelling whatever request it was making.
+async fn before<F: Future>(deadline: Option<Instant>, future: F) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+impl WitClient {
+    /// Transcribes audio through `/dictation`, returning the final
+    /// transcriptions joined by spaces.
+    ///
+    /// With a `deadline`, the upload is abandoned once it passes and the text
+    /// transcribed so far is returned.
+    pub async fn transcribe(
+        &self,
+        query: DictationQuery,
+        deadline: Option<Duration>,
+    ) -> Result<Partial<String>, ApiError> {
+        let deadline = deadline.map(|deadline| Instant::now() + deadline);
+        let mut texts = Vec::new();
+        let mut completion = Completion::Complete;
+
+        match before(deadline, self.post_dictation(query)).await {
+            Some(stream) => {
+                let mut stream = stream?;
+                loop {
+                    match before(deadline, stream.next()).await {
+                        Some(Some(dictation)) => {
+                            let dictation = dictation?;
+                            if dictation.is_final && !dictation.text.is_empty() {
+                                texts.push(dictation.text);
+                            }
+                        }
+                        Some(None) => break,
+                        None => {
+                            stream.close();
+                            completion = Completion::DeadlineExceeded;
+                            break;
+                        }
+                    }
+                }
+            }
+            None => completion = Completion::DeadlineExceeded,
+        }
+        Ok(Partial {
+            value: texts.join(" "),
+            completion,
+        })
+    }
+
+    /// Understands audio through `/speech`, returning the last final
+    /// understanding.
+    ///
+    /// With a `deadline`, the upload is abandoned once it passes, and the
+    /// result is `None` unless a final understanding had already arrived.
+    pub async fn speech_to_understanding(
+        &self,
+        query: SpeechQuery,
+        deadline: Option<Duration>,
+    ) -> Result<Partial<Option<SpeechUnderstanding>>, ApiError> {
+        self.speech_to_understanding_with(query, deadline, TraitMergePolicy::LastFinal)
+            .await
+    }
+
+    /// Like [`WitClient::speech_to_understanding`], with the traits of the
+    /// returned understanding picked according to `traits`.
+    pub async fn speech_to_understanding_with(
+        &self,
+        query: SpeechQuery,
+        deadline: Option<Duration>,
+        traits: TraitMergePolicy,
+    ) -> Result<Partial<Option<SpeechUnderstanding>>, ApiError> {
+        let deadline = deadline.map(|deadline| Instant::now() + deadline);
+        let mut understanding: Option<SpeechUnderstanding> = None;
+        let mut merged_traits = HashMap::new();
+        let mut completion = Completion::Complete;
+
+        match before(deadline, self.post_speech(query)).await {
+            Some(stream) => {
+                let mut stream = stream?;
+                loop {
+                    match before(deadline, stream.next()).await {
+                        Some(Some(event)) => match event? {
+                            SpeechResponse::PartialUnderstanding(u) => {
+                                traits.merge(&mut merged_traits, &u.traits);
+                            }
+                            SpeechResponse::FinalUnderstanding(u) => {
+                                traits.merge(&mut merged_traits, &u.traits);
+                                understanding = Some(u);
+                            }
+                            _ => {}
+                        },
+                        Some(None) => break,
+                        None => {
+                            stream.close();
+                            completion = Completion::DeadlineExceeded;
+                            break;
+                        }
+                    }
+                }
+            }
+            None => completion = Completion::DeadlineExceeded,
+        }
+        if traits != TraitMergePolicy::LastFinal
+            && let Some(understanding) = &mut understanding
+        {
+            understanding.traits = merged_traits;
+        }
+        Ok(Partial {
+            value: understanding,
+            completion,
+        })
+    }
+
+    /// Synthesises text longer than [`MAX_SYNTHESIZE_CHARACTERS`] by splitting
+    /// it on sentence, then word boundaries, and synthesising each segment with
+    /// the voice and settings of `query`.
+    ///
+    /// The text must be plain, SSML documents cannot be split safely. With a
+    /// `deadline`, the segments synthesised before it passed are returned.
+    pub async fn synthesize_long(
+        &self,
+        query: &SynthesizeQuery,
+        deadline: Option<Duration>,
+    ) -> Result<Partial<Vec<SynthesizedAudio>>, ApiError> {
+        let deadline = deadline.map(|deadline| Instant::now() + deadline);
+        let mut segments = Vec::new();
+
+        for text in split_text(&query.text, MAX_SYNTHESIZE_CHARACTERS) {
+            let segment = SynthesizeQuery {
+                text,
+                ..query.clone()
+            };
+            match before(deadline, self.post_synthesize(&segment)).await {
+                Some(data) => segments.push(SynthesizedAudio::new(query.codec, data?)),
+                None => {
+                    return Ok(Partial {
+                        value: segments,
+                        completion: Completion::DeadlineExceeded,
+                    });
+                }
+            }
+        }
+        Ok(Partial {
+            value: segments,
+            completion: Completion::Complete,
+        })
+    }
+}
+
+/// Splits `text` into segments of at most `max` characters, preferring to
+/// cut after sentences, then between words, and only then mid-word.
+fn split_text(text: &str, max: usize) -> Vec<String> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut push = |piece: &str, current: &mut String| {
+        if current.chars().count() + piece.chars().count() > max {
+            let segment = current.trim();
+            if !segment.is_empty() {
+                segments.push(segment.to_string());
+            }
+            current.clear();
+        }
+        current.push_str(piece);
+    };
+
+    for sentence in text.split_inclusive(['.', '!', '?']) {
+        if sentence.chars().count() <= max {
+            push(sentence, &mut current);
+            continue;
+        }
+        for word in sentence.split_inclusive(char::is_whitespace) {
+            let chars: Vec<char> = word.chars().collect();
+            for piece in chars.chunks(max) {
+                push(&piece.iter().collect::<String>(), &mut current);
+            }
+        }
+    }
+    let segment = current.trim();
+    if !segment.is_empty() {
+        segments.push(segment.to_string());
+    }
+    segments
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use bytes::Bytes;
+    use serde_json::{Value, json};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+    use wiremock::matchers::{body_string_contains, path};
+    use wiremock::{Mock, ResponseTemplate};
+
+    use super::*;
+    use crate::model::speech::Encoding;
+    use crate::test_util::{MockWit, payloads};
+
+    const AUDIO: Bytes = Bytes::from_static(b"RIFF");
+
+    /// A server that answers one request with `events`, then stalls without
+    /// ending the response. The task finishes once the client hangs up.
+    async fn stalling_server(events: &[Value]) -> (String, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let body: String = events.iter().map(|event| format!("{event}\r\n")).collect();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                let read = socket.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            let head = "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ntransfer-encoding: chunked\r\n\r\n";
+            let chunk = format!("{head}{:x}\r\n{body}\r\n", body.len());
+            socket.write_all(chunk.as_bytes()).await.unwrap();
+            while socket.read(&mut buffer).await.is_ok_and(|read| read > 0) {}
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn transcribe_joins_the_final_transcriptions() {
+        let mock = MockWit::start().await;
+        mock.mock_dictation(&payloads::dictation_events()).await;
+        let transcript = mock
+            .client()
+            .transcribe(DictationQuery::new(Encoding::Wav, AUDIO), None)
+            .await
+            .unwrap();
+        assert!(transcript.is_complete());
+        assert_eq!(transcript.value, "Hello world");
+    }
+
+    #[tokio::test]
+    async fn transcribe_keeps_the_text_heard_before_the_deadline() {
+        let (url, server) = stalling_server(&payloads::dictation_events()).await;
+        let client = WitClient::new("token").with_base_url(&url);
+        let transcript = client
+            .transcribe(
+                DictationQuery::new(Encoding::Wav, AUDIO),
+                Some(Duration::from_millis(300)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(transcript.completion, Completion::DeadlineExceeded);
+        assert_eq!(transcript.value, "Hello world");
+
+        // The stalled response was dropped, closing the connection.
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("the request was not cancelled")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_deadline_before_the_response_cancels_the_request() {
+        let mock = MockWit::start().await;
+        Mock::given(path("/speech"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(30)))
+            .mount(mock.server())
+            .await;
+        let understanding = mock
+            .client()
+            .speech_to_understanding(
+                SpeechQuery::new(Encoding::Wav, AUDIO),
+                Some(Duration::from_millis(100)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(understanding.completion, Completion::DeadlineExceeded);
+        assert!(understanding.value.is_none());
+    }
+
+    #[tokio::test]
+    async fn synthesize_long_keeps_the_segments_made_before_the_deadline() {
+        let mock = MockWit::start().await;
+        Mock::given(path("/synthesize"))
+            .and(body_string_contains("first"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![1, 0]))
+            .mount(mock.server())
+            .await;
+        Mock::given(path("/synthesize"))
+            .and(body_string_contains("second"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(vec![2, 0])
+                    .set_delay(Duration::from_secs(30)),
+            )
+            .mount(mock.server())
+            .await;
+
+        let filler = "word ".repeat(40);
+        let text = format!("The first {filler}. The second {filler}.");
+        let segments = mock
+            .client()
+            .synthesize_long(
+                &SynthesizeQuery::new(&text, "wit$Rebecca"),
+                Some(Duration::from_secs(1)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(segments.completion, Completion::DeadlineExceeded);
+        assert_eq!(segments.value.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn before_gives_up_once_the_deadline_passed() {
+        let past = Some(Instant::now());
+        assert_eq!(before(past, std::future::pending::<()>()).await, None);
+        assert_eq!(before(None, async { 1 }).await, Some(1));
+    }
+
+    /// An understanding of "I love it" whose traits are `traits`.
+    fn understanding(kind: &str, traits: Value) -> Value {
+        json!({
+            "type": kind,
+            "text": "I love it",
+            "is_final": kind == "FINAL_UNDERSTANDING",
+            "intents": [],
+            "entities": {},
+            "traits": traits,
+        })
+    }
+
+    /// The values of `trait_name` and their confidences, in order.
+    fn values(understanding: &SpeechUnderstanding, trait_name: &str) -> Vec<(String, f32)> {
+        understanding
+            .traits
+            .get(trait_name)
+            .map_or_else(Vec::new, |values| {
+                values
+                    .iter()
+                    .map(|value| (value.value.clone(), value.confidence))
+                    .collect()
+            })
+    }
+
+    async fn merged(policy: TraitMergePolicy) -> SpeechUnderstanding {
+        let mock = MockWit::start().await;
+        mock.mock_speech(&[
+            understanding(
+                "PARTIAL_UNDERSTANDING",
+                json!({
+                    "wit$sentiment": [{ "id": "1", "value": "positive", "confidence": 0.75 }],
+                    "wit$greetings": [{ "id": "2", "value": "true", "confidence": 0.5 }],
+                }),
+            ),
+            understanding(
+                "FINAL_UNDERSTANDING",
+                json!({
+                    "wit$sentiment": [
+                        { "id": "3", "value": "neutral", "confidence": 0.625 },
+                        { "id": "1", "value": "positive", "confidence": 0.25 },
+                    ],
+                }),
+            ),
+        ])
+        .await;
+        let understanding = mock
+            .client()
+            .speech_to_understanding_with(SpeechQuery::new(Encoding::Wav, AUDIO), None, policy)
+            .await
+            .unwrap();
+        assert!(understanding.is_complete());
+        understanding.value.unwrap()
+    }
+
+    #[tokio::test]
+    async fn last_final_keeps_the_traits_of_the_final_understanding() {
+        let understanding = merged(TraitMergePolicy::LastFinal).await;
+        assert_eq!(
+            values(&understanding, "wit$sentiment"),
+            [
+                ("neutral".to_string(), 0.625),
+                ("positive".to_string(), 0.25)
+            ]
+        );
+        assert!(values(&understanding, "wit$greetings").is_empty());
+    }
+
+    #[tokio::test]
+    async fn highest_confidence_keeps_the_best_value_seen() {
+        let understanding = merged(TraitMergePolicy::HighestConfidence).await;
+        assert_eq!(
+            values(&understanding, "wit$sentiment"),
+            [
+                ("positive".to_string(), 0.75),
+                ("neutral".to_string(), 0.625)
+            ]
+        );
+        assert_eq!(
+            values(&understanding, "wit$greetings"),
+            [("true".to_string(), 0.5)]
+        );
+    }
+
+    #[test]
+    fn highest_confidence_raises_known_values() {
+        let traits = |values: &[(&str, f32)]| -> HashMap<String, Vec<Trait>> {
+            let values = values
+                .iter()
+                .map(|(value, confidence)| json!({ "id": value, "value": value, "confidence": confidence }))
+                .collect::<Vec<_>>();
+            HashMap::from([(
+                "mood".to_string(),
+                serde_json::from_value(Value::Array(values)).unwrap(),
+            )])
+        };
+        let mut merged = HashMap::new();
+        TraitMergePolicy::HighestConfidence.merge(&mut merged, &traits(&[("calm", 0.25)]));
+        TraitMergePolicy::HighestConfidence
+            .merge(&mut merged, &traits(&[("calm", 0.5), ("angry", 0.375)]));
+        TraitMergePolicy::HighestConfidence.merge(&mut merged, &traits(&[("calm", 0.125)]));
+        let confidences: Vec<_> = merged["mood"]
+            .iter()
+            .map(|value| (value.value.as_str(), value.confidence))
+            .collect();
+        assert_eq!(confidences, [("calm", 0.5), ("angry", 0.375)]);
+
+        let mut untouched = HashMap::new();
+        TraitMergePolicy::LastFinal.merge(&mut untouched, &traits(&[("calm", 0.25)]));
+        assert!(untouched.is_empty());
+    }
+}
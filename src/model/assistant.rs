@@ -0,0 +1,150 @@
+//! A voice loop: speech in, intent dispatch, speech out.
+
+use std::collections::HashMap;
+
+use futures::StreamExt;
+
+use crate::error::ApiError;
+use crate::model::client::WitClient;
+use crate::model::speech::{SpeechQuery, SpeechResponse, SpeechUnderstanding};
+use crate::model::synthesize::{SynthesizeQuery, SynthesizedAudio};
+use crate::model::voice::VoiceName;
+
+/// A callback answering an understood utterance, returning the text to speak
+/// back, if any.
+pub type IntentHandler = Box<dyn FnMut(&SpeechUnderstanding) -> Option<String> + Send>;
+
+/// A callback run on every event of the speech stream, to show partial
+/// transcriptions while the user is still speaking.
+pub type EventHandler = Box<dyn FnMut(&SpeechResponse) + Send>;
+
+/// What happened during one turn of an [`Assistant`].
+#[derive(Debug, Clone, Default)]
+pub struct AssistantTurn {
+    /// The final understanding of the utterance, `None` when Wit.ai sent
+    /// none, such as for silence.
+    pub understanding: Option<SpeechUnderstanding>,
+    /// The intent the utterance was dispatched to, `None` when it went to
+    /// the fallback handler or to no handler at all.
+    pub intent: Option<String>,
+    /// The text the handler answered with.
+    pub reply: Option<String>,
+    /// The synthesised reply, split in segments when it was too long to be
+    /// synthesised at once.
+    pub audio: Vec<SynthesizedAudio>,
+}
+
+/// Listens to an utterance, runs the handler registered for its intent and
+/// speaks the handler's answer back.
+///
+/// An utterance goes to the handler of its most confident intent, provided
+/// that intent is at least as confident as the minimum set with
+/// [`Assistant::with_min_confidence`]. Anything else goes to the handler
+/// registered with [`Assistant::on_unhandled`], if any.
+pub struct Assistant {
+    client: WitClient,
+    reply: SynthesizeQuery,
+    min_confidence: f32,
+    handlers: HashMap<String, IntentHandler>,
+    fallback: Option<IntentHandler>,
+    on_event: Option<EventHandler>,
+}
+
+impl Assistant {
+    /// Creates an assistant answering with the given voice.
+    pub fn new(client: WitClient, voice: impl Into<VoiceName>) -> Self {
+        Self {
+            client,
+            reply: SynthesizeQuery::new("", voice),
+            min_confidence: 0.0,
+            handlers: HashMap::new(),
+            fallback: None,
+            on_event: None,
+        }
+    }
+
+    /// Synthesises replies with the voice, style and codec of `query`, whose
+    /// text is replaced by each reply.
+    pub fn with_reply_settings(mut self, query: SynthesizeQuery) -> Self {
+        self.reply = query;
+        self
+    }
+
+    /// Sends intents less confident than `confidence` to the fallback
+    /// handler instead of their own.
+    pub fn with_min_confidence(mut self, confidence: f32) -> Self {
+        self.min_confidence = confidence;
+        self
+    }
+
+    /// Registers the handler run for utterances matching `intent`.
+    pub fn on_intent<F>(mut self, intent: &str, handler: F) -> Self
+    where
+        F: FnMut(&SpeechUnderstanding) -> Option<String> + Send + 'static,
+    {
+        self.handlers.insert(intent.to_string(), Box::new(handler));
+        self
+    }
+
+    /// Registers the handler run for utterances no other handler takes.
+    pub fn on_unhandled<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(&SpeechUnderstanding) -> Option<String> + Send + 'static,
+    {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Registers a callback run on every event of the speech stream, as it
+    /// arrives.
+    pub fn on_event<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(&SpeechResponse) + Send + 'static,
+    {
+        self.on_event = Some(Box::new(handler));
+        self
+    }
+
+    /// Runs one turn: understands the audio of `query`, dispatches it and
+    /// synthesises the reply.
+    pub async fn handle(&mut self, query: SpeechQuery) -> Result<AssistantTurn, ApiError> {
+        let mut turn = AssistantTurn::default();
+        let mut stream = self.client.post_speech(query).await?;
+        while let Some(event) = stream.next().await {
+            let event = event?;
+            if let Some(on_event) = &mut self.on_event {
+                on_event(&event);
+            }
+            if let SpeechResponse::FinalUnderstanding(understanding) = event {
+                turn.understanding = Some(understanding);
+            }
+        }
+        let Some(understanding) = &turn.understanding else {
+            return Ok(turn);
+        };
+
+        let intent = understanding
+            .intents
+            .iter()
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+            .filter(|intent| intent.confidence >= self.min_confidence)
+            .map(|intent| intent.name.clone());
+        let handler = match &intent {
+            Some(name) if self.handlers.contains_key(name) => {
+                turn.intent = intent.clone();
+                self.handlers.get_mut(name)
+            }
+            _ => self.fallback.as_mut(),
+        };
+        turn.reply = handler.and_then(|handler| handler(understanding));
+
+        if let Some(reply) = &turn.reply {
+            let query = SynthesizeQuery {
+                text: reply.clone(),
+                ..self.reply.clone()
+            };
+            turn.audio = self.client.synthesize_long(&query, None).await?.value;
+        }
+        Ok(turn)
+    }
+}
@@ -0,0 +1,101 @@
+//! The outcome of operations applied to many items at once.
+
+use crate::error::{ApiError, BatchError};
+
+/// An item of a batch that failed.
+#[derive(Debug)]
+pub struct BatchFailure {
+    /// The position of the item in the batch.
+    pub index: usize,
+    /// Why the item failed.
+    pub error: ApiError,
+}
+
+/// The results of a batch operation, which keeps going when single items fail.
+#[derive(Debug)]
+pub struct BatchOutcome<T> {
+    /// The items that succeeded with their position in the batch, in order.
+    pub successes: Vec<(usize, T)>,
+    /// The items that failed, in order.
+    pub failures: Vec<BatchFailure>,
+}
+
+impl<T> Default for BatchOutcome<T> {
+    fn default() -> Self {
+        Self {
+            successes: Vec::new(),
+            failures: Vec::new(),
+        }
+    }
+}
+
+impl<T> BatchOutcome<T> {
+    /// Creates an outcome without any item.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the result of the item at `index`.
+    pub fn push(&mut self, index: usize, result: Result<T, ApiError>) {
+        match result {
+            Ok(value) => self.successes.push((index, value)),
+            Err(error) => self.failures.push(BatchFailure { index, error }),
+        }
+    }
+
+    /// The number of items in the batch.
+    pub fn len(&self) -> usize {
+        self.successes.len() + self.failures.len()
+    }
+
+    /// Whether the batch had no item at all.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of items that succeeded.
+    pub fn success_count(&self) -> usize {
+        self.successes.len()
+    }
+
+    /// The number of items that failed.
+    pub fn failure_count(&self) -> usize {
+        self.failures.len()
+    }
+
+    /// The share of items that succeeded, between 0 and 1. An empty batch
+    /// counts as fully successful.
+    pub fn success_rate(&self) -> f32 {
+        if self.is_empty() {
+            return 1.0;
+        }
+        self.success_count() as f32 / self.len() as f32
+    }
+
+    /// Whether every item succeeded.
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Strict mode: the successful values in batch order if every item
+    /// succeeded, every failure otherwise.
+    pub fn into_result(self) -> Result<Vec<T>, BatchError> {
+        if self.failures.is_empty() {
+            return Ok(self.successes.into_iter().map(|(_, value)| value).collect());
+        }
+        Err(BatchError {
+            succeeded: self.successes.len(),
+            failures: self.failures,
+        })
+    }
+}
+
+impl<T> FromIterator<Result<T, ApiError>> for BatchOutcome<T> {
+    fn from_iter<I: IntoIterator<Item = Result<T, ApiError>>>(iter: I) -> Self {
+        let mut outcome = Self::new();
+        for (index, result) in iter.into_iter().enumerate() {
+            outcome.push(index, result);
+        }
+        outcome
+    }
+}
@@ -0,0 +1,389 @@
+//! Blocking counterparts of the async endpoints, behind the `blocking` feature.
+//!
+//! `reqwest`'s blocking client panics when it is created or dropped on a
+//! thread driving an async runtime. To keep that from happening by accident,
+//! every blocking call made from within a tokio runtime is transparently run
+//! on a short-lived thread of its own. The calling thread is still blocked
+//! until the call returns, so prefer the async API, or
+//! `tokio::task::spawn_blocking`, when you are in async code.
+//!
+//! The blocking HTTP client is created on first use and shared by every
+//! clone of a [`WitClient`], so blocking calls reuse pooled connections.
+
+use std::fmt;
+use std::io::{self, Read};
+use std::marker::PhantomData;
+use std::sync::OnceLock;
+
+use bytes::Bytes;
+use futures::executor::{BlockingStream, block_on_stream};
+use reqwest::Method;
+use reqwest::blocking::{Body, Client, RequestBuilder, Response};
+#[cfg(feature = "compression")]
+use reqwest::header::CONTENT_ENCODING;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+
+use crate::constants::{DICTATION_MAX_DURATION, REQUEST_ID_HEADER, SPEECH_MAX_DURATION};
+use crate::error::{ApiError, WitErrorResponse};
+use crate::model::client::{WitClient, retry_after};
+use crate::model::dictation::{Dictation, DictationQuery};
+use crate::model::language::{DetectedLocale, LanguageResponse};
+use crate::model::params::{Param, QueryParams};
+use crate::model::speech::{AudioSource, AudioStream, SpeechQuery, SpeechResponse};
+use crate::model::synthesize::{SynthesizeQuery, SynthesizedAudio};
+use crate::model::voice::{Voice, VoicesResponse};
+use crate::utils::json::{extract_complete_json, parse_event, parse_json, truncated_object};
+
+/// Runs `f` outside of any async runtime context.
+fn outside_runtime<R: Send>(f: impl FnOnce() -> R + Send) -> R {
+    #[cfg(feature = "tokio")]
+    if tokio::runtime::Handle::try_current().is_ok() {
+        tracing::warn!(
+            "blocking Wit.ai call made from an async context, moving it off the runtime"
+        );
+        return std::thread::scope(|scope| match scope.spawn(f).join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        });
+    }
+    f()
+}
+
+/// The blocking HTTP client of a [`WitClient`], created on first use.
+///
+/// Like creating it, dropping it from an async context is moved off the
+/// runtime.
+#[derive(Debug, Default)]
+pub(crate) struct BlockingClient(OnceLock<Client>);
+
+impl Drop for BlockingClient {
+    fn drop(&mut self) {
+        if let Some(client) = self.0.take() {
+            outside_runtime(move || drop(client));
+        }
+    }
+}
+
+impl WitClient {
+    /// Runs a blocking operation with the shared blocking HTTP client, away
+    /// from any async runtime.
+    pub(crate) fn blocking<R: Send>(
+        &self,
+        f: impl FnOnce(&Client) -> Result<R, ApiError> + Send,
+    ) -> Result<R, ApiError> {
+        outside_runtime(|| f(self.blocking_client.0.get_or_init(Client::new)))
+    }
+
+    /// Prepares an authenticated blocking request to the given URL.
+    pub(crate) fn prepare_blocking_request(
+        &self,
+        client: &Client,
+        method: Method,
+        url: impl AsRef<str>,
+    ) -> RequestBuilder {
+        let request = client
+            .request(method, self.rebase(url))
+            .bearer_auth(self.token())
+            .query(&QueryParams::new().with(Param::V, &self.api_version));
+        match self.timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        }
+    }
+
+    fn prepare_blocking_get_request(
+        &self,
+        client: &Client,
+        url: impl AsRef<str>,
+    ) -> RequestBuilder {
+        self.prepare_blocking_request(client, Method::GET, url)
+    }
+
+    fn prepare_blocking_post_request(
+        &self,
+        client: &Client,
+        url: impl AsRef<str>,
+    ) -> RequestBuilder {
+        self.prepare_blocking_request(client, Method::POST, url)
+    }
+
+    pub(crate) fn send_blocking(&self, request: RequestBuilder) -> Result<Response, ApiError> {
+        let (client, request) = request.build_split();
+        let mut request = request?;
+        let request_id = self.apply_request_id(request.headers_mut());
+        let response = client.execute(request)?;
+        self.quota.observe(response.headers());
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let status = response.status();
+        let retry_after = retry_after(response.headers());
+        let body = response.text()?;
+        let error = WitErrorResponse::into_error(status, body);
+        Err(error
+            .with_request_id(request_id)
+            .with_retry_after(retry_after))
+    }
+
+    /// Blocking version of [`WitClient::get_language`].
+    pub fn get_blocking_language(
+        &self,
+        text: &str,
+        n: u8,
+    ) -> Result<Vec<DetectedLocale>, ApiError> {
+        self.blocking(|client| {
+            let request = self
+                .prepare_blocking_get_request(client, self.url("language"))
+                .query(&QueryParams::new().with(Param::Q, text).with(Param::N, n));
+            let body = self.send_blocking(request)?.text()?;
+            Ok(serde_json::from_str::<LanguageResponse>(&body)?.detected_locales)
+        })
+    }
+
+    /// Blocking version of [`WitClient::get_voices`].
+    pub fn get_blocking_voices(&self) -> Result<Vec<Voice>, ApiError> {
+        self.blocking(|client| {
+            let request = self.prepare_blocking_get_request(client, self.url("voices"));
+            let body = self.send_blocking(request)?.text()?;
+            Ok(serde_json::from_str::<VoicesResponse>(&body)?.into_voices())
+        })
+    }
+
+    /// Blocking version of [`WitClient::post_speech`], returning every event
+    /// once the response is complete.
+    pub fn post_blocking_speech(
+        &self,
+        mut query: SpeechQuery,
+    ) -> Result<Vec<SpeechResponse>, ApiError> {
+        if let Some(context) = self.query_context(query.context.as_ref()) {
+            query.context = Some(context);
+        }
+        query.validate()?;
+        let mut content_type = String::new();
+        fmt::write(&mut content_type, format_args!("{query}"))?;
+
+        let url = query.to_url()?;
+        let data = query.format.normalize(query.data, query.normalization);
+        #[cfg(feature = "compression")]
+        let (data, content_encoding) = data.compressed(query.compression)?;
+        let request_id = query.request_id;
+        let confidence = query.confidence;
+        self.blocking(move |client| {
+            let mut request = self
+                .prepare_blocking_post_request(client, url)
+                .header(CONTENT_TYPE, &content_type)
+                .body(blocking_body(data));
+            #[cfg(feature = "compression")]
+            if let Some(encoding) = content_encoding {
+                request = request.header(CONTENT_ENCODING, encoding);
+            }
+            if let Some(id) = &request_id {
+                request = request.header(REQUEST_ID_HEADER, id);
+            }
+            let response = self
+                .send_blocking(request)
+                .map_err(|e| e.for_audio(&content_type, SPEECH_MAX_DURATION))?;
+            JsonIter::new(response, parse_event)
+                .filter_map(|event| confidence.apply(event))
+                .collect()
+        })
+    }
+
+    /// Blocking version of [`WitClient::post_dictation`], returning every event
+    /// once the response is complete.
+    pub fn post_blocking_dictation(
+        &self,
+        query: DictationQuery,
+    ) -> Result<Vec<Dictation>, ApiError> {
+        query.validate()?;
+        let mut content_type = String::new();
+        fmt::write(&mut content_type, format_args!("{query}"))?;
+
+        let url = query.to_url()?;
+        let data = query.format.normalize(query.data, query.normalization);
+        #[cfg(feature = "compression")]
+        let (data, content_encoding) = data.compressed(query.compression)?;
+        let request_id = query.request_id;
+        self.blocking(move |client| {
+            let mut request = self
+                .prepare_blocking_post_request(client, url)
+                .header(CONTENT_TYPE, &content_type)
+                .body(blocking_body(data));
+            #[cfg(feature = "compression")]
+            if let Some(encoding) = content_encoding {
+                request = request.header(CONTENT_ENCODING, encoding);
+            }
+            if let Some(id) = &request_id {
+                request = request.header(REQUEST_ID_HEADER, id);
+            }
+            let response = self
+                .send_blocking(request)
+                .map_err(|e| e.for_audio(&content_type, DICTATION_MAX_DURATION))?;
+            JsonIter::new(response, parse_json).collect()
+        })
+    }
+
+    /// Blocking version of [`WitClient::post_synthesize`].
+    pub fn post_blocking_synthesize(
+        &self,
+        query: &SynthesizeQuery,
+    ) -> Result<SynthesizedAudio, ApiError> {
+        if let Some(data) = self.synthesis_cache.as_ref().and_then(|c| c.load(query)) {
+            return Ok(SynthesizedAudio::new(query.codec, data));
+        }
+        self.blocking(|client| {
+            let mut request = self
+                .prepare_blocking_post_request(client, self.url("synthesize"))
+                .header(ACCEPT, query.codec.mime_type())
+                .json(query);
+            if let Some(id) = &query.request_id {
+                request = request.header(REQUEST_ID_HEADER, id);
+            }
+            let data = self.send_blocking(request)?.bytes()?;
+            if let Some(cache) = &self.synthesis_cache {
+                cache.store(query, &data);
+            }
+            Ok(SynthesizedAudio::new(query.codec, data))
+        })
+    }
+}
+
+fn blocking_body(data: AudioSource) -> Body {
+    match data {
+        AudioSource::Buffered(data) => Body::from(data),
+        AudioSource::Stream(stream) => Body::new(StreamReader {
+            chunks: block_on_stream(stream),
+            current: Bytes::new(),
+        }),
+    }
+}
+
+/// Reads an async audio stream by blocking on each chunk.
+struct StreamReader {
+    chunks: BlockingStream<AudioStream>,
+    current: Bytes,
+}
+
+impl Read for StreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.current.is_empty() {
+            match self.chunks.next() {
+                Some(chunk) => self.current = chunk?,
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.current.len());
+        buf[..len].copy_from_slice(&self.current.split_to(len));
+        Ok(len)
+    }
+}
+
+/// Iterates over the JSON objects of a chunked blocking response.
+struct JsonIter<T> {
+    response: Response,
+    parse: fn(&[u8]) -> Result<T, ApiError>,
+    buffer: Vec<u8>,
+    finished: bool,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T> JsonIter<T> {
+    fn new(response: Response, parse: fn(&[u8]) -> Result<T, ApiError>) -> Self {
+        Self {
+            response,
+            parse,
+            buffer: Vec::new(),
+            finished: false,
+            _item: PhantomData,
+        }
+    }
+}
+
+impl<T> Iterator for JsonIter<T> {
+    type Item = Result<T, ApiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            if let Some(object) = extract_complete_json(&mut self.buffer) {
+                return Some((self.parse)(&object));
+            }
+            if self.finished {
+                return truncated_object(&mut self.buffer).map(Err);
+            }
+            match self.response.read(&mut chunk) {
+                Ok(0) => self.finished = true,
+                Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+                Err(e) => {
+                    self.finished = true;
+                    self.buffer.clear();
+                    return Some(Err(e.into()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use wiremock::matchers::path;
+    use wiremock::{Mock, ResponseTemplate};
+
+    use crate::error::ApiError;
+    use crate::test_util::{MockWit, payloads};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn blocking_calls_share_one_client() {
+        let mock = MockWit::start().await;
+        mock.mock_voices(payloads::voices()).await;
+        let client = mock.client();
+        assert!(client.blocking_client.0.get().is_none());
+
+        let voices = client.get_blocking_voices().unwrap();
+        assert_eq!(voices.len(), 3);
+        let first = client.blocking_client.0.get().unwrap() as *const _;
+        client.clone().get_blocking_voices().unwrap();
+        assert_eq!(client.blocking_client.0.get().unwrap() as *const _, first);
+
+        // Dropping the last clone from the runtime must not panic.
+        drop(client);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn non_json_error_bodies_keep_the_request_id() {
+        let mock = MockWit::start().await;
+        Mock::given(path("/voices"))
+            .respond_with(ResponseTemplate::new(502).set_body_string("Bad Gateway"))
+            .mount(mock.server())
+            .await;
+        let client = mock.client().with_request_id("trace-42");
+        let error = client.get_blocking_voices().unwrap_err();
+        assert_eq!(error.request_id(), Some("trace-42"));
+        assert!(matches!(error, ApiError::WitError { ref code, .. } if code == "502"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn blocking_speech_holds_back_unconfident_understandings() {
+        use crate::model::speech::tests::kinds;
+        use crate::model::speech::{Encoding, SpeechQuery};
+
+        let mock = MockWit::start().await;
+        mock.mock_speech(&payloads::speech_events()).await;
+        let client = mock.client();
+        let query = || {
+            SpeechQuery::new(Encoding::Wav, bytes::Bytes::from_static(b"RIFF"))
+                .with_min_confidence(0.999)
+        };
+
+        let dropped = client.post_blocking_speech(query()).unwrap();
+        assert_eq!(
+            kinds(&dropped),
+            ["PARTIAL_TRANSCRIPTION", "FINAL_TRANSCRIPTION"]
+        );
+
+        let reported = client
+            .post_blocking_speech(query().with_low_confidence_events())
+            .unwrap();
+        assert_eq!(kinds(&reported)[2..], ["LOW_CONFIDENCE", "LOW_CONFIDENCE"]);
+    }
+}
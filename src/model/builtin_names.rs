@@ -0,0 +1,50 @@
+//! Names of the `wit$*` built-in entities.
+//!
+//! Wit.ai keys the entities of a response by `name:role`; for built-in
+//! entities used with their default role, that key is produced by
+//! [`default_key`], for instance `wit$datetime:datetime`.
+
+/// An age, such as "32 years old".
+pub const WIT_AGE_OF_PERSON: &str = "wit$age_of_person";
+/// An amount of money, such as "10 euros".
+pub const WIT_AMOUNT_OF_MONEY: &str = "wit$amount_of_money";
+/// The name of a person, such as "Alice".
+pub const WIT_CONTACT: &str = "wit$contact";
+/// The title of a book, film, song or other work.
+pub const WIT_CREATIVE_WORK: &str = "wit$creative_work";
+/// A point or range in time, such as "tomorrow at 8".
+pub const WIT_DATETIME: &str = "wit$datetime";
+/// A distance, such as "5 km".
+pub const WIT_DISTANCE: &str = "wit$distance";
+/// A length of time, such as "for 20 minutes".
+pub const WIT_DURATION: &str = "wit$duration";
+/// An email address.
+pub const WIT_EMAIL: &str = "wit$email";
+/// A place, such as "Paris".
+pub const WIT_LOCATION: &str = "wit$location";
+/// The content of a message to send, such as "I'm running late".
+pub const WIT_MESSAGE_BODY: &str = "wit$message_body";
+/// The subject of a message to send.
+pub const WIT_MESSAGE_SUBJECT: &str = "wit$message_subject";
+/// A number, such as "forty-two".
+pub const WIT_NUMBER: &str = "wit$number";
+/// A position in a sequence, such as "third".
+pub const WIT_ORDINAL: &str = "wit$ordinal";
+/// A phone number.
+pub const WIT_PHONE_NUMBER: &str = "wit$phone_number";
+/// A quantity of something, such as "3 cups of sugar".
+pub const WIT_QUANTITY: &str = "wit$quantity";
+/// What to be reminded of, such as "call mom".
+pub const WIT_REMINDER: &str = "wit$reminder";
+/// A temperature, such as "21 degrees".
+pub const WIT_TEMPERATURE: &str = "wit$temperature";
+/// A web address.
+pub const WIT_URL: &str = "wit$url";
+/// A volume, such as "2 litres".
+pub const WIT_VOLUME: &str = "wit$volume";
+
+/// The key of a built-in entity used with its default role.
+pub fn default_key(name: &str) -> String {
+    let role = name.strip_prefix("wit$").unwrap_or(name);
+    format!("{name}:{role}")
+}
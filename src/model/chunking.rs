@@ -0,0 +1,232 @@
+//! How buffered audio is split into chunks while it is uploaded.
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures::{StreamExt, stream};
+
+use crate::model::speech::{AudioSource, AudioStream};
+
+/// How long the transport may take to ask for the next chunk before the
+/// upload is considered backpressured, which stops adaptive chunks from growing.
+const BACKPRESSURE_THRESHOLD: Duration = Duration::from_millis(50);
+
+/// How buffered audio is split into chunks while it is uploaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkStrategy {
+    /// Chunks of a constant size, in bytes.
+    Fixed(usize),
+    /// Small chunks until Wit.ai sends its first event, for a low latency,
+    /// then chunks doubling in size up to `max` as long as the upload is not
+    /// backpressured.
+    Adaptive {
+        /// The size of the first chunks, in bytes.
+        initial: usize,
+        /// The largest chunk size, in bytes.
+        max: usize,
+    },
+}
+
+/// Describes a chunk that was just handed to the transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkMetrics {
+    /// The position of the chunk in the upload, starting at 0.
+    pub index: usize,
+    /// The size of the chunk, in bytes.
+    pub size: usize,
+    /// The time elapsed since the first chunk was sent.
+    pub elapsed: Duration,
+    /// Whether Wit.ai had already sent an event when the chunk was sent.
+    pub established: bool,
+}
+
+/// A callback notified of every chunk sent, for metrics.
+pub type ChunkMetricsHook = Arc<dyn Fn(&ChunkMetrics) + Send + Sync>;
+
+/// The chunking configuration of a query.
+#[derive(Clone, Default)]
+pub(crate) struct Chunking {
+    pub strategy: Option<ChunkStrategy>,
+    pub hook: Option<ChunkMetricsHook>,
+}
+
+impl fmt::Debug for Chunking {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chunking")
+            .field("strategy", &self.strategy)
+            .field("hook", &self.hook.is_some())
+            .finish()
+    }
+}
+
+impl Chunking {
+    /// Splits buffered audio according to the strategy. Streamed audio, or
+    /// audio without a strategy, is left as is.
+    ///
+    /// `established` is set by the caller once the first event is received.
+    pub(crate) fn apply(&self, data: AudioSource, established: Arc<AtomicBool>) -> AudioSource {
+        let (AudioSource::Buffered(data), Some(strategy)) = (&data, self.strategy) else {
+            return data;
+        };
+        AudioSource::Stream(chunk_stream(
+            data.clone(),
+            strategy,
+            self.hook.clone(),
+            established,
+        ))
+    }
+}
+
+struct ChunkState {
+    data: Bytes,
+    size: usize,
+    index: usize,
+    started: Option<Instant>,
+    last_sent: Option<Instant>,
+}
+
+fn chunk_stream(
+    data: Bytes,
+    strategy: ChunkStrategy,
+    hook: Option<ChunkMetricsHook>,
+    established: Arc<AtomicBool>,
+) -> AudioStream {
+    let size = match strategy {
+        ChunkStrategy::Fixed(size) => size,
+        ChunkStrategy::Adaptive { initial, .. } => initial,
+    }
+    .max(1);
+    let state = ChunkState {
+        data,
+        size,
+        index: 0,
+        started: None,
+        last_sent: None,
+    };
+
+    stream::unfold(state, move |mut state| {
+        let hook = hook.clone();
+        let established = established.clone();
+        async move {
+            if state.data.is_empty() {
+                return None;
+            }
+            let now = Instant::now();
+            let is_established = established.load(Ordering::Relaxed);
+            if let ChunkStrategy::Adaptive { max, .. } = strategy {
+                let flowing = state
+                    .last_sent
+                    .is_some_and(|sent| now.duration_since(sent) < BACKPRESSURE_THRESHOLD);
+                if is_established && flowing {
+                    state.size = (state.size * 2).min(max.max(1));
+                }
+            }
+
+            let chunk = state.data.split_to(state.size.min(state.data.len()));
+            let started = *state.started.get_or_insert(now);
+            if let Some(hook) = &hook {
+                hook(&ChunkMetrics {
+                    index: state.index,
+                    size: chunk.len(),
+                    elapsed: now.duration_since(started),
+                    established: is_established,
+                });
+            }
+            state.index += 1;
+            state.last_sent = Some(Instant::now());
+            Some((Ok(chunk), state))
+        }
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use futures::executor::block_on_stream;
+
+    use super::*;
+
+    /// The sizes of the chunks `strategy` splits `len` bytes into.
+    fn sizes(strategy: ChunkStrategy, len: usize, established: bool) -> Vec<usize> {
+        let chunking = Chunking {
+            strategy: Some(strategy),
+            hook: None,
+        };
+        let data = AudioSource::Buffered(Bytes::from(vec![0; len]));
+        let AudioSource::Stream(chunks) =
+            chunking.apply(data, Arc::new(AtomicBool::new(established)))
+        else {
+            panic!("buffered audio is chunked");
+        };
+        block_on_stream(chunks)
+            .map(|chunk| chunk.unwrap().len())
+            .collect()
+    }
+
+    #[test]
+    fn fixed_chunks_end_with_the_remainder() {
+        assert_eq!(sizes(ChunkStrategy::Fixed(4), 10, false), [4, 4, 2]);
+        assert_eq!(sizes(ChunkStrategy::Fixed(5), 10, false), [5, 5]);
+        assert_eq!(sizes(ChunkStrategy::Fixed(0), 2, false), [1, 1]);
+        assert!(sizes(ChunkStrategy::Fixed(4), 0, false).is_empty());
+    }
+
+    #[test]
+    fn adaptive_chunks_stay_small_until_established() {
+        let strategy = ChunkStrategy::Adaptive { initial: 2, max: 6 };
+        assert_eq!(sizes(strategy, 8, false), [2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn adaptive_chunks_double_up_to_their_maximum() {
+        let strategy = ChunkStrategy::Adaptive { initial: 2, max: 6 };
+        assert_eq!(sizes(strategy, 20, true), [2, 4, 6, 6, 2]);
+    }
+
+    #[test]
+    fn the_hook_sees_every_chunk() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let chunking = Chunking {
+            strategy: Some(ChunkStrategy::Fixed(3)),
+            hook: Some({
+                let seen = seen.clone();
+                Arc::new(move |metrics: &ChunkMetrics| {
+                    seen.lock().unwrap().push((metrics.index, metrics.size));
+                })
+            }),
+        };
+        let data = AudioSource::Buffered(Bytes::from_static(b"abcdefg"));
+        let AudioSource::Stream(chunks) = chunking.apply(data, Arc::default()) else {
+            panic!("buffered audio is chunked");
+        };
+        assert_eq!(block_on_stream(chunks).count(), 3);
+        assert_eq!(*seen.lock().unwrap(), [(0, 3), (1, 3), (2, 1)]);
+    }
+
+    #[test]
+    fn streams_and_unchunked_audio_are_left_alone() {
+        let unchunked = Chunking::default().apply(
+            AudioSource::Buffered(Bytes::from_static(b"abc")),
+            Arc::default(),
+        );
+        assert!(matches!(unchunked, AudioSource::Buffered(data) if data == "abc"));
+
+        let chunking = Chunking {
+            strategy: Some(ChunkStrategy::Fixed(1)),
+            hook: None,
+        };
+        let audio = stream::iter([Ok(Bytes::from_static(b"abc"))]).boxed();
+        let AudioSource::Stream(chunks) =
+            chunking.apply(AudioSource::Stream(audio), Arc::default())
+        else {
+            panic!("streamed audio stays streamed");
+        };
+        let chunks: Vec<_> = block_on_stream(chunks).map(Result::unwrap).collect();
+        assert_eq!(chunks, [Bytes::from_static(b"abc")]);
+    }
+}
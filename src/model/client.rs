@@ -0,0 +1,453 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+use reqwest::{Client, Method, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+
+use crate::constants::{API_VERSION, BASE_URL, REQUEST_ID_HEADER, TOKEN_ENV};
+use crate::error::{ApiError, ConfigError, WitErrorResponse};
+#[cfg(feature = "blocking")]
+use crate::model::blocking::BlockingClient;
+use crate::model::context::Context;
+use crate::model::params::{Param, QueryParams};
+use crate::model::quota::{QuotaInfo, QuotaTracker};
+use crate::model::synthesize::SynthesisCache;
+use crate::model::voice::VoiceCache;
+#[cfg(feature = "test-util")]
+use crate::test_util::Recorder;
+
+/// The entry point to every Wit.ai endpoint.
+///
+/// A `WitClient` is cheap to clone, all clones share the same connection pool,
+/// voices cache and rate-limit information.
+///
+/// Its [`Debug`](fmt::Debug) output redacts the access token, so a client can
+/// be logged as it is.
+#[derive(Clone)]
+pub struct WitClient {
+    token: String,
+    pub(crate) api_version: String,
+    pub(crate) client: Client,
+    #[cfg(feature = "blocking")]
+    pub(crate) blocking_client: Arc<BlockingClient>,
+    pub(crate) voice_cache: VoiceCache,
+    pub(crate) request_id: Option<String>,
+    pub(crate) base_url: String,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) quota: QuotaTracker,
+    pub(crate) auto_reference_time: bool,
+    pub(crate) clock: Clock,
+    pub(crate) default_context: Option<Context>,
+    pub(crate) synthesis_cache: Option<SynthesisCache>,
+    #[cfg(feature = "test-util")]
+    pub(crate) recorder: Option<Recorder>,
+}
+
+impl fmt::Debug for WitClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("WitClient");
+        debug
+            .field("token", &REDACTED)
+            .field("api_version", &self.api_version)
+            .field("client", &self.client)
+            .field("voice_cache", &self.voice_cache)
+            .field("request_id", &self.request_id)
+            .field("base_url", &self.base_url)
+            .field("timeout", &self.timeout)
+            .field("quota", &self.quota)
+            .field("auto_reference_time", &self.auto_reference_time)
+            .field("clock", &self.clock)
+            .field("default_context", &self.default_context)
+            .field("synthesis_cache", &self.synthesis_cache);
+        #[cfg(feature = "blocking")]
+        debug.field("blocking_client", &self.blocking_client);
+        #[cfg(feature = "test-util")]
+        debug.field("recorder", &self.recorder);
+        debug.finish()
+    }
+}
+
+/// What the [`Debug`](fmt::Debug) output of a client shows in place of its
+/// access token.
+const REDACTED: &str = "<redacted>";
+
+impl WitClient {
+    /// Creates a new client authenticated with the given server or client access token.
+    pub fn new(token: &str) -> Self {
+        Self {
+            token: token.to_string(),
+            api_version: API_VERSION.to_string(),
+            client: Client::new(),
+            #[cfg(feature = "blocking")]
+            blocking_client: Arc::default(),
+            voice_cache: VoiceCache::default(),
+            request_id: None,
+            base_url: BASE_URL.to_string(),
+            timeout: None,
+            quota: QuotaTracker::default(),
+            auto_reference_time: false,
+            clock: Clock::default(),
+            default_context: None,
+            synthesis_cache: None,
+            #[cfg(feature = "test-util")]
+            recorder: None,
+        }
+    }
+
+    /// Creates a client authenticated with the token held by the
+    /// [`TOKEN_ENV`] environment variable, so that tokens stay out of the
+    /// code.
+    ///
+    /// Fails with [`ApiError::InvalidConfig`] when the variable is not set
+    /// or is empty.
+    pub fn from_env() -> Result<Self, ApiError> {
+        match std::env::var(TOKEN_ENV) {
+            Ok(token) if !token.trim().is_empty() => Ok(Self::new(token.trim())),
+            _ => Err(ConfigError(format!("{TOKEN_ENV} is not set")).into()),
+        }
+    }
+
+    /// Pins the API version sent with every request.
+    pub fn with_api_version(mut self, api_version: &str) -> Self {
+        self.api_version = api_version.to_string();
+        self
+    }
+
+    /// Sends `request_id` as the `X-Request-Id` header of every request that
+    /// does not set its own, so failures can be matched against Wit.ai logs.
+    ///
+    /// Errors reported by Wit.ai carry the identifier, see
+    /// [`ApiError::request_id`].
+    pub fn with_request_id(mut self, request_id: &str) -> Self {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+
+    /// Sends every request to `base_url` instead of `https://api.wit.ai/`,
+    /// such as a local mock server in tests or a proxy.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.to_string();
+        if !self.base_url.ends_with('/') {
+            self.base_url.push('/');
+        }
+        self
+    }
+
+    /// Fails every request, async or blocking, that takes longer than
+    /// `timeout` from start to finish.
+    ///
+    /// Streamed requests are covered too, so leave room for the whole audio
+    /// stream when using speech or dictation.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Calls `callback` with the rate-limit headers of every response carrying
+    /// some, so the application can slow down before hitting 429 errors.
+    pub fn with_quota_callback(
+        mut self,
+        callback: impl Fn(&QuotaInfo) + Send + Sync + 'static,
+    ) -> Self {
+        self.quota = self.quota.with_callback(Arc::new(callback));
+        self
+    }
+
+    /// The rate-limit headers of the last response carrying some, shared by
+    /// the clones of this client.
+    pub fn last_quota(&self) -> Option<QuotaInfo> {
+        self.quota.last()
+    }
+
+    /// Sends the current local time as [`Context::reference_time`] with every
+    /// message and speech query that does not set its own, so relative
+    /// date-times such as "tomorrow" resolve against the user's day rather
+    /// than Wit.ai's.
+    pub fn with_auto_reference_time(mut self, enabled: bool) -> Self {
+        self.auto_reference_time = enabled;
+        self
+    }
+
+    /// Reads the current time from `now` instead of the system clock, such as
+    /// a fixed time in tests.
+    pub fn with_clock(
+        mut self,
+        now: impl Fn() -> DateTime<FixedOffset> + Send + Sync + 'static,
+    ) -> Self {
+        self.clock = Clock(Some(Arc::new(now)));
+        self
+    }
+
+    /// The current local time, as read from the client's clock.
+    pub fn now(&self) -> DateTime<FixedOffset> {
+        self.clock.now()
+    }
+
+    /// Sends `context` with every `/message` and `/speech` query, such as
+    /// the timezone, locale and location of a single-user application.
+    ///
+    /// The fields a query sets in its own context take precedence, see
+    /// [`Context::with_defaults`].
+    pub fn with_default_context(mut self, context: Context) -> Self {
+        self.default_context = Some(context);
+        self
+    }
+
+    /// The context to send instead of `context`, once completed with the
+    /// client's default context and reference time, `None` when it should be
+    /// sent as is.
+    pub(crate) fn query_context(&self, context: Option<&Context>) -> Option<Context> {
+        let mut context = match (context, &self.default_context) {
+            (Some(context), Some(defaults)) => context.clone().with_defaults(defaults),
+            (None, Some(defaults)) => defaults.clone(),
+            (context, None) => context.cloned().unwrap_or_default(),
+        };
+        let stamp = self.auto_reference_time && context.reference_time.is_none();
+        if stamp {
+            context.reference_time = Some(self.now());
+        }
+        (stamp || self.default_context.is_some()).then_some(context)
+    }
+
+    /// Looks synthesised audio up in `cache` before calling `/synthesize`,
+    /// and stores what Wit.ai returns in it, to save quota on phrases that
+    /// are spoken again.
+    pub fn with_synthesis_cache(mut self, cache: SynthesisCache) -> Self {
+        self.synthesis_cache = Some(cache);
+        self
+    }
+
+    /// Records every exchange of this client with Wit.ai with `recorder`, to
+    /// replay it later with
+    /// [`MockWit::replay`](crate::test_util::MockWit::replay).
+    ///
+    /// Only requests sent by the async endpoints are recorded. Requests are
+    /// replayed only when sent exactly as recorded, so pin anything that
+    /// varies between runs, such as the clock with [`WitClient::with_clock`].
+    #[cfg(feature = "test-util")]
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Returns a client authenticated with another token that shares this
+    /// client's connection pool and configuration.
+    ///
+    /// This is meant for servers handling requests on behalf of several Wit.ai
+    /// apps: keep one client around and override the token for each call.
+    pub fn with_token_override(&self, token: &str) -> Self {
+        Self {
+            token: token.to_string(),
+            quota: self.quota.reset(),
+            ..self.clone()
+        }
+    }
+
+    /// The access token requests are authenticated with. Kept behind this
+    /// accessor so that it only leaves the client as an `Authorization` header.
+    pub(crate) fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// The URL of the endpoint at `path`.
+    pub(crate) fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+
+    /// Moves a URL built against the default base URL, such as the URL of a
+    /// query, to this client's base URL.
+    pub(crate) fn rebase(&self, url: impl AsRef<str>) -> String {
+        let url = url.as_ref();
+        match url.strip_prefix(BASE_URL) {
+            Some(path) => self.url(path),
+            None => url.to_string(),
+        }
+    }
+
+    /// Prepares an authenticated request to the given URL.
+    pub(crate) fn prepare_request(&self, method: Method, url: impl AsRef<str>) -> RequestBuilder {
+        let request = self
+            .client
+            .request(method, self.rebase(url))
+            .bearer_auth(self.token())
+            .query(&QueryParams::new().with(Param::V, &self.api_version));
+        match self.timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        }
+    }
+
+    /// Prepares an authenticated GET request to the given URL.
+    pub(crate) fn prepare_get_request(&self, url: impl AsRef<str>) -> RequestBuilder {
+        self.prepare_request(Method::GET, url)
+    }
+
+    /// Prepares an authenticated POST request to the given URL.
+    pub(crate) fn prepare_post_request(&self, url: impl AsRef<str>) -> RequestBuilder {
+        self.prepare_request(Method::POST, url)
+    }
+
+    /// Sends a request and turns any non-successful status into an [`ApiError`].
+    pub(crate) async fn send(&self, request: RequestBuilder) -> Result<Response, ApiError> {
+        let (client, request) = request.build_split();
+        let mut request = request?;
+        let request_id = self.apply_request_id(request.headers_mut());
+        #[cfg(feature = "test-util")]
+        let recorded = self
+            .recorder
+            .as_ref()
+            .map(|recorder| (recorder, recorder.capture(&request, &self.base_url)));
+        let response = client.execute(request).await?;
+        #[cfg(feature = "test-util")]
+        let response = match recorded {
+            Some((recorder, request)) => recorder.record(request, response).await?,
+            None => response,
+        };
+        self.quota.observe(response.headers());
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let status = response.status();
+        let retry_after = retry_after(response.headers());
+        let body = response.text().await?;
+        let error = WitErrorResponse::into_error(status, body);
+        Err(error
+            .with_request_id(request_id)
+            .with_retry_after(retry_after))
+    }
+
+    /// Sets the client's request identifier on requests without one of their
+    /// own, returning the identifier the request is sent with.
+    pub(crate) fn apply_request_id(&self, headers: &mut HeaderMap) -> Option<String> {
+        if let (false, Some(id)) = (headers.contains_key(REQUEST_ID_HEADER), &self.request_id)
+            && let Ok(value) = HeaderValue::from_str(id)
+        {
+            headers.insert(REQUEST_ID_HEADER, value);
+        }
+        headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    }
+
+    /// Sends a request and deserialises its JSON body.
+    pub(crate) async fn send_json<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T, ApiError> {
+        let body = self.send(request).await?.text().await?;
+        Ok(serde_json::from_str(&body)?)
+    }
+}
+
+/// Where a client reads the current time from, the system clock by default.
+#[derive(Clone, Default)]
+pub(crate) struct Clock(Option<Arc<dyn Fn() -> DateTime<FixedOffset> + Send + Sync>>);
+
+impl fmt::Debug for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Clock(custom)"),
+            None => f.write_str("Clock(system)"),
+        }
+    }
+}
+
+impl Clock {
+    fn now(&self) -> DateTime<FixedOffset> {
+        match &self.0 {
+            Some(now) => now(),
+            None => Local::now().fixed_offset(),
+        }
+    }
+}
+
+/// Reads the `Retry-After` header of a response, given either as a number of
+/// seconds or as an HTTP date.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+/// Percent-encodes a user-supplied path segment, such as an entity or voice
+/// name, so that it cannot change the path it is interpolated into.
+///
+/// Only the unreserved characters of RFC 3986 are kept as they are.
+pub(crate) fn path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// Sets the `X-Request-Id` header of a request whose query has its own
+/// identifier, overriding the client's.
+pub(crate) fn query_request_id(
+    request: RequestBuilder,
+    request_id: Option<&String>,
+) -> RequestBuilder {
+    match request_id {
+        Some(id) => request.header(REQUEST_ID_HEADER, id),
+        None => request,
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use wiremock::matchers::path;
+    use wiremock::{Mock, ResponseTemplate};
+
+    use super::*;
+    use crate::test_util::MockWit;
+
+    const PROXY_PAGE: &str = "<html><body>429 Too Many Requests</body></html>";
+
+    async fn mock_proxy_error(mock: &MockWit) {
+        Mock::given(path("/voices"))
+            .respond_with(
+                ResponseTemplate::new(429)
+                    .insert_header("retry-after", "7")
+                    .set_body_string(PROXY_PAGE),
+            )
+            .mount(mock.server())
+            .await;
+    }
+
+    #[tokio::test]
+    async fn non_json_error_bodies_keep_status_and_retry_after() {
+        let mock = MockWit::start().await;
+        mock_proxy_error(&mock).await;
+        let error = mock.client().get_voices().await.unwrap_err();
+        match &error {
+            ApiError::WitError { error, code, .. } => {
+                assert_eq!(error, PROXY_PAGE);
+                assert_eq!(code, "429");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(7)));
+        assert_eq!(error.retry_after_or(Duration::ZERO), Duration::from_secs(7));
+    }
+
+    #[tokio::test]
+    async fn non_json_error_bodies_keep_the_request_id() {
+        let mock = MockWit::start().await;
+        mock_proxy_error(&mock).await;
+        let client = mock.client().with_request_id("trace-42");
+        let error = client.get_voices().await.unwrap_err();
+        assert_eq!(error.request_id(), Some("trace-42"));
+    }
+}
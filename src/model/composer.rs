@@ -0,0 +1,207 @@
+//! Wit.ai Composer endpoints and a stateful conversation session on top of them.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::error::ApiError;
+use crate::model::client::WitClient;
+use crate::model::params::{Param, QueryParams};
+
+/// The state Composer carries between turns of a conversation.
+pub type ContextMap = Map<String, Value>;
+
+/// An event sent to the `/event` endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct ComposerEvent {
+    /// The event type, `"message"` for user input.
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// The user message, only meaningful for `"message"` events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl ComposerEvent {
+    /// A user message event.
+    pub fn message(text: &str) -> Self {
+        Self {
+            type_: "message".to_string(),
+            message: Some(text.to_string()),
+        }
+    }
+
+    /// Any other event type understood by the Composer graph.
+    pub fn custom(type_: &str) -> Self {
+        Self {
+            type_: type_.to_string(),
+            message: None,
+        }
+    }
+}
+
+/// What the bot wants to say back to the user.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct ComposerReply {
+    /// The text of the reply.
+    pub text: Option<String>,
+}
+
+/// The response of both `/event` and `/converse`.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct ComposerResponse {
+    /// The updated context map.
+    #[serde(default)]
+    pub context_map: ContextMap,
+    /// A custom action the client is expected to run before resuming.
+    pub action: Option<String>,
+    /// Whether the bot is waiting for user input.
+    #[serde(default)]
+    pub expects_input: bool,
+    /// Whether the conversation has ended.
+    #[serde(default)]
+    pub is_final: bool,
+    /// The bot's reply, if any.
+    pub response: Option<ComposerReply>,
+}
+
+#[derive(Serialize)]
+struct EventBody<'a> {
+    #[serde(flatten)]
+    event: &'a ComposerEvent,
+    context_map: &'a ContextMap,
+}
+
+#[derive(Serialize)]
+struct ConverseBody<'a> {
+    context_map: &'a ContextMap,
+}
+
+impl WitClient {
+    /// Sends an event to a Composer session.
+    pub async fn post_event(
+        &self,
+        session_id: &str,
+        event: &ComposerEvent,
+        context_map: &ContextMap,
+    ) -> Result<ComposerResponse, ApiError> {
+        let request = self
+            .prepare_post_request(self.url("event"))
+            .query(&QueryParams::new().with(Param::SessionId, session_id))
+            .json(&EventBody { event, context_map });
+        self.send_json(request).await
+    }
+
+    /// Resumes a Composer session, typically after running a custom action.
+    pub async fn post_converse(
+        &self,
+        session_id: &str,
+        context_map: &ContextMap,
+    ) -> Result<ComposerResponse, ApiError> {
+        let request = self
+            .prepare_post_request(self.url("converse"))
+            .query(&QueryParams::new().with(Param::SessionId, session_id))
+            .json(&ConverseBody { context_map });
+        self.send_json(request).await
+    }
+}
+
+/// A callback run when Composer asks the client to perform a custom action.
+///
+/// It may freely update the context map, which is sent back when resuming.
+pub type ActionHandler = Box<dyn FnMut(&mut ContextMap) + Send>;
+
+/// A conversation with a Composer bot that remembers its session and context map.
+///
+/// Custom actions returned by Composer are dispatched to the handlers registered
+/// with [`ComposerSession::on_action`], after which the session resumes on its
+/// own until the bot expects input or has nothing left to do.
+pub struct ComposerSession {
+    client: WitClient,
+    session_id: String,
+    context_map: ContextMap,
+    handlers: HashMap<String, ActionHandler>,
+}
+
+impl ComposerSession {
+    /// Starts a new session with the given identifier.
+    pub fn new(client: WitClient, session_id: &str) -> Self {
+        Self {
+            client,
+            session_id: session_id.to_string(),
+            context_map: ContextMap::new(),
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Seeds the session with an initial context map.
+    pub fn with_context_map(mut self, context_map: ContextMap) -> Self {
+        self.context_map = context_map;
+        self
+    }
+
+    /// Registers the handler run when Composer requests `action`.
+    pub fn on_action<F>(mut self, action: &str, handler: F) -> Self
+    where
+        F: FnMut(&mut ContextMap) + Send + 'static,
+    {
+        self.handlers.insert(action.to_string(), Box::new(handler));
+        self
+    }
+
+    /// The identifier of this session.
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// The context map as of the last turn.
+    pub fn context_map(&self) -> &ContextMap {
+        &self.context_map
+    }
+
+    /// Sends a user message and returns every response until the bot settles.
+    pub async fn say(&mut self, text: &str) -> Result<Vec<ComposerResponse>, ApiError> {
+        self.trigger(ComposerEvent::message(text)).await
+    }
+
+    /// Sends an arbitrary event and returns every response until the bot settles.
+    pub async fn trigger(
+        &mut self,
+        event: ComposerEvent,
+    ) -> Result<Vec<ComposerResponse>, ApiError> {
+        let response = self
+            .client
+            .post_event(&self.session_id, &event, &self.context_map)
+            .await?;
+        self.run(response).await
+    }
+
+    async fn run(
+        &mut self,
+        mut response: ComposerResponse,
+    ) -> Result<Vec<ComposerResponse>, ApiError> {
+        let mut responses = Vec::new();
+        loop {
+            self.context_map = response.context_map.clone();
+            let action = response.action.clone();
+            let settled = response.expects_input || response.is_final;
+            responses.push(response);
+
+            let handler = match action {
+                Some(action) if !settled => self.handlers.get_mut(&action),
+                _ => None,
+            };
+            let Some(handler) = handler else {
+                return Ok(responses);
+            };
+            handler(&mut self.context_map);
+            response = self
+                .client
+                .post_converse(&self.session_id, &self.context_map)
+                .await?;
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Building clients from a TOML configuration file, behind the `config`
+//! feature.
+//!
+//! A file holds any number of named profiles, so that CLI tools and services
+//! can share one configuration format:
+//!
+//! ```toml
+//! [profiles.production]
+//! token_env = "WIT_SERVER_TOKEN"
+//! api_version = "20240304"
+//! timeout_secs = 30
+//!
+//! [profiles.local]
+//! token = "not-a-secret"
+//! base_url = "http://localhost:8080/"
+//! ```
+//!
+//! Unknown keys are rejected, so that a typo does not silently fall back to a
+//! default. The crate has no retry policy or client-side rate limiter, so
+//! there are no keys for them.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::error::{ApiError, ConfigError};
+use crate::model::client::WitClient;
+
+/// The layout of a configuration file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+}
+
+/// The settings of a single client.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Profile {
+    /// The access token, in clear.
+    token: Option<String>,
+    /// The environment variable holding the access token, preferred over
+    /// storing it in the file.
+    token_env: Option<String>,
+    base_url: Option<String>,
+    api_version: Option<String>,
+    timeout_secs: Option<u64>,
+    request_id: Option<String>,
+}
+
+impl Profile {
+    fn token(&self, name: &str) -> Result<String, ConfigError> {
+        match (&self.token, &self.token_env) {
+            (Some(_), Some(_)) => Err(ConfigError(format!(
+                "profile {name:?} sets both token and token_env"
+            ))),
+            (Some(token), None) => Ok(token.clone()),
+            (None, Some(var)) => env::var(var).map_err(|_| {
+                ConfigError(format!(
+                    "profile {name:?} reads its token from {var}, which is not set"
+                ))
+            }),
+            (None, None) => Err(ConfigError(format!(
+                "profile {name:?} sets neither token nor token_env"
+            ))),
+        }
+    }
+}
+
+impl WitClient {
+    /// Creates a client from the profile named `profile` of the TOML file at
+    /// `path`, see the [module documentation](self) for its layout.
+    ///
+    /// Fails with [`ApiError::IoError`] when the file cannot be read and with
+    /// [`ApiError::InvalidConfig`] when it is malformed, the profile is
+    /// missing or its token cannot be found.
+    pub fn from_config(path: impl AsRef<Path>, profile: &str) -> Result<Self, ApiError> {
+        let contents = fs::read_to_string(path)?;
+        Ok(Self::from_config_str(&contents, profile)?)
+    }
+
+    /// Like [`WitClient::from_config`], with the contents of the file.
+    pub fn from_config_str(contents: &str, profile: &str) -> Result<Self, ConfigError> {
+        let mut file: ConfigFile =
+            toml::from_str(contents).map_err(|e| ConfigError(e.message().to_string()))?;
+        let settings = file
+            .profiles
+            .remove(profile)
+            .ok_or_else(|| ConfigError(format!("no profile named {profile:?}")))?;
+
+        let mut client = WitClient::new(&settings.token(profile)?);
+        if let Some(base_url) = &settings.base_url {
+            client = client.with_base_url(base_url);
+        }
+        if let Some(api_version) = &settings.api_version {
+            client = client.with_api_version(api_version);
+        }
+        if let Some(timeout) = settings.timeout_secs {
+            client = client.with_timeout(Duration::from_secs(timeout));
+        }
+        if let Some(request_id) = &settings.request_id {
+            client = client.with_request_id(request_id);
+        }
+        Ok(client)
+    }
+}
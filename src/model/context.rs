@@ -0,0 +1,227 @@
+//! The context sent alongside a query to help Wit.ai resolve it.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use serde::{Serialize, Serializer};
+
+use crate::model::intents::Intent;
+use crate::model::locale::Locale;
+use crate::model::message::{Entity, Message, MessageQuery};
+use crate::model::speech::{SpeechQuery, SpeechUnderstanding};
+use crate::model::values::builtin::BuiltinEntity;
+use crate::model::values::datetime::WitDatetime;
+
+/// Geographic coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Coords {
+    /// The latitude, in degrees.
+    pub lat: f64,
+    /// The longitude, in degrees.
+    pub long: f64,
+}
+
+/// Information about the user that helps Wit.ai resolve relative values,
+/// such as "tomorrow" or "the nearest station".
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Context {
+    /// The local time of the user, used to resolve relative date-times.
+    ///
+    /// It is sent in whole seconds with a numeric offset, such as
+    /// `2024-03-04T09:30:00+01:00`, whatever the precision and offset it
+    /// was created with.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_reference_time"
+    )]
+    pub reference_time: Option<DateTime<FixedOffset>>,
+    /// The IANA timezone of the user, such as `Europe/London`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// The locale of the user, such as `en_GB`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<Locale>,
+    /// The location of the user, used to resolve locations.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coords: Option<Coords>,
+}
+
+impl Context {
+    /// Creates an empty context.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the local time of the user.
+    pub fn with_reference_time(mut self, reference_time: DateTime<FixedOffset>) -> Self {
+        self.reference_time = Some(reference_time);
+        self
+    }
+
+    /// Sets the IANA timezone of the user.
+    pub fn with_timezone(mut self, timezone: &str) -> Self {
+        self.timezone = Some(timezone.to_string());
+        self
+    }
+
+    /// Sets the locale of the user.
+    pub fn with_locale(mut self, locale: Locale) -> Self {
+        self.locale = Some(locale);
+        self
+    }
+
+    /// Sets the location of the user.
+    pub fn with_coords(mut self, lat: f64, long: f64) -> Self {
+        self.coords = Some(Coords { lat, long });
+        self
+    }
+
+    /// Fills the fields this context leaves unset with those of `defaults`.
+    pub fn with_defaults(self, defaults: &Context) -> Self {
+        Self {
+            reference_time: self.reference_time.or(defaults.reference_time),
+            timezone: self.timezone.or_else(|| defaults.timezone.clone()),
+            locale: self.locale.or_else(|| defaults.locale.clone()),
+            coords: self.coords.or(defaults.coords),
+        }
+    }
+
+    /// The context to send for a query with `context` and a pinned `locale`,
+    /// which takes precedence over the locale of the context.
+    pub(crate) fn pinned<'a>(
+        context: Option<&'a Context>,
+        locale: Option<&Locale>,
+    ) -> Option<Cow<'a, Context>> {
+        match (context, locale) {
+            (context, Some(locale)) => {
+                let context = context.cloned().unwrap_or_default();
+                Some(Cow::Owned(context.with_locale(locale.clone())))
+            }
+            (Some(context), None) => Some(Cow::Borrowed(context)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Writes a reference time the way Wit.ai documents it, so that times read
+/// from `Local` or `Utc` clocks serialise alike.
+fn serialize_reference_time<S: Serializer>(
+    time: &Option<DateTime<FixedOffset>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serializer.serialize_str(&time.to_rfc3339_opts(SecondsFormat::Secs, false)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Carries what was understood in previous turns of a conversation over to
+/// the next query.
+///
+/// Every understood [`Message`] or [`SpeechUnderstanding`] is fed to
+/// [`ConversationContext::observe_message`] or
+/// [`ConversationContext::observe_understanding`], and
+/// [`ConversationContext::context`] then returns the context to send with the
+/// next query:
+///
+/// - the last resolved date-time becomes the reference time, so "and at 5pm"
+///   after "tomorrow" resolves to tomorrow at 5pm,
+/// - the coordinates of the last resolved location replace the user's,
+/// - the locale reported by the recogniser replaces the user's.
+///
+/// The last intent and the last entity under each `name:role` key are kept
+/// too, for the application's own slot filling.
+#[derive(Debug, Clone, Default)]
+pub struct ConversationContext {
+    context: Context,
+    intent: Option<Intent>,
+    entities: HashMap<String, Entity>,
+    turns: usize,
+}
+
+impl ConversationContext {
+    /// Starts a conversation with the given context.
+    pub fn new(context: Context) -> Self {
+        Self {
+            context,
+            ..Self::default()
+        }
+    }
+
+    /// Records the understanding of a text.
+    pub fn observe_message(&mut self, message: &Message) {
+        self.observe(&message.intents, &message.entities);
+    }
+
+    /// Records the understanding of an utterance, along with its locale.
+    pub fn observe_understanding(&mut self, understanding: &SpeechUnderstanding) {
+        self.observe(&understanding.intents, &understanding.entities);
+        if let Some(locale) = &understanding.locale {
+            self.context.locale = Some(locale.clone());
+        }
+    }
+
+    fn observe(&mut self, intents: &[Intent], entities: &HashMap<String, Vec<Entity>>) {
+        self.turns += 1;
+        if let Some(intent) = intents
+            .iter()
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+        {
+            self.intent = Some(intent.clone());
+        }
+        for (key, candidates) in entities {
+            let Some(entity) = candidates
+                .iter()
+                .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+            else {
+                continue;
+            };
+            self.entities.insert(key.clone(), entity.clone());
+
+            match entity.as_datetime() {
+                Some(WitDatetime::Value(value)) => self.context.reference_time = Some(value.value),
+                Some(WitDatetime::Interval {
+                    from: Some(from), ..
+                }) => self.context.reference_time = Some(from.value),
+                _ => {}
+            }
+            if let Some(BuiltinEntity::Location(location)) = entity.builtin()
+                && let Some((lat, long)) = location.coords
+            {
+                self.context.coords = Some(Coords { lat, long });
+            }
+        }
+    }
+
+    /// The context to send with the next query.
+    pub fn context(&self) -> Context {
+        self.context.clone()
+    }
+
+    /// The most confident intent of the last turn that had one.
+    pub fn last_intent(&self) -> Option<&Intent> {
+        self.intent.as_ref()
+    }
+
+    /// The last entity seen under a `name:role` key, such as
+    /// `wit$datetime:datetime`.
+    pub fn entity(&self, key: &str) -> Option<&Entity> {
+        self.entities.get(key)
+    }
+
+    /// The number of turns observed so far.
+    pub fn turns(&self) -> usize {
+        self.turns
+    }
+
+    /// A message query for the next turn, carrying the conversation context.
+    pub fn message_query(&self, text: &str) -> MessageQuery {
+        MessageQuery::new(text).with_context(self.context())
+    }
+
+    /// Adds the conversation context to a speech query for the next turn.
+    pub fn speech_query(&self, query: SpeechQuery) -> SpeechQuery {
+        query.with_context(self.context())
+    }
+}
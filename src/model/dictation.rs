@@ -0,0 +1,552 @@
+//! Speech to text through the streaming `/dictation` endpoint.
+
+use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt, stream};
+use reqwest::Body;
+#[cfg(feature = "compression")]
+use reqwest::header::CONTENT_ENCODING;
+use reqwest::header::CONTENT_TYPE;
+use serde::Deserialize;
+#[cfg(feature = "serde-serialize")]
+use serde::Serialize;
+
+use url::Url;
+
+use crate::constants::{DICTATION_MAX_DURATION, DYNAMIC_ENTITIES_MAX_BYTES};
+use crate::error::ApiError;
+use crate::model::chunking::{ChunkMetrics, ChunkStrategy, Chunking};
+use crate::model::client::{WitClient, query_request_id};
+use crate::model::context::Context;
+use crate::model::entities::DynamicEntities;
+use crate::model::locale::Locale;
+use crate::model::params::{Param, QueryParams};
+use crate::model::speech::{AudioFormat, AudioSource, Encoding, Endian, RawEncoding, Speech};
+use crate::model::stream::{DictationStream, StreamCounters};
+use crate::model::transcript::Transcript;
+use crate::model::vad::VadConfig;
+use crate::utils::audio::Normalization;
+#[cfg(feature = "compression")]
+use crate::utils::compression::Compression;
+use crate::utils::json::{json_stream, parse_json};
+
+/// A request to the `/dictation` endpoint.
+#[derive(Debug)]
+pub struct DictationQuery {
+    pub(crate) format: AudioFormat,
+    pub(crate) data: AudioSource,
+    pub(crate) tag: Option<String>,
+    pub(crate) context: Option<Context>,
+    pub(crate) locale: Option<Locale>,
+    pub(crate) dynamic_entities: Option<DynamicEntities>,
+    pub(crate) entities_limit: usize,
+    pub(crate) chunking: Chunking,
+    pub(crate) vad: VadConfig,
+    pub(crate) request_id: Option<String>,
+    pub(crate) normalization: Option<Normalization>,
+    #[cfg(feature = "compression")]
+    pub(crate) compression: Option<Compression>,
+}
+
+impl DictationQuery {
+    /// Creates a query sending `data`, encoded as `encoding`.
+    pub fn new(encoding: Encoding, data: impl Into<AudioSource>) -> Self {
+        Self {
+            format: AudioFormat::new(encoding),
+            data: data.into(),
+            tag: None,
+            context: None,
+            locale: None,
+            dynamic_entities: None,
+            entities_limit: DYNAMIC_ENTITIES_MAX_BYTES,
+            chunking: Chunking::default(),
+            vad: VadConfig::default(),
+            request_id: None,
+            normalization: None,
+            #[cfg(feature = "compression")]
+            compression: None,
+        }
+    }
+
+    /// Creates a query sending the samples of a WAV file as raw audio, with
+    /// the raw parameters read from its header.
+    ///
+    /// Multi-channel files are sent whole as [`Encoding::Wav`] instead. Fails
+    /// with [`ApiError::InvalidWav`] when the header is malformed or describes
+    /// samples Wit.ai cannot decode.
+    pub fn from_wav_bytes(data: impl Into<Bytes>) -> Result<Self, ApiError> {
+        let (format, samples) = AudioFormat::from_wav(data.into())?;
+        Ok(Self {
+            format,
+            ..Self::new(Encoding::Raw, samples)
+        })
+    }
+
+    /// Describes headerless samples, required with [`Encoding::Raw`].
+    pub fn with_raw_params(
+        mut self,
+        raw_encoding: RawEncoding,
+        bits: u8,
+        sample_rate: u32,
+        endian: Endian,
+    ) -> Self {
+        self.format.raw_encoding = Some(raw_encoding);
+        self.format.bits = Some(bits);
+        self.format.sample_rate = Some(sample_rate);
+        self.format.endian = Some(endian);
+        self
+    }
+
+    /// Scales quiet audio to the given loudness before uploading it.
+    ///
+    /// Only buffered audio made of signed 16-bit samples, raw or in a WAV
+    /// file, is normalised; anything else is sent unchanged.
+    pub fn with_normalization(mut self, target: Normalization) -> Self {
+        self.normalization = Some(target);
+        self
+    }
+
+    /// Compresses the audio before uploading it, see
+    /// [`Compression`](crate::utils::compression::Compression). Streamed
+    /// audio is compressed chunk by chunk as it is sent.
+    #[cfg(feature = "compression")]
+    pub fn with_transfer_encoding(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Sets the sample rate of [`Encoding::Ulaw`] audio, 8000 or 16000 Hz.
+    ///
+    /// Without it Wit.ai assumes 8kHz. μ-law samples are single bytes, so
+    /// there is no endianness to set. Any other rate makes the query fail
+    /// with [`ApiError::Validation`], from [`DictationQuery::validate`] or when it
+    /// is sent.
+    pub fn with_ulaw_rate(mut self, sample_rate: u32) -> Self {
+        self.format.sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// Sends `content_type` verbatim as the `Content-Type` header, ignoring the
+    /// encoding parameters. The audio itself is sent unchanged.
+    pub fn with_content_type_override(mut self, content_type: String) -> Self {
+        self.format.content_type_override = Some(content_type);
+        self
+    }
+
+    /// Targets a specific tagged version of the app.
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    /// Sends information about the user to help the recogniser.
+    pub fn with_context(mut self, context: Context) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// Pins the language the audio is recognised in, such as `fr_FR`,
+    /// instead of relying on the app's default.
+    ///
+    /// It is sent as the locale of the context, overriding the one set with
+    /// `with_context`.
+    pub fn with_locale(mut self, locale: Locale) -> Self {
+        self.locale = Some(locale);
+        self
+    }
+
+    /// Extends the app's keywords entities for this query only.
+    pub fn with_dynamic_entities(mut self, entities: impl Into<DynamicEntities>) -> Self {
+        self.dynamic_entities = Some(entities.into());
+        self
+    }
+
+    /// Fails the query with [`ApiError::DynamicEntitiesTooLarge`] when its
+    /// dynamic entities take more than `bytes` once serialised, instead of
+    /// the default [`DYNAMIC_ENTITIES_MAX_BYTES`].
+    pub fn with_max_dynamic_entities_bytes(mut self, bytes: usize) -> Self {
+        self.entities_limit = bytes;
+        self
+    }
+
+    /// Sends `request_id` as the `X-Request-Id` header of this query,
+    /// overriding the one set on the client.
+    pub fn with_request_id(mut self, request_id: &str) -> Self {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+
+    /// Tunes how Wit.ai detects the end of speech.
+    pub fn with_vad(mut self, vad: VadConfig) -> Self {
+        self.vad = vad;
+        self
+    }
+
+    /// Splits buffered audio into chunks while uploading it. Streamed audio
+    /// keeps the chunks it is produced in. Blocking calls ignore the strategy.
+    pub fn with_chunk_strategy(mut self, strategy: ChunkStrategy) -> Self {
+        self.chunking.strategy = Some(strategy);
+        self
+    }
+
+    /// Calls `hook` with the size and timing of every chunk uploaded under the
+    /// chunk strategy.
+    pub fn with_chunk_metrics<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&ChunkMetrics) + Send + Sync + 'static,
+    {
+        self.chunking.hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Checks the audio of the query, as is done before sending it, failing
+    /// with [`ApiError::Validation`] when its parameters are incomplete and
+    /// with [`ApiError::AudioTooLong`] when it is estimated to last longer
+    /// than [`DICTATION_MAX_DURATION`].
+    pub fn validate(&self) -> Result<(), ApiError> {
+        self.format.validate()?;
+        self.format
+            .check_duration(&self.data, DICTATION_MAX_DURATION)
+    }
+
+    /// How long the audio of the query lasts, estimated from its size and
+    /// parameters, see [`DictationQuery::validate`].
+    ///
+    /// Only buffered raw, μ-law and WAV audio can be estimated.
+    pub fn estimated_duration(&self) -> Option<Duration> {
+        self.format.estimated_duration(&self.data)
+    }
+
+    /// A copy of the query sending `data` instead of its own audio.
+    pub(crate) fn with_data(&self, data: AudioSource) -> Self {
+        Self {
+            format: self.format.clone(),
+            data,
+            tag: self.tag.clone(),
+            context: self.context.clone(),
+            locale: self.locale.clone(),
+            dynamic_entities: self.dynamic_entities.clone(),
+            entities_limit: self.entities_limit,
+            chunking: self.chunking.clone(),
+            vad: self.vad,
+            request_id: self.request_id.clone(),
+            normalization: self.normalization,
+            #[cfg(feature = "compression")]
+            compression: self.compression,
+        }
+    }
+
+    /// Splits the audio of the query into overlapping pieces, see
+    /// [`WitClient::transcribe_long_audio`], each sent with the other
+    /// parameters of the query.
+    pub(crate) fn split(
+        &self,
+        segment_len: Duration,
+        overlap: Duration,
+    ) -> Result<Vec<(Duration, DictationQuery)>, ApiError> {
+        let pieces = self.format.split(&self.data, segment_len, overlap)?;
+        Ok(pieces
+            .into_iter()
+            .map(|(offset, data)| (offset, self.with_data(AudioSource::Buffered(data))))
+            .collect())
+    }
+
+    /// The URL of the `/dictation` endpoint with the parameters of this query.
+    pub fn to_url(&self) -> Result<Url, ApiError> {
+        let params = QueryParams::new()
+            .with_opt(Param::Tag, self.tag.as_ref())
+            .with_json(
+                Param::Context,
+                Context::pinned(self.context.as_ref(), self.locale.as_ref()).as_ref(),
+            )?
+            .with_entities(self.dynamic_entities.as_ref(), self.entities_limit)?;
+        self.vad.apply(params).to_url("dictation")
+    }
+}
+
+/// Formats the `Content-Type` header value of the query.
+impl Display for DictationQuery {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.format.fmt(f)
+    }
+}
+
+/// A transcription streamed back by the `/dictation` endpoint.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct Dictation {
+    /// The transcribed text.
+    pub text: String,
+    /// Whether this transcription is final.
+    #[serde(default)]
+    pub is_final: bool,
+    /// Details about the recognised speech.
+    pub speech: Option<Speech>,
+    /// The locale the recogniser used for this event, when Wit.ai reports it.
+    #[serde(default)]
+    pub locale: Option<Locale>,
+    /// Other hypotheses for the same audio, when Wit.ai sends them.
+    #[serde(default)]
+    pub alternatives: Vec<Alternative>,
+}
+
+/// Another hypothesis the recogniser considered for a [`Dictation`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct Alternative {
+    /// The transcribed text.
+    pub text: String,
+    /// How confident the recogniser is about this hypothesis, when reported.
+    #[serde(default)]
+    pub confidence: Option<f32>,
+}
+
+impl Dictation {
+    /// The most confident hypothesis: [`Dictation::text`], unless an
+    /// alternative reports a higher confidence than its speech. When the
+    /// text itself reports no confidence there is nothing to beat, so it is
+    /// kept, and alternatives without a confidence never win.
+    pub fn best(&self) -> &str {
+        let Some(confidence) = self.speech.as_ref().map(|speech| speech.confidence) else {
+            return &self.text;
+        };
+        self.alternatives
+            .iter()
+            .filter_map(|alternative| Some((alternative.confidence?, alternative)))
+            .filter(|(candidate, _)| *candidate > confidence)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map_or(&self.text, |(_, alternative)| &alternative.text)
+    }
+
+    /// The other hypotheses for the same audio, in the order Wit.ai sent
+    /// them. Empty when it sent none.
+    pub fn alternatives(&self) -> &[Alternative] {
+        &self.alternatives
+    }
+
+    /// The time spanned by the recognised words, `None` when Wit.ai sent no timings.
+    pub fn duration(&self) -> Option<Duration> {
+        self.speech.as_ref()?.duration()
+    }
+}
+
+impl WitClient {
+    /// Streams audio to the `/dictation` endpoint, yielding transcriptions as
+    /// Wit.ai produces them.
+    pub async fn post_dictation(&self, query: DictationQuery) -> Result<DictationStream, ApiError> {
+        query.validate()?;
+        let mut content_type = String::new();
+        fmt::write(&mut content_type, format_args!("{query}"))?;
+
+        let url = query.to_url()?;
+        let established = Arc::new(AtomicBool::new(false));
+        let data = query.chunking.apply(
+            query.format.normalize(query.data, query.normalization),
+            established.clone(),
+        );
+        #[cfg(feature = "compression")]
+        let (data, content_encoding) = data.compressed(query.compression)?;
+        let (data, upload) = data.abortable();
+        let counters = Arc::new(StreamCounters::default());
+        let request = query_request_id(self.prepare_post_request(url), query.request_id.as_ref())
+            .header(CONTENT_TYPE, &content_type);
+        #[cfg(feature = "compression")]
+        let request = match content_encoding {
+            Some(encoding) => request.header(CONTENT_ENCODING, encoding),
+            None => request,
+        };
+        let request = request.body(Body::from(data.counted(counters.clone())));
+        let response = self
+            .send(request)
+            .await
+            .map_err(|e| e.for_audio(&content_type, DICTATION_MAX_DURATION))?;
+        let body = {
+            let counters = counters.clone();
+            response
+                .bytes_stream()
+                .inspect(move |chunk| counters.count_download(chunk))
+                .boxed()
+        };
+        let events = {
+            let counters = counters.clone();
+            json_stream(body, parse_json).inspect(move |event| {
+                established.store(true, Ordering::Relaxed);
+                counters.count_event(event);
+            })
+        };
+        Ok(DictationStream::new(events, counters).with_upload(upload))
+    }
+
+    /// Transcribes a recording longer than `/dictation` accepts, such as a
+    /// podcast or a meeting, by splitting it into pieces of `segment_len`
+    /// that each start `overlap` before the previous one ends.
+    ///
+    /// Up to `concurrency` pieces are transcribed at once, and their
+    /// transcripts are stitched back together with [`Transcript::stitch`],
+    /// so word timings count from the start of the recording. Only buffered
+    /// raw, μ-law and WAV audio can be split, on frame boundaries; anything
+    /// else fails with [`ApiError::Validation`].
+    pub async fn transcribe_long_audio(
+        &self,
+        query: DictationQuery,
+        segment_len: Duration,
+        overlap: Duration,
+        concurrency: usize,
+    ) -> Result<Transcript, ApiError> {
+        let pieces = stream::iter(query.split(segment_len, overlap)?)
+            .map(|(offset, piece)| async move {
+                let mut events = self.post_dictation(piece).await?;
+                let mut transcript = Transcript::new();
+                while let Some(event) = events.next().await {
+                    transcript.push(&event?);
+                }
+                Ok::<_, ApiError>((offset, transcript))
+            })
+            .buffered(concurrency.max(1))
+            .try_collect()
+            .await?;
+        Ok(Transcript::stitch(pieces, overlap))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn alternatives_are_read_in_order() {
+        let payload = json!({
+            "type": "FINAL_TRANSCRIPTION",
+            "text": "recognize speech",
+            "is_final": true,
+            "speech": { "confidence": 0.5, "tokens": [] },
+            "alternatives": [
+                { "text": "wreck a nice beach", "confidence": 0.75 },
+                { "text": "recognise peach", "confidence": 0.25 },
+                { "text": "reckon eyes speech" }
+            ]
+        });
+        let dictation: Dictation = serde_json::from_value(payload).unwrap();
+        let texts: Vec<&str> = dictation
+            .alternatives()
+            .iter()
+            .map(|alternative| alternative.text.as_str())
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "wreck a nice beach",
+                "recognise peach",
+                "reckon eyes speech"
+            ]
+        );
+        assert_eq!(dictation.alternatives()[2].confidence, None);
+        assert_eq!(dictation.best(), "wreck a nice beach");
+    }
+
+    #[test]
+    fn dictations_without_alternatives_keep_their_text() {
+        let payload = json!({ "text": "hello", "is_final": true });
+        let dictation: Dictation = serde_json::from_value(payload).unwrap();
+        assert!(dictation.alternatives().is_empty());
+        assert_eq!(dictation.best(), "hello");
+    }
+
+    #[test]
+    fn unscored_text_is_preferred_over_scored_alternatives() {
+        let payload = json!({
+            "text": "recognize speech",
+            "is_final": true,
+            "alternatives": [{ "text": "wreck a nice beach", "confidence": 0.75 }]
+        });
+        let dictation: Dictation = serde_json::from_value(payload).unwrap();
+        assert_eq!(dictation.best(), "recognize speech");
+    }
+
+    #[test]
+    fn unscored_alternatives_never_win() {
+        let payload = json!({
+            "text": "recognize speech",
+            "is_final": true,
+            "speech": { "confidence": 0.0, "tokens": [] },
+            "alternatives": [{ "text": "wreck a nice beach" }]
+        });
+        let dictation: Dictation = serde_json::from_value(payload).unwrap();
+        assert_eq!(dictation.best(), "recognize speech");
+    }
+
+    #[cfg(feature = "serde-serialize")]
+    #[test]
+    fn dictations_round_trip() {
+        let payload = json!({
+            "text": "Hello world",
+            "is_final": true,
+            "speech": {
+                "confidence": 0.875,
+                "tokens": [{ "token": "Hello", "start": 300, "end": 720, "confidence": 0.75 }]
+            },
+            "alternatives": [{ "text": "Hollow world", "confidence": 0.25 }]
+        });
+        let dictation: Dictation = serde_json::from_value(payload.clone()).unwrap();
+        let encoded = serde_json::to_value(&dictation).unwrap();
+        for field in ["text", "is_final", "speech", "alternatives"] {
+            assert_eq!(encoded[field], payload[field], "{field}");
+        }
+        let decoded: Dictation = serde_json::from_value(encoded.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), encoded);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn long_audio_is_transcribed_in_pieces_and_stitched() {
+        use crate::test_util::{MockWit, payloads};
+
+        let mock = MockWit::start().await;
+        // Every piece hears "Hello" at 300ms and "world" at 720ms.
+        mock.mock_dictation(&payloads::dictation_events()).await;
+        let ten_seconds = Bytes::from(vec![0; 80_000]);
+        let transcript = mock
+            .client()
+            .transcribe_long_audio(
+                DictationQuery::new(Encoding::Ulaw, ten_seconds),
+                Duration::from_secs(4),
+                Duration::from_secs(1),
+                2,
+            )
+            .await
+            .unwrap();
+
+        // Pieces start at 0s, 3s and 6s, and later pieces only keep what they
+        // heard past the middle of their overlap.
+        assert_eq!(mock.server().received_requests().await.unwrap().len(), 3);
+        assert_eq!(transcript.text(), "Hello world world world");
+        let starts: Vec<u64> = transcript
+            .segments
+            .iter()
+            .flat_map(|segment| segment.words.iter().map(|word| word.start))
+            .collect();
+        assert_eq!(starts, [300, 720, 3_720, 6_720]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn streamed_long_audio_is_rejected() {
+        let client = crate::model::client::WitClient::new("token");
+        let audio = AudioSource::Stream(futures::stream::empty().boxed());
+        let result = client
+            .transcribe_long_audio(
+                DictationQuery::new(Encoding::Ulaw, audio),
+                Duration::from_secs(4),
+                Duration::from_secs(1),
+                1,
+            )
+            .await;
+        assert!(matches!(result, Err(ApiError::Validation(_))));
+    }
+}
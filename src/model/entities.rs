@@ -0,0 +1,116 @@
+//! Dynamic entities, sent alongside a query to extend an app's entities for
+//! that query only.
+
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+
+/// A keyword of a keywords entity and its synonyms.
+///
+/// The same type is sent with dynamic entities and used by the management
+/// API, so keywords fetched with
+/// [`ServerClient::get_entity`](crate::model::server::ServerClient::get_entity)
+/// can be added to a [`DynamicEntity`] as they are.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keyword {
+    /// The canonical value of the keyword.
+    pub keyword: String,
+    /// Other ways users refer to the keyword.
+    #[serde(default)]
+    pub synonyms: Vec<String>,
+}
+
+/// The former name of [`Keyword`] for dynamic entities.
+pub type EntityValue = Keyword;
+
+impl Keyword {
+    /// Creates a keyword with the given synonyms.
+    pub fn new(keyword: &str, synonyms: &[&str]) -> Self {
+        Self {
+            keyword: keyword.to_string(),
+            synonyms: synonyms.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+/// Extra keywords for one of the app's keywords entities.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DynamicEntity {
+    /// The name of the entity the keywords are added to.
+    pub name: String,
+    /// The role the keywords are restricted to, such as `callee` for a
+    /// `contact` entity, or `None` for every role of the entity.
+    pub role: Option<String>,
+    /// The keywords added to the entity.
+    pub keywords: Vec<Keyword>,
+}
+
+impl DynamicEntity {
+    /// Creates a dynamic entity without any keyword.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            role: None,
+            keywords: Vec::new(),
+        }
+    }
+
+    /// Restricts the keywords to one role of the entity.
+    pub fn with_role(mut self, role: &str) -> Self {
+        self.role = Some(role.to_string());
+        self
+    }
+
+    /// The key of the entity in the `entities` parameter: its name, followed
+    /// by `:` and its role when it has one, as in `contact:callee`.
+    pub fn key(&self) -> String {
+        match &self.role {
+            Some(role) => format!("{}:{role}", self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Adds a keyword to the entity.
+    pub fn with_keyword(mut self, keyword: Keyword) -> Self {
+        self.keywords.push(keyword);
+        self
+    }
+
+    /// Adds several keywords to the entity, such as the keywords of an
+    /// entity fetched from the management API.
+    pub fn with_keywords(mut self, keywords: impl IntoIterator<Item = Keyword>) -> Self {
+        self.keywords.extend(keywords);
+        self
+    }
+}
+
+/// Splits an entity key, `name:role` or just `name`, into the name of the
+/// entity and its role. Only the last `:` separates the role, as built-in
+/// entity names such as `wit$location` never contain one.
+pub fn split_entity_key(key: &str) -> (&str, Option<&str>) {
+    match key.rsplit_once(':') {
+        Some((name, role)) => (name, Some(role)),
+        None => (key, None),
+    }
+}
+
+/// The dynamic entities of a query, serialised the way the `entities`
+/// parameter expects them: a map from entity key, see [`DynamicEntity::key`],
+/// to keywords.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DynamicEntities(pub Vec<DynamicEntity>);
+
+impl Serialize for DynamicEntities {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for entity in &self.0 {
+            map.serialize_entry(&entity.key(), &entity.keywords)?;
+        }
+        map.end()
+    }
+}
+
+impl From<Vec<DynamicEntity>> for DynamicEntities {
+    fn from(value: Vec<DynamicEntity>) -> Self {
+        Self(value)
+    }
+}
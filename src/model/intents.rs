@@ -0,0 +1,123 @@
+//! Intents, as matched by the understanding endpoints and as managed by the
+//! management API.
+
+use serde::Deserialize;
+#[cfg(feature = "serde-serialize")]
+use serde::Serialize;
+
+use crate::model::id::Id;
+
+/// An intent matching a piece of text.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct Intent {
+    /// The unique identifier of the intent.
+    pub id: Id,
+    /// The name of the intent.
+    pub name: String,
+    /// How confident Wit.ai is about this intent, between 0 and 1.
+    pub confidence: f32,
+    /// The entities the intent was trained with.
+    ///
+    /// The understanding endpoints do not report them, so this is empty
+    /// unless filled with [`Intent::with_entities`], such as from
+    /// [`ServerClient::get_intent_full`](crate::model::server::ServerClient::get_intent_full).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entities: Vec<IntentEntity>,
+}
+
+impl Intent {
+    /// Attaches the entities the intent was trained with.
+    pub fn with_entities(mut self, entities: Vec<IntentEntity>) -> Self {
+        self.entities = entities;
+        self
+    }
+
+    /// The identity of this intent, without the confidence of the match.
+    pub fn to_ref(&self) -> IntentRef {
+        IntentRef {
+            id: self.id.clone(),
+            name: self.name.clone(),
+        }
+    }
+}
+
+/// An entity an intent was trained with.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct IntentEntity {
+    /// The unique identifier of the entity.
+    pub id: Id,
+    /// The name of the entity, such as `wit$datetime:datetime`.
+    pub name: String,
+}
+
+/// An intent of an app, as listed by the management API, which carries no
+/// confidence.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct IntentRef {
+    /// The unique identifier of the intent.
+    pub id: Id,
+    /// The name of the intent.
+    pub name: String,
+}
+
+impl From<Intent> for IntentRef {
+    fn from(value: Intent) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+        }
+    }
+}
+
+impl From<&Intent> for IntentRef {
+    fn from(value: &Intent) -> Self {
+        value.to_ref()
+    }
+}
+
+impl PartialEq<IntentRef> for Intent {
+    fn eq(&self, other: &IntentRef) -> bool {
+        self.id == other.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn matched_intents_carry_no_entities_until_attached() {
+        let intent: Intent = serde_json::from_value(json!({
+            "id": "1",
+            "name": "book_flight",
+            "confidence": 0.5,
+        }))
+        .unwrap();
+        assert!(intent.entities.is_empty());
+
+        let entities: Vec<IntentEntity> = serde_json::from_value(json!([
+            { "id": "2", "name": "wit$location:destination" },
+        ]))
+        .unwrap();
+        let intent = intent.with_entities(entities.clone());
+        assert_eq!(intent.entities, entities);
+    }
+
+    #[cfg(feature = "serde-serialize")]
+    #[test]
+    fn empty_entities_are_not_serialised() {
+        let intent: Intent =
+            serde_json::from_value(json!({ "id": "1", "name": "greet", "confidence": 0.5 }))
+                .unwrap();
+        let encoded = serde_json::to_value(&intent).unwrap();
+        assert_eq!(
+            encoded,
+            json!({ "id": "1", "name": "greet", "confidence": 0.5 })
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Language detection through the `/language` endpoint.
+
+use serde::Deserialize;
+#[cfg(feature = "serde-serialize")]
+use serde::Serialize;
+
+use crate::error::ApiError;
+use crate::model::client::WitClient;
+use crate::model::locale::Locale;
+use crate::model::params::{Param, QueryParams};
+
+/// A locale detected in a piece of text.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct DetectedLocale {
+    /// The detected locale, such as `en_XX`.
+    pub locale: Locale,
+    /// How confident Wit.ai is about this locale, between 0 and 1.
+    pub confidence: f32,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct LanguageResponse {
+    pub detected_locales: Vec<DetectedLocale>,
+}
+
+/// The number of candidates requested by [`WitClient::detect_language_constrained`].
+const CONSTRAINED_CANDIDATES: u8 = 8;
+
+impl WitClient {
+    /// Detects the locales of a piece of text, returning up to `n` candidates,
+    /// most confident first.
+    pub async fn get_language(&self, text: &str, n: u8) -> Result<Vec<DetectedLocale>, ApiError> {
+        let request = self
+            .prepare_get_request(self.url("language"))
+            .query(&QueryParams::new().with(Param::Q, text).with(Param::N, n));
+        let response: LanguageResponse = self.send_json(request).await?;
+        Ok(response.detected_locales)
+    }
+
+    /// Detects the locale of a piece of text among an allowed set.
+    ///
+    /// The most confident candidate whose locale is in `allowed` is returned.
+    /// Locales are compared with [`Locale::same_language`], so `fr_FR` in
+    /// `allowed` matches a detected `fr_XX`. When no candidate is allowed,
+    /// [`ApiError::NoAllowedLocale`] lists what was detected instead.
+    pub async fn detect_language_constrained(
+        &self,
+        text: &str,
+        allowed: &[Locale],
+    ) -> Result<DetectedLocale, ApiError> {
+        let detected = self.get_language(text, CONSTRAINED_CANDIDATES).await?;
+        best_allowed(detected, allowed)
+    }
+}
+
+/// The most confident of the `detected` locales speaking the language of one
+/// of the `allowed` ones.
+fn best_allowed(
+    detected: Vec<DetectedLocale>,
+    allowed: &[Locale],
+) -> Result<DetectedLocale, ApiError> {
+    detected
+        .iter()
+        .filter(|candidate| {
+            allowed
+                .iter()
+                .any(|locale| locale.same_language(&candidate.locale))
+        })
+        .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+        .cloned()
+        .ok_or_else(|| ApiError::NoAllowedLocale {
+            detected: detected.into_iter().map(|c| c.locale).collect(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detected(locales: &[(&str, f32)]) -> Vec<DetectedLocale> {
+        locales
+            .iter()
+            .map(|(locale, confidence)| DetectedLocale {
+                locale: locale.parse().unwrap(),
+                confidence: *confidence,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn allowed_locales_match_on_their_language() {
+        let allowed = ["fr_FR".parse().unwrap(), "de".parse().unwrap()];
+        let candidates = detected(&[("en_XX", 0.9), ("fr_XX", 0.3), ("de_XX", 0.5)]);
+        let best = best_allowed(candidates, &allowed).unwrap();
+        assert_eq!(best.locale, "de_XX");
+    }
+
+    #[test]
+    fn no_allowed_locale_lists_the_detected_ones() {
+        let allowed = ["fr_FR".parse().unwrap()];
+        let error = best_allowed(detected(&[("en_XX", 0.9), ("es", 0.1)]), &allowed).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "none of the detected locales are allowed: [en_XX, es]"
+        );
+        let ApiError::NoAllowedLocale { detected } = error else {
+            panic!("expected NoAllowedLocale, got {error:?}");
+        };
+        assert_eq!(detected[0].language(), "en");
+    }
+}
@@ -0,0 +1,59 @@
+//! The names earlier releases used for the client and the speech models,
+//! kept as deprecated aliases of the current API so that code written against
+//! them builds with a warning pointing at the replacement.
+//!
+//! [`WitClient`] is the only client of the crate and the current types its
+//! only speech data model: the blocking helpers in
+//! [`model::blocking`](crate::model::blocking) are methods of the same client,
+//! taking and returning the same types.
+
+#[cfg(feature = "blocking")]
+use crate::error::ApiError;
+use crate::model::client::WitClient;
+use crate::model::dictation::Dictation;
+#[cfg(feature = "blocking")]
+use crate::model::speech::SpeechResponse;
+use crate::model::speech::{Encoding, SpeechQuery};
+
+/// The former name of [`WitClient`], which the legacy client was merged into.
+#[deprecated(since = "1.2.0", note = "use WitClient")]
+pub type Client = WitClient;
+
+/// The former name of [`SpeechQuery`].
+#[deprecated(since = "1.2.0", note = "use SpeechQuery")]
+pub type SpeechRequest = SpeechQuery;
+
+/// The former name of [`Encoding`], the container format of uploaded audio.
+#[deprecated(since = "1.2.0", note = "use Encoding")]
+pub type AudioContentType = Encoding;
+
+/// The former name of [`Dictation`], an event of the `/dictation` stream.
+#[deprecated(since = "1.2.0", note = "use Dictation")]
+pub type DictationResponse = Dictation;
+
+#[cfg(feature = "blocking")]
+impl WitClient {
+    /// The former name of [`WitClient::post_blocking_speech`].
+    #[deprecated(since = "1.2.0", note = "use post_blocking_speech")]
+    pub fn blocking_speech(&self, query: SpeechQuery) -> Result<Vec<SpeechResponse>, ApiError> {
+        self.post_blocking_speech(query)
+    }
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_names_build_current_types() {
+        let format: AudioContentType = Encoding::Wav;
+        let request: SpeechRequest = SpeechQuery::new(format, vec![0u8; 4]);
+        assert_eq!(request.to_string(), "audio/wav");
+        let dictation: DictationResponse =
+            serde_json::from_str(r#"{"text":"hello","is_final":true}"#).unwrap();
+        assert!(dictation.is_final);
+        let client: Client = WitClient::new("token");
+        assert!(format!("{client:?}").starts_with("WitClient"));
+    }
+}
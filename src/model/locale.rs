@@ -0,0 +1,107 @@
+//! Typed locales, such as `en_US`.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ParseLocaleError;
+
+/// A locale in the `language_REGION` form used by Wit.ai, such as `en_US`.
+///
+/// Parsing is lenient about case and also accepts `-` as a separator, so
+/// `en-us` parses to `en_US`. The region is optional, and Wit.ai uses `XX`
+/// when it does not know it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Locale {
+    language: String,
+    region: Option<String>,
+}
+
+impl Locale {
+    /// The lowercase language code, such as `en`.
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// The uppercase region code, such as `US`, if any.
+    pub fn region(&self) -> Option<&str> {
+        self.region.as_deref()
+    }
+
+    /// Whether both locales speak the same language, whatever their region.
+    pub fn same_language(&self, other: &Locale) -> bool {
+        self.language == other.language
+    }
+}
+
+impl FromStr for Locale {
+    type Err = ParseLocaleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseLocaleError(s.to_string());
+        let mut parts = s.split(['_', '-']);
+        let language = parts.next().ok_or_else(error)?;
+        let region = parts.next();
+        if parts.next().is_some()
+            || !(2..=3).contains(&language.len())
+            || !language.chars().all(|c| c.is_ascii_alphabetic())
+        {
+            return Err(error());
+        }
+        if let Some(region) = region
+            && !(region.len() == 2 && region.chars().all(|c| c.is_ascii_alphabetic())
+                || region.len() == 3 && region.chars().all(|c| c.is_ascii_digit()))
+        {
+            return Err(error());
+        }
+        Ok(Self {
+            language: language.to_ascii_lowercase(),
+            region: region.map(str::to_ascii_uppercase),
+        })
+    }
+}
+
+impl TryFrom<String> for Locale {
+    type Error = ParseLocaleError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl TryFrom<&str> for Locale {
+    type Error = ParseLocaleError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for Locale {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.region {
+            Some(region) => write!(f, "{}_{region}", self.language),
+            None => f.write_str(&self.language),
+        }
+    }
+}
+
+impl From<Locale> for String {
+    fn from(value: Locale) -> Self {
+        value.to_string()
+    }
+}
+
+impl PartialEq<str> for Locale {
+    fn eq(&self, other: &str) -> bool {
+        other.parse::<Locale>().is_ok_and(|other| *self == other)
+    }
+}
+
+impl PartialEq<&str> for Locale {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
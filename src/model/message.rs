@@ -0,0 +1,734 @@
+//! The natural language understanding models returned by Wit.ai.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::str::FromStr;
+
+use serde::Deserialize;
+#[cfg(feature = "serde-serialize")]
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use url::Url;
+
+use crate::constants::DYNAMIC_ENTITIES_MAX_BYTES;
+use crate::error::ApiError;
+use crate::model::builtin_names::WIT_DATETIME;
+use crate::model::client::{WitClient, query_request_id};
+use crate::model::context::Context;
+use crate::model::entities::{DynamicEntities, split_entity_key};
+use crate::model::id::Id;
+pub use crate::model::intents::Intent;
+use crate::model::params::{Param, QueryParams};
+
+/// A request to the `/message` endpoint.
+#[derive(Debug, Clone)]
+pub struct MessageQuery {
+    pub(crate) text: String,
+    pub(crate) n: Option<u8>,
+    pub(crate) tag: Option<String>,
+    pub(crate) context: Option<Context>,
+    pub(crate) dynamic_entities: Option<DynamicEntities>,
+    pub(crate) entities_limit: usize,
+    pub(crate) request_id: Option<String>,
+}
+
+impl MessageQuery {
+    /// Creates a query understanding `text`.
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            n: None,
+            tag: None,
+            context: None,
+            dynamic_entities: None,
+            entities_limit: DYNAMIC_ENTITIES_MAX_BYTES,
+            request_id: None,
+        }
+    }
+
+    /// Sets the maximum number of intents returned.
+    pub fn with_n(mut self, n: u8) -> Self {
+        self.n = Some(n);
+        self
+    }
+
+    /// Targets a specific tagged version of the app.
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    /// Sends information about the user to help resolve the text.
+    ///
+    /// Date-times such as "tomorrow at 5" are resolved against its reference
+    /// time and timezone, and locations against its coordinates.
+    pub fn with_context(mut self, context: Context) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// Extends the app's keywords entities for this query only.
+    pub fn with_dynamic_entities(mut self, entities: impl Into<DynamicEntities>) -> Self {
+        self.dynamic_entities = Some(entities.into());
+        self
+    }
+
+    /// Fails the query with [`ApiError::DynamicEntitiesTooLarge`] when its
+    /// dynamic entities take more than `bytes` once serialised, instead of
+    /// the default [`DYNAMIC_ENTITIES_MAX_BYTES`].
+    pub fn with_max_dynamic_entities_bytes(mut self, bytes: usize) -> Self {
+        self.entities_limit = bytes;
+        self
+    }
+
+    /// Sends `request_id` as the `X-Request-Id` header of this query,
+    /// overriding the one set on the client.
+    pub fn with_request_id(mut self, request_id: &str) -> Self {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+
+    /// The URL of the `/message` endpoint with the parameters of this query.
+    pub fn to_url(&self) -> Result<Url, ApiError> {
+        QueryParams::new()
+            .with(Param::Q, &self.text)
+            .with_opt(Param::N, self.n)
+            .with_opt(Param::Tag, self.tag.as_ref())
+            .with_json(Param::Context, self.context.as_ref())?
+            .with_entities(self.dynamic_entities.as_ref(), self.entities_limit)?
+            .to_url("message")
+    }
+}
+
+impl WitClient {
+    /// Understands a piece of text through the `/message` endpoint.
+    pub async fn get_message(&self, query: &MessageQuery) -> Result<Message, ApiError> {
+        let mut query = Cow::Borrowed(query);
+        if let Some(context) = self.query_context(query.context.as_ref()) {
+            query.to_mut().context = Some(context);
+        }
+        let request = query_request_id(
+            self.prepare_get_request(query.to_url()?),
+            query.request_id.as_ref(),
+        );
+        self.send_json(request).await
+    }
+}
+
+/// The understanding of a piece of text.
+///
+/// Its [`Debug`] output only summarises the intents, entities and traits, use
+/// the alternate form (`{:#?}`) to print the full tree.
+#[derive(Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct Message {
+    /// The text that was understood.
+    pub text: String,
+    /// The intents matching the text, most confident first.
+    #[serde(default)]
+    pub intents: Vec<Intent>,
+    /// The entities found in the text, keyed by `name:role`.
+    #[serde(default)]
+    pub entities: HashMap<String, Vec<Entity>>,
+    /// The traits of the text, keyed by trait name.
+    #[serde(default)]
+    pub traits: HashMap<String, Vec<Trait>>,
+    /// The fields Wit.ai sent that this model does not cover yet, as they
+    /// were received.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// An entity extracted from a piece of text.
+///
+/// Its [`Debug`] output only shows the essentials, use the alternate form
+/// (`{:#?}`) to print every field.
+#[derive(Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct Entity {
+    /// The unique identifier of the entity.
+    pub id: Id,
+    /// The name of the entity, such as `wit$datetime`.
+    pub name: String,
+    /// The role the entity plays in the utterance.
+    pub role: String,
+    /// The index of the first character of the entity in the text.
+    pub start: usize,
+    /// The index after the last character of the entity in the text.
+    pub end: usize,
+    /// The part of the text the entity was extracted from.
+    pub body: String,
+    /// How confident Wit.ai is about this entity, between 0 and 1.
+    pub confidence: f32,
+    /// Entities nested inside this one, such as the parts of a composite entity.
+    #[serde(default)]
+    pub entities: HashMap<String, Vec<Entity>>,
+    /// The kind of resolved value, `value` or `interval` for most built-in entities.
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+    /// The resolved value of the entity.
+    pub value: Option<Value>,
+    /// Alternative resolved values, for ambiguous entities.
+    #[serde(default)]
+    pub values: Vec<Value>,
+    /// The start of the interval, for interval entities.
+    pub from: Option<Value>,
+    /// The end of the interval, for interval entities.
+    pub to: Option<Value>,
+    /// The unit of the value, for quantities, temperatures and amounts of money.
+    pub unit: Option<String>,
+    /// The grain of the value, for date-times.
+    pub grain: Option<String>,
+    /// The value normalised to a base unit, for durations.
+    pub normalized: Option<Value>,
+    /// Resolved information for entities such as locations.
+    pub resolved: Option<Value>,
+    /// The fields Wit.ai sent that this model does not cover yet, as they
+    /// were received.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl Entity {
+    /// The key of the entity in the `entities` maps of an understanding,
+    /// `name:role`, such as `wit$datetime:datetime`.
+    pub fn role_qualified_name(&self) -> String {
+        format!("{}:{}", self.name, self.role)
+    }
+
+    /// Whether the entity is keyed by `key`, either `name:role` or just its
+    /// name.
+    pub fn matches_key(&self, key: &str) -> bool {
+        let (name, role) = split_entity_key(key);
+        name == self.name && role.is_none_or(|role| role == self.role)
+    }
+}
+
+/// A trait of a piece of text.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct Trait {
+    /// The unique identifier of the trait value.
+    pub id: Id,
+    /// The value of the trait.
+    pub value: String,
+    /// How confident Wit.ai is about this value, between 0 and 1.
+    pub confidence: f32,
+}
+
+impl Trait {
+    /// The most confident of `values`, such as the values of one trait.
+    ///
+    /// Values with the same confidence are told apart by their text, the one
+    /// sorting first winning, so the result does not depend on the order
+    /// Wit.ai listed them in.
+    pub fn best<'a>(values: impl IntoIterator<Item = &'a Trait>) -> Option<&'a Trait> {
+        values.into_iter().max_by(|a, b| {
+            a.confidence
+                .total_cmp(&b.confidence)
+                .then_with(|| b.value.cmp(&a.value))
+        })
+    }
+
+    /// The value of a yes/no trait, such as `wit$greetings`.
+    ///
+    /// `true`, `yes` and `1` are true, `false`, `no` and `0` are false, in
+    /// any case; other values give `None`.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.value.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "1" => Some(true),
+            "false" | "no" | "0" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// The value parsed as `T`, such as an enum of the values a trait can
+    /// take, `None` when it does not parse.
+    pub fn parse<T: FromStr>(&self) -> Option<T> {
+        self.value.parse().ok()
+    }
+}
+
+/// A view over the traits of an understanding that only sees values at
+/// least as confident as a minimum, see [`TraitLookup::trait_set`].
+#[derive(Debug, Clone, Copy)]
+pub struct TraitSet<'a> {
+    traits: &'a HashMap<String, Vec<Trait>>,
+    min_confidence: f32,
+    thresholds: Option<&'a TraitThresholds>,
+}
+
+impl<'a> TraitSet<'a> {
+    /// A view over every value of `traits`.
+    pub fn new(traits: &'a HashMap<String, Vec<Trait>>) -> Self {
+        Self {
+            traits,
+            min_confidence: 0.0,
+            thresholds: None,
+        }
+    }
+
+    /// Narrows the view to the values at least as confident as `confidence`.
+    pub fn above(self, confidence: f32) -> Self {
+        Self {
+            min_confidence: self.min_confidence.max(confidence),
+            ..self
+        }
+    }
+
+    /// Narrows the view to the values at least as confident as the
+    /// threshold of their trait.
+    pub fn with_thresholds(self, thresholds: &'a TraitThresholds) -> Self {
+        Self {
+            thresholds: Some(thresholds),
+            ..self
+        }
+    }
+
+    /// The values of a trait in view, in the order Wit.ai listed them.
+    pub fn values(&self, name: &str) -> impl Iterator<Item = &'a Trait> + use<'a> {
+        let threshold = self
+            .thresholds
+            .map_or(0.0, |thresholds| thresholds.threshold(name));
+        let min_confidence = self.min_confidence.max(threshold);
+        self.traits
+            .get(name)
+            .into_iter()
+            .flatten()
+            .filter(move |value| value.confidence >= min_confidence)
+    }
+
+    /// The most confident value of a trait in view, see [`Trait::best`].
+    pub fn get(&self, name: &str) -> Option<&'a Trait> {
+        Trait::best(self.values(name))
+    }
+
+    /// The most confident value of a trait in view, as text.
+    pub fn get_str(&self, name: &str) -> Option<&'a str> {
+        self.get(name).map(|value| value.value.as_str())
+    }
+
+    /// The most confident value of a yes/no trait in view, see
+    /// [`Trait::as_bool`].
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        self.get(name)?.as_bool()
+    }
+
+    /// The most confident value of a trait in view, parsed as `T`.
+    pub fn get_as<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.get(name)?.parse()
+    }
+
+    /// The traits with a value in view and their most confident value, in
+    /// no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a Trait)> + use<'a> {
+        let set = *self;
+        self.traits
+            .keys()
+            .filter_map(move |name| Some((name.as_str(), set.get(name)?)))
+    }
+}
+
+/// Lookups over the entities of an understanding, shared by [`Message`] and
+/// [`SpeechUnderstanding`](crate::model::speech::SpeechUnderstanding).
+pub trait EntityLookup {
+    /// The entities, keyed by `name:role`.
+    fn entity_map(&self) -> &HashMap<String, Vec<Entity>>;
+
+    /// The most confident entity under a `name:role` key, such as
+    /// `wit$datetime:datetime`.
+    fn entity(&self, key: &str) -> Option<&Entity> {
+        self.entity_map()
+            .get(key)?
+            .iter()
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+    }
+
+    /// Every entity with the given name, whatever its role.
+    fn entities_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Entity> {
+        self.entity_map()
+            .values()
+            .flatten()
+            .filter(move |entity| entity.name == name)
+    }
+
+    /// Every entity with the given name and role.
+    fn entities_with_role<'a>(
+        &'a self,
+        name: &'a str,
+        role: &'a str,
+    ) -> impl Iterator<Item = &'a Entity> {
+        self.entities_named(name)
+            .filter(move |entity| entity.role == role)
+    }
+
+    /// Every `wit$datetime` entity, whatever its role.
+    fn datetime_entities(&self) -> impl Iterator<Item = &Entity> {
+        self.entities_named(WIT_DATETIME)
+    }
+
+    /// The value of the most confident entity with the given name, whatever
+    /// its role, deserialised as `T`.
+    ///
+    /// Entities without a value, or whose value is not a `T`, are skipped.
+    fn first_entity_value<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        let mut entities: Vec<&Entity> = self.entities_named(name).collect();
+        entities.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        entities
+            .into_iter()
+            .find_map(|entity| T::deserialize(entity.value.as_ref()?).ok())
+    }
+}
+
+impl EntityLookup for Message {
+    fn entity_map(&self) -> &HashMap<String, Vec<Entity>> {
+        &self.entities
+    }
+}
+
+/// Lookups over the traits of an understanding, shared by [`Message`] and
+/// [`SpeechUnderstanding`](crate::model::speech::SpeechUnderstanding), which
+/// both key their traits by name.
+pub trait TraitLookup {
+    /// The traits, keyed by trait name.
+    fn trait_map(&self) -> &HashMap<String, Vec<Trait>>;
+
+    /// The traits, as a [`TraitSet`] to filter them by confidence.
+    fn trait_set(&self) -> TraitSet<'_> {
+        TraitSet::new(self.trait_map())
+    }
+
+    /// The most confident value of a trait, such as `wit$sentiment`, see
+    /// [`Trait::best`].
+    fn best_trait(&self, name: &str) -> Option<&Trait> {
+        Trait::best(self.trait_map().get(name)?)
+    }
+
+    /// The most confident value of a trait, see [`TraitLookup::best_trait`].
+    fn get_trait(&self, name: &str) -> Option<&Trait> {
+        self.best_trait(name)
+    }
+
+    /// The most confident value of a trait, as text.
+    fn trait_value(&self, name: &str) -> Option<&str> {
+        self.get_trait(name).map(|t| t.value.as_str())
+    }
+
+    /// The most confident value of a yes/no trait, such as `wit$greetings`,
+    /// see [`Trait::as_bool`].
+    fn trait_bool(&self, name: &str) -> Option<bool> {
+        self.get_trait(name)?.as_bool()
+    }
+}
+
+impl TraitLookup for Message {
+    fn trait_map(&self) -> &HashMap<String, Vec<Trait>> {
+        &self.traits
+    }
+}
+
+impl Message {
+    /// The most confident intent, if any.
+    pub fn top_intent(&self) -> Option<&Intent> {
+        self.intents
+            .iter()
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+    }
+
+    /// The intents at least as confident as `confidence`, most confident first.
+    pub fn intents_above(&self, confidence: f32) -> Vec<&Intent> {
+        let mut intents: Vec<&Intent> = self
+            .intents
+            .iter()
+            .filter(|intent| intent.confidence >= confidence)
+            .collect();
+        intents.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        intents
+    }
+
+    /// Decides which intent, if any, the text expresses.
+    pub fn match_intent(&self, thresholds: &IntentThresholds) -> IntentMatch {
+        let candidates = self.intents_above(thresholds.minimum);
+        match candidates.as_slice() {
+            [] => IntentMatch::None,
+            [top, rest @ ..]
+                if top.confidence >= thresholds.confident
+                    && rest.first().is_none_or(|next| {
+                        top.confidence - next.confidence >= thresholds.margin
+                    }) =>
+            {
+                IntentMatch::Confident((*top).clone())
+            }
+            _ => IntentMatch::Ambiguous(candidates.into_iter().cloned().collect()),
+        }
+    }
+}
+
+/// The confidences [`Message::match_intent`] uses to tell intents apart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntentThresholds {
+    /// Intents below this confidence are ignored altogether.
+    pub minimum: f32,
+    /// The confidence the top intent needs to be accepted.
+    pub confident: f32,
+    /// How far ahead of the runner-up the top intent needs to be to be accepted.
+    pub margin: f32,
+}
+
+impl Default for IntentThresholds {
+    fn default() -> Self {
+        Self {
+            minimum: 0.3,
+            confident: 0.7,
+            margin: 0.15,
+        }
+    }
+}
+
+/// The confidence each trait needs for its values to be trusted, applied by
+/// [`TraitSet::with_thresholds`].
+///
+/// Wit.ai stores no detection thresholds, so they are kept with the rest of
+/// the configuration, such as in the
+/// [`TraitDefinition`](crate::model::server::traits::TraitDefinition)s of an
+/// app they can be collected from.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct TraitThresholds {
+    #[serde(default)]
+    default: f32,
+    #[serde(default)]
+    traits: HashMap<String, f32>,
+}
+
+impl TraitThresholds {
+    /// Thresholds letting every value through.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses `confidence` for the traits without a threshold of their own.
+    pub fn with_default(mut self, confidence: f32) -> Self {
+        self.default = confidence;
+        self
+    }
+
+    /// Sets the threshold of a trait.
+    pub fn with_threshold(mut self, name: &str, confidence: f32) -> Self {
+        self.set_threshold(name, confidence);
+        self
+    }
+
+    /// Sets the threshold of a trait, such as when tuning it at runtime.
+    pub fn set_threshold(&mut self, name: &str, confidence: f32) {
+        self.traits.insert(name.to_string(), confidence);
+    }
+
+    /// Removes the threshold of a trait, which falls back to the default.
+    pub fn remove_threshold(&mut self, name: &str) -> Option<f32> {
+        self.traits.remove(name)
+    }
+
+    /// The threshold of a trait.
+    pub fn threshold(&self, name: &str) -> f32 {
+        self.traits.get(name).copied().unwrap_or(self.default)
+    }
+}
+
+/// The intent a text expresses, as decided by [`Message::match_intent`].
+#[derive(Debug, Clone)]
+pub enum IntentMatch {
+    /// A single intent stands out.
+    Confident(Intent),
+    /// Several intents are plausible, or the best one is not confident
+    /// enough. They are listed most confident first.
+    Ambiguous(Vec<Intent>),
+    /// No intent reaches the minimum confidence.
+    None,
+}
+
+/// Counts the values of a map of lists, such as the entities of a [`Message`].
+pub(crate) fn count_values<T>(map: &HashMap<String, Vec<T>>) -> usize {
+    map.values().map(Vec::len).sum()
+}
+
+impl Debug for Message {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return f
+                .debug_struct("Message")
+                .field("text", &self.text)
+                .field("intents", &self.intents)
+                .field("entities", &self.entities)
+                .field("traits", &self.traits)
+                .field("extra", &self.extra)
+                .finish();
+        }
+        f.debug_struct("Message")
+            .field("text", &self.text)
+            .field("top_intent", &self.top_intent().map(|i| &i.name))
+            .field("intents", &self.intents.len())
+            .field("entities", &count_values(&self.entities))
+            .field("traits", &count_values(&self.traits))
+            .finish()
+    }
+}
+
+impl Debug for Entity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return f
+                .debug_struct("Entity")
+                .field("id", &self.id)
+                .field("name", &self.name)
+                .field("role", &self.role)
+                .field("start", &self.start)
+                .field("end", &self.end)
+                .field("body", &self.body)
+                .field("confidence", &self.confidence)
+                .field("entities", &self.entities)
+                .field("type_", &self.type_)
+                .field("value", &self.value)
+                .field("values", &self.values)
+                .field("from", &self.from)
+                .field("to", &self.to)
+                .field("unit", &self.unit)
+                .field("grain", &self.grain)
+                .field("normalized", &self.normalized)
+                .field("resolved", &self.resolved)
+                .field("extra", &self.extra)
+                .finish();
+        }
+        f.debug_struct("Entity")
+            .field("name", &self.name)
+            .field("role", &self.role)
+            .field("body", &self.body)
+            .field("confidence", &self.confidence)
+            .field("entities", &count_values(&self.entities))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn message(intents: &[(&str, f32)]) -> Message {
+        let intents: Vec<Value> = intents
+            .iter()
+            .map(|(name, confidence)| json!({ "id": name, "name": name, "confidence": confidence }))
+            .collect();
+        serde_json::from_value(json!({ "text": "text", "intents": intents })).unwrap()
+    }
+
+    fn names(intents: &[&Intent]) -> Vec<String> {
+        intents.iter().map(|intent| intent.name.clone()).collect()
+    }
+
+    #[test]
+    fn intents_are_ranked_by_confidence() {
+        let message = message(&[("low", 0.2), ("high", 0.9), ("mid", 0.5)]);
+        assert_eq!(message.top_intent().unwrap().name, "high");
+        assert_eq!(names(&message.intents_above(0.0)), ["high", "mid", "low"]);
+    }
+
+    #[test]
+    fn debug_names_the_most_confident_intent() {
+        let message = message(&[("low", 0.2), ("high", 0.9)]);
+        let debug = format!("{message:?}");
+        assert!(debug.contains(r#"top_intent: Some("high")"#), "{debug}");
+    }
+
+    #[test]
+    fn the_threshold_keeps_intents_exactly_at_it() {
+        let message = message(&[("below", 0.49), ("at", 0.5), ("above", 0.8)]);
+        assert_eq!(names(&message.intents_above(0.5)), ["above", "at"]);
+    }
+
+    #[test]
+    fn messages_without_intents_match_nothing() {
+        let message = message(&[]);
+        assert!(message.top_intent().is_none());
+        assert!(message.intents_above(0.0).is_empty());
+        assert!(matches!(
+            message.match_intent(&IntentThresholds::default()),
+            IntentMatch::None
+        ));
+    }
+
+    #[test]
+    fn close_intents_are_ambiguous() {
+        let thresholds = IntentThresholds::default();
+        let confident = message(&[("a", 0.95), ("b", 0.1)]);
+        assert!(matches!(
+            confident.match_intent(&thresholds),
+            IntentMatch::Confident(intent) if intent.name == "a"
+        ));
+        let close = message(&[("a", 0.8), ("b", 0.75)]);
+        assert!(matches!(
+            close.match_intent(&thresholds),
+            IntentMatch::Ambiguous(intents) if intents.len() == 2
+        ));
+    }
+
+    #[test]
+    fn trait_thresholds_apply_per_trait() {
+        let message: Message = serde_json::from_value(json!({
+            "text": "text",
+            "traits": {
+                "wit$sentiment": [{ "id": "1", "value": "positive", "confidence": 0.6 }],
+                "wit$greetings": [{ "id": "2", "value": "true", "confidence": 0.6 }],
+            },
+        }))
+        .unwrap();
+        let mut thresholds = TraitThresholds::new()
+            .with_default(0.5)
+            .with_threshold("wit$sentiment", 0.8);
+        let traits = message.trait_set().with_thresholds(&thresholds);
+        assert_eq!(traits.get_str("wit$sentiment"), None);
+        assert_eq!(traits.get_bool("wit$greetings"), Some(true));
+
+        thresholds.set_threshold("wit$greetings", 0.7);
+        assert_eq!(thresholds.remove_threshold("wit$sentiment"), Some(0.8));
+        let traits = message.trait_set().with_thresholds(&thresholds);
+        assert_eq!(traits.get_str("wit$sentiment"), Some("positive"));
+        assert_eq!(traits.get_bool("wit$greetings"), None);
+        assert_eq!(traits.above(0.65).get_str("wit$sentiment"), None);
+    }
+
+    #[cfg(feature = "serde-serialize")]
+    #[test]
+    fn messages_round_trip() {
+        let payload = json!({
+            "text": "Paint it blue",
+            "intents": [{ "id": "1", "name": "paint", "confidence": 0.9 }],
+            "entities": {
+                "color:color": [{
+                    "id": "2",
+                    "name": "color",
+                    "role": "color",
+                    "start": 9,
+                    "end": 13,
+                    "body": "blue",
+                    "confidence": 0.8,
+                    "entities": {},
+                    "type": "value",
+                    "value": "blue"
+                }]
+            },
+            "traits": { "wit$sentiment": [{ "id": "3", "value": "neutral", "confidence": 0.6 }] },
+            "unknown_field": [1, 2]
+        });
+        let message: Message = serde_json::from_value(payload).unwrap();
+        let encoded = serde_json::to_value(&message).unwrap();
+        assert_eq!(encoded["entities"]["color:color"][0]["type"], "value");
+        assert_eq!(encoded["unknown_field"], json!([1, 2]));
+        let decoded: Message = serde_json::from_value(encoded.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), encoded);
+        assert_eq!(decoded.entity("color:color").unwrap().body, "blue");
+    }
+}
@@ -1,2 +1,43 @@
+#[cfg(feature = "tokio")]
+pub mod aggregate;
+#[cfg(feature = "tokio")]
+pub mod assistant;
+pub mod batch;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod builtin_names;
+pub mod chunking;
 pub mod client;
+pub mod composer;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod context;
+pub mod dictation;
 pub mod entities;
+pub mod id;
+pub mod intents;
+pub mod language;
+pub mod legacy;
+pub mod locale;
+pub mod message;
+pub mod params;
+#[cfg(feature = "playback")]
+pub mod playback;
+pub mod quota;
+#[cfg(feature = "websocket")]
+pub mod realtime;
+pub mod registry;
+#[cfg(feature = "tokio")]
+pub mod resume;
+pub mod router;
+pub mod server;
+pub mod speech;
+pub mod ssml;
+pub mod stream;
+pub mod synthesize;
+pub mod token;
+pub mod transcript;
+pub mod transcription;
+pub mod vad;
+pub mod values;
+pub mod voice;
@@ -0,0 +1,146 @@
+//! Typed query string parameters shared by every endpoint.
+
+use std::fmt::{self, Display, Formatter};
+
+use serde::Serialize;
+use serde::ser::{SerializeSeq, Serializer};
+use url::Url;
+
+use crate::constants::BASE_URL;
+use crate::error::ApiError;
+use crate::model::entities::DynamicEntities;
+
+/// A query string parameter understood by Wit.ai.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Param {
+    /// `q`, the text of a query.
+    Q,
+    /// `n`, the maximum number of results.
+    N,
+    /// `tag`, the tagged version of the app to use.
+    Tag,
+    /// `entities`, the dynamic entities of a query, as JSON.
+    Entities,
+    /// `context`, the context of a query, as JSON.
+    Context,
+    /// `v`, the API version.
+    V,
+    /// `session_id`, the Composer session of an event.
+    SessionId,
+    /// `vad`, whether the end of speech is detected by Wit.ai.
+    Vad,
+    /// `vad_silence_ms`, how long a silence ends the speech.
+    VadSilence,
+}
+
+impl Param {
+    /// The name of the parameter in the query string.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Param::Q => "q",
+            Param::N => "n",
+            Param::Tag => "tag",
+            Param::Entities => "entities",
+            Param::Context => "context",
+            Param::V => "v",
+            Param::SessionId => "session_id",
+            Param::Vad => "vad",
+            Param::VadSilence => "vad_silence_ms",
+        }
+    }
+}
+
+impl Display for Param {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An ordered list of query string parameters.
+///
+/// It can be turned into an endpoint URL with [`QueryParams::to_url`], or
+/// passed to `RequestBuilder::query` as is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryParams {
+    pairs: Vec<(Param, String)>,
+}
+
+impl QueryParams {
+    /// Creates an empty list of parameters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a parameter.
+    pub fn with(mut self, param: Param, value: impl ToString) -> Self {
+        self.pairs.push((param, value.to_string()));
+        self
+    }
+
+    /// Adds a parameter if it has a value.
+    pub fn with_opt(self, param: Param, value: Option<impl ToString>) -> Self {
+        match value {
+            Some(value) => self.with(param, value),
+            None => self,
+        }
+    }
+
+    /// Adds a parameter serialised as JSON if it has a value.
+    pub fn with_json(self, param: Param, value: Option<&impl Serialize>) -> Result<Self, ApiError> {
+        Ok(match value {
+            Some(value) => self.with(param, serde_json::to_string(value)?),
+            None => self,
+        })
+    }
+
+    /// Adds the dynamic entities of a query, failing with
+    /// [`ApiError::DynamicEntitiesTooLarge`] when their JSON is longer than
+    /// `limit` bytes.
+    pub fn with_entities(
+        self,
+        entities: Option<&DynamicEntities>,
+        limit: usize,
+    ) -> Result<Self, ApiError> {
+        let Some(entities) = entities else {
+            return Ok(self);
+        };
+        let json = serde_json::to_string(entities)?;
+        let bytes = json.len();
+        if bytes > limit {
+            tracing::warn!(bytes, limit, "dynamic entities too large, query not sent");
+            return Err(ApiError::DynamicEntitiesTooLarge { bytes, limit });
+        }
+        if bytes > limit / 2 {
+            tracing::warn!(bytes, limit, "dynamic entities are close to the size limit");
+        } else {
+            tracing::debug!(bytes, "dynamic entities size");
+        }
+        Ok(self.with(Param::Entities, json))
+    }
+
+    /// The value of a parameter, if it was added.
+    pub fn get(&self, param: Param) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(p, _)| *p == param)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The URL of the endpoint at `path` with these parameters.
+    pub fn to_url(&self, path: &str) -> Result<Url, ApiError> {
+        Ok(Url::parse_with_params(
+            &format!("{BASE_URL}{path}"),
+            self.pairs.iter().map(|(p, value)| (p.as_str(), value)),
+        )?)
+    }
+}
+
+impl Serialize for QueryParams {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.pairs.len()))?;
+        for (param, value) in &self.pairs {
+            seq.serialize_element(&(param.as_str(), value))?;
+        }
+        seq.end()
+    }
+}
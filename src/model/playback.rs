@@ -0,0 +1,77 @@
+//! Playing synthesised speech through [`rodio`], behind the `playback`
+//! feature.
+//!
+//! The crate does not open an audio device itself: create a
+//! `rodio::OutputStream` and a [`Sink`] connected to it with rodio's own
+//! `playback` feature, then hand the sink to [`WitClient::speak`].
+
+use std::io::{self, Cursor};
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use futures::StreamExt;
+use reqwest::header::ACCEPT;
+use rodio::buffer::SamplesBuffer;
+use rodio::{Decoder, Sink};
+
+use crate::error::ApiError;
+use crate::model::client::{WitClient, query_request_id};
+use crate::model::synthesize::{PCM_SAMPLE_RATE, SynthesizeCodec, SynthesizeQuery};
+
+/// How many PCM chunks may wait in the sink before the download is paused.
+const MAX_QUEUED_CHUNKS: usize = 8;
+
+/// How often a paused download checks whether the sink caught up.
+const BACKPRESSURE_POLL: Duration = Duration::from_millis(20);
+
+impl WitClient {
+    /// Synthesises speech and queues it on `sink`, returning once all of it
+    /// is queued. Call [`Sink::sleep_until_end`] to wait for it to be played.
+    ///
+    /// [`SynthesizeCodec::Pcm`] audio is played as it downloads, and the
+    /// download is paused while the sink is more than a few chunks behind.
+    /// MP3 and WAV audio cannot be decoded before it is complete, so it is
+    /// downloaded whole first.
+    pub async fn speak(&self, query: &SynthesizeQuery, sink: &Sink) -> Result<(), ApiError> {
+        let request = query_request_id(
+            self.prepare_post_request(self.url("synthesize")),
+            query.request_id.as_ref(),
+        )
+        .header(ACCEPT, query.codec.mime_type())
+        .json(query);
+        let response = self.send(request).await?;
+
+        if query.codec != SynthesizeCodec::Pcm {
+            let audio = response.bytes().await?;
+            let source = Decoder::new(Cursor::new(audio))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            sink.append(source);
+            return Ok(());
+        }
+
+        let mut body = response.bytes_stream();
+        let mut pending = BytesMut::new();
+        while let Some(chunk) = body.next().await {
+            pending.extend_from_slice(&chunk?);
+            let whole = pending.len() - pending.len() % 2;
+            let samples = pending.split_to(whole).freeze();
+            if samples.is_empty() {
+                continue;
+            }
+            while sink.len() > MAX_QUEUED_CHUNKS {
+                tokio::time::sleep(BACKPRESSURE_POLL).await;
+            }
+            sink.append(pcm_source(&samples));
+        }
+        Ok(())
+    }
+}
+
+/// Turns signed 16-bit little-endian mono samples into a playable source.
+fn pcm_source(samples: &Bytes) -> SamplesBuffer {
+    let samples: Vec<f32> = samples
+        .chunks_exact(2)
+        .map(|sample| f32::from(i16::from_le_bytes([sample[0], sample[1]])) / 32_768.0)
+        .collect();
+    SamplesBuffer::new(1, PCM_SAMPLE_RATE, samples)
+}
@@ -0,0 +1,127 @@
+//! Rate-limit information reported by Wit.ai alongside its responses.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use reqwest::header::HeaderMap;
+
+/// The header holding the number of requests allowed in the current window.
+const LIMIT_HEADER: &str = "X-RateLimit-Limit";
+/// The header holding the number of requests left in the current window.
+const REMAINING_HEADER: &str = "X-RateLimit-Remaining";
+/// The header holding when the current window ends.
+const RESET_HEADER: &str = "X-RateLimit-Reset";
+
+/// Values of the reset header above this are Unix timestamps rather than a
+/// number of seconds, as no window lasts 30 years.
+const RESET_TIMESTAMP_THRESHOLD: u64 = 1_000_000_000;
+
+/// The rate-limit headers of a response.
+///
+/// Wit.ai does not document these headers and only sends them on some
+/// responses, so every field is optional and applications should keep
+/// handling 429 errors, see [`ApiError::retry_after`](crate::error::ApiError::retry_after).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaInfo {
+    /// How many requests are allowed in the current window.
+    pub limit: Option<u64>,
+    /// How many requests are left in the current window.
+    pub remaining: Option<u64>,
+    /// How long until the current window ends, as of `observed_at`.
+    pub reset_after: Option<Duration>,
+    /// When the response carrying these values was received.
+    pub observed_at: Instant,
+}
+
+impl QuotaInfo {
+    /// Reads the rate-limit headers of a response, `None` when it has none.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let number =
+            |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.trim().parse().ok() };
+        let (limit, remaining, reset) = (
+            number(LIMIT_HEADER),
+            number(REMAINING_HEADER),
+            number(RESET_HEADER),
+        );
+        if limit.is_none() && remaining.is_none() && reset.is_none() {
+            return None;
+        }
+        let reset_after = reset.map(|reset| {
+            if reset < RESET_TIMESTAMP_THRESHOLD {
+                return Duration::from_secs(reset);
+            }
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            Duration::from_secs(reset).saturating_sub(now)
+        });
+        Some(Self {
+            limit,
+            remaining,
+            reset_after,
+            observed_at: Instant::now(),
+        })
+    }
+
+    /// Whether the current window has no request left.
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == Some(0)
+    }
+}
+
+/// Called with the rate-limit information of every response carrying some.
+pub type QuotaCallback = Arc<dyn Fn(&QuotaInfo) + Send + Sync>;
+
+/// The last rate-limit information seen by a client and its clones.
+#[derive(Clone, Default)]
+pub(crate) struct QuotaTracker {
+    last: Arc<Mutex<Option<QuotaInfo>>>,
+    callback: Option<QuotaCallback>,
+}
+
+impl fmt::Debug for QuotaTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QuotaTracker")
+            .field("last", &self.last())
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+impl QuotaTracker {
+    /// A tracker with the same callback and no information yet, for a client
+    /// using another token and so another quota.
+    pub(crate) fn reset(&self) -> Self {
+        Self {
+            last: Arc::default(),
+            callback: self.callback.clone(),
+        }
+    }
+
+    pub(crate) fn with_callback(mut self, callback: QuotaCallback) -> Self {
+        self.callback = Some(callback);
+        self
+    }
+
+    pub(crate) fn last(&self) -> Option<QuotaInfo> {
+        self.last.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Records the rate-limit headers of a response, if it has any.
+    pub(crate) fn observe(&self, headers: &HeaderMap) {
+        let Some(quota) = QuotaInfo::from_headers(headers) else {
+            return;
+        };
+        tracing::debug!(
+            limit = ?quota.limit,
+            remaining = ?quota.remaining,
+            reset_after = ?quota.reset_after,
+            "Wit.ai rate limit"
+        );
+        if let Some(callback) = &self.callback {
+            callback(&quota);
+        }
+        *self.last.lock().unwrap_or_else(|e| e.into_inner()) = Some(quota);
+    }
+}
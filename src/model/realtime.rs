@@ -0,0 +1,253 @@
+//! Realtime speech sessions over a WebSocket, behind the `websocket` feature.
+//!
+//! A session keeps a single connection open for as long as the microphone is,
+//! audio frames are pushed through a [`SpeechSender`] while the events come
+//! back on a regular [`SpeechStream`].
+//!
+//! Wit.ai does not publicly document its realtime protocol. This module
+//! connects to the `/speech` endpoint over `wss://`, sends the same query
+//! parameters and `Content-Type` as the HTTP endpoint, sends audio as binary
+//! frames and marks the end of the audio with an `END_OF_AUDIO` text frame.
+//! Events are expected as the same JSON objects the HTTP endpoint streams.
+
+use std::fmt;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::stream::SplitSink;
+use futures::{SinkExt, StreamExt};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderValue};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+use url::Url;
+
+use crate::error::ApiError;
+use crate::model::client::WitClient;
+use crate::model::params::Param;
+use crate::model::speech::{AudioSource, SpeechQuery, SpeechResponse};
+use crate::model::stream::{SpeechStream, StreamCounters};
+use crate::utils::json::parse_event;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// The text frame telling Wit.ai that no more audio will be sent.
+const END_OF_AUDIO: &str = r#"{"type":"END_OF_AUDIO"}"#;
+
+/// The sending half of a realtime speech session.
+pub struct SpeechSender {
+    sink: SplitSink<Socket, Message>,
+    counters: Arc<StreamCounters>,
+}
+
+impl fmt::Debug for SpeechSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpeechSender").finish_non_exhaustive()
+    }
+}
+
+impl SpeechSender {
+    /// Sends a frame of audio, in the encoding the session was opened with.
+    pub async fn send_audio(&mut self, frame: impl Into<Bytes>) -> Result<(), ApiError> {
+        let frame = frame.into();
+        self.counters.count_upload(frame.len());
+        Ok(self.sink.send(Message::Binary(frame)).await?)
+    }
+
+    /// Tells Wit.ai that the audio is over. The final events keep arriving on
+    /// the event stream, which ends once Wit.ai closes the connection.
+    pub async fn finish(mut self) -> Result<(), ApiError> {
+        self.sink.send(Message::text(END_OF_AUDIO)).await?;
+        Ok(self.sink.flush().await?)
+    }
+}
+
+impl WitClient {
+    /// Opens a realtime speech session.
+    ///
+    /// The audio of `query` is sent right away, before returning, so pass an
+    /// empty buffer to send every frame through the returned [`SpeechSender`].
+    /// Dropping the sender without calling [`SpeechSender::finish`] leaves the
+    /// session open until Wit.ai times it out, unless the event stream is
+    /// dropped as well: both halves share the connection, which is closed
+    /// without a close handshake once neither is left.
+    pub async fn speech_session(
+        &self,
+        mut query: SpeechQuery,
+    ) -> Result<(SpeechSender, SpeechStream), ApiError> {
+        if let Some(context) = self.query_context(query.context.as_ref()) {
+            query.context = Some(context);
+        }
+        query.validate()?;
+        let content_type = query.to_string();
+        let mut url = Url::parse(&self.rebase(query.to_url()?))?;
+        url.query_pairs_mut()
+            .append_pair(Param::V.as_str(), &self.api_version);
+        let scheme = match url.scheme() {
+            "http" => "ws",
+            "https" => "wss",
+            other => {
+                return Err(ApiError::Validation(format!(
+                    "realtime sessions need an http(s) base URL, not {other}"
+                )));
+            }
+        };
+        url.set_scheme(scheme)
+            .map_err(|()| ApiError::Validation(format!("{url} cannot be switched to {scheme}")))?;
+
+        let mut request = url.as_str().into_client_request()?;
+        let headers = request.headers_mut();
+        let invalid_header = |_| ApiError::ContentTypeError(fmt::Error);
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", self.token())).map_err(invalid_header)?,
+        );
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_str(&content_type).map_err(invalid_header)?,
+        );
+
+        let (socket, _) = connect_async(request).await?;
+        let (sink, stream) = socket.split();
+        let counters = Arc::new(StreamCounters::default());
+        let mut sender = SpeechSender {
+            sink,
+            counters: counters.clone(),
+        };
+        match query.format.normalize(query.data, query.normalization) {
+            AudioSource::Buffered(data) if data.is_empty() => {}
+            AudioSource::Buffered(data) => sender.send_audio(data).await?,
+            AudioSource::Stream(mut frames) => {
+                while let Some(frame) = frames.next().await {
+                    sender.send_audio(frame?).await?;
+                }
+            }
+        }
+
+        let confidence = query.confidence;
+        let events = {
+            let counters = counters.clone();
+            stream.filter_map(move |message| {
+                let counters = counters.clone();
+                async move {
+                    let event = match message {
+                        Ok(Message::Text(text)) => {
+                            counters.count_downloaded(text.len());
+                            parse_event::<SpeechResponse>(text.as_bytes())
+                        }
+                        Ok(_) => return None,
+                        Err(e) => Err(e.into()),
+                    };
+                    counters.count_event(&event);
+                    confidence.apply(event)
+                }
+            })
+        };
+        Ok((sender, SpeechStream::new(events, counters)))
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use futures::TryStreamExt;
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+    use tokio_tungstenite::accept_hdr_async;
+    use tokio_tungstenite::tungstenite::handshake::server::{
+        Callback, ErrorResponse, Request, Response,
+    };
+
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::model::speech::Encoding;
+    use crate::test_util::payloads;
+
+    /// What a realtime server saw of a session.
+    #[derive(Debug, Default)]
+    struct Session {
+        uri: String,
+        authorization: String,
+        content_type: String,
+        frames: Vec<Message>,
+    }
+
+    /// Records the handshake of a session.
+    struct Handshake<'a>(&'a mut Session);
+
+    impl Callback for Handshake<'_> {
+        fn on_request(
+            self,
+            request: &Request,
+            response: Response,
+        ) -> Result<Response, ErrorResponse> {
+            let header = |name| request.headers()[name].to_str().unwrap().to_string();
+            self.0.uri = request.uri().to_string();
+            self.0.authorization = header(AUTHORIZATION);
+            self.0.content_type = header(CONTENT_TYPE);
+            Ok(response)
+        }
+    }
+
+    /// A server accepting one session: it records the handshake and the
+    /// frames up to `END_OF_AUDIO`, then answers with the speech events and closes.
+    async fn realtime_server() -> (String, oneshot::Receiver<Session>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let (seen, session) = oneshot::channel();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut session = Session::default();
+            let mut socket = accept_hdr_async(socket, Handshake(&mut session))
+                .await
+                .unwrap();
+            while let Some(frame) = socket.next().await {
+                let frame = frame.unwrap();
+                let end = frame == Message::text(END_OF_AUDIO);
+                session.frames.push(frame);
+                if end {
+                    break;
+                }
+            }
+            for event in payloads::speech_events() {
+                socket.send(Message::text(event.to_string())).await.unwrap();
+            }
+            socket.close(None).await.unwrap();
+            seen.send(session).unwrap();
+        });
+        (url, session)
+    }
+
+    #[tokio::test]
+    async fn sessions_send_audio_and_parse_events() {
+        let (url, session) = realtime_server().await;
+        let client = WitClient::new("token").with_base_url(&url);
+        let query = SpeechQuery::new(Encoding::Wav, Bytes::new());
+        let content_type = query.to_string();
+
+        let (mut sender, events) = client.speech_session(query).await.unwrap();
+        sender.send_audio(&b"RIFF"[..]).await.unwrap();
+        sender.finish().await.unwrap();
+        let events: Vec<_> = events.try_collect().await.unwrap();
+
+        assert_eq!(events.len(), 4);
+        assert!(matches!(events[3], SpeechResponse::FinalUnderstanding(_)));
+        let session = session.await.unwrap();
+        assert!(session.uri.starts_with("/speech?"), "{}", session.uri);
+        assert!(session.uri.contains(&format!("v={}", client.api_version)));
+        assert_eq!(session.authorization, "Bearer token");
+        assert_eq!(session.content_type, content_type);
+        assert_eq!(
+            session.frames,
+            [Message::binary(&b"RIFF"[..]), Message::text(END_OF_AUDIO)]
+        );
+    }
+
+    #[tokio::test]
+    async fn other_schemes_are_rejected() {
+        let client = WitClient::new("token").with_base_url("ftp://example.com/");
+        let query = SpeechQuery::new(Encoding::Wav, Bytes::new());
+        let result = client.speech_session(query).await;
+        assert!(matches!(result, Err(e) if e.kind() == ErrorKind::Validation));
+    }
+}
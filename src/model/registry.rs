@@ -0,0 +1,77 @@
+//! Routing requests between several Wit.ai apps, such as one app per
+//! language.
+
+use std::collections::HashMap;
+
+use crate::model::client::WitClient;
+use crate::model::locale::Locale;
+
+/// Clients for several Wit.ai apps, looked up by app name or by the locale
+/// they serve.
+///
+/// Products shipping one app per language register each app under a name,
+/// route locales to them and pick a client with [`WitRegistry::for_locale`].
+#[derive(Debug, Clone, Default)]
+pub struct WitRegistry {
+    apps: HashMap<String, WitClient>,
+    locales: HashMap<Locale, String>,
+    fallbacks: Vec<String>,
+}
+
+impl WitRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the client of the app named `name`, replacing any client
+    /// registered under that name.
+    ///
+    /// Use [`WitClient::with_token_override`] to create the clients of the
+    /// other apps, so that they share a connection pool.
+    pub fn with_app(mut self, name: &str, client: WitClient) -> Self {
+        self.apps.insert(name.to_string(), client);
+        self
+    }
+
+    /// Routes `locale` to the app named `app`. A locale without a region,
+    /// such as `fr`, also serves every region of its language that is not
+    /// routed on its own.
+    pub fn with_locale(mut self, locale: Locale, app: &str) -> Self {
+        self.locales.insert(locale, app.to_string());
+        self
+    }
+
+    /// Adds `app` to the apps tried, in the order they were added, for
+    /// locales that are not routed.
+    pub fn with_fallback(mut self, app: &str) -> Self {
+        self.fallbacks.push(app.to_string());
+        self
+    }
+
+    /// The client of the app named `name`.
+    pub fn get(&self, name: &str) -> Option<&WitClient> {
+        self.apps.get(name)
+    }
+
+    /// The client serving `locale`: the app routed to the exact locale, then
+    /// the one routed to its language, then the first registered fallback.
+    pub fn for_locale(&self, locale: &Locale) -> Option<&WitClient> {
+        let language = locale
+            .language()
+            .parse::<Locale>()
+            .ok()
+            .and_then(|language| self.locales.get(&language));
+        self.locales
+            .get(locale)
+            .into_iter()
+            .chain(language)
+            .chain(&self.fallbacks)
+            .find_map(|app| self.apps.get(app))
+    }
+
+    /// The names of the registered apps, in no particular order.
+    pub fn apps(&self) -> impl Iterator<Item = &str> {
+        self.apps.keys().map(String::as_str)
+    }
+}
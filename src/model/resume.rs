@@ -0,0 +1,474 @@
+//! Streaming audio that survives dropped connections, for live sources on
+//! flaky networks.
+//!
+//! The last seconds of audio sent are kept, and when the connection fails
+//! mid-stream a new request is made, replaying them before carrying on with
+//! the live audio. Wit.ai treats the new request as a new utterance, so the
+//! output stream marks where it starts with [`Resumable::Reconnected`].
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::lock::Mutex as AsyncMutex;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt, future, stream};
+
+use crate::error::{ApiError, ErrorKind};
+use crate::model::client::WitClient;
+use crate::model::dictation::{Dictation, DictationQuery};
+use crate::model::speech::{AudioFormat, AudioSource, AudioStream, SpeechQuery, SpeechResponse};
+use crate::model::stream::WitStream;
+
+/// How a resumable stream recovers from dropped connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResumePolicy {
+    pub(crate) tail: Duration,
+    pub(crate) max_attempts: u32,
+    pub(crate) initial_backoff: Duration,
+    pub(crate) max_backoff: Duration,
+}
+
+impl Default for ResumePolicy {
+    /// Replays the last 5 seconds, reconnecting up to 5 times in a row with
+    /// waits doubling from 250ms up to 8s.
+    fn default() -> Self {
+        Self {
+            tail: Duration::from_secs(5),
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(8),
+        }
+    }
+}
+
+impl ResumePolicy {
+    /// Creates the default policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replays up to `tail` of the audio sent last after reconnecting, so
+    /// that words cut off by the dropped connection are heard again.
+    pub fn with_tail(mut self, tail: Duration) -> Self {
+        self.tail = tail;
+        self
+    }
+
+    /// Gives up after `max_attempts` failed connections in a row.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Waits `initial` before the first reconnection, doubling the wait
+    /// after each failed one up to `max`.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// The wait before the given reconnection attempt, counted from 1.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// An item of a resumable stream.
+#[derive(Debug, Clone)]
+pub enum Resumable<T> {
+    /// An event of the current connection.
+    Event(T),
+    /// The connection dropped and a new one was made. The events that follow
+    /// belong to a new utterance, starting with the replayed audio.
+    Reconnected {
+        /// How many connections were attempted before this one succeeded.
+        attempt: u32,
+        /// How much audio was replayed.
+        replayed: Duration,
+    },
+}
+
+/// The events of a streaming endpoint across reconnections.
+pub struct ResumableStream<T> {
+    inner: BoxStream<'static, Result<Resumable<T>, ApiError>>,
+}
+
+impl<T> Stream for ResumableStream<T> {
+    type Item = Result<Resumable<T>, ApiError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+impl WitClient {
+    /// Like [`WitClient::post_speech`], reconnecting when the connection
+    /// drops mid-stream, as set by `policy`.
+    ///
+    /// Only raw and μ-law audio can be resumed, as the replayed audio needs
+    /// no header. Requests rejected by Wit.ai are not retried.
+    pub fn post_speech_resumable(
+        &self,
+        query: SpeechQuery,
+        policy: ResumePolicy,
+    ) -> Result<ResumableStream<SpeechResponse>, ApiError> {
+        query.validate()?;
+        let byte_rate = resumable_byte_rate(&query.format)?;
+        let client = self.clone();
+        let template = query.with_data(AudioSource::Buffered(Bytes::new()));
+        let connect = move |data| {
+            let (client, query) = (client.clone(), template.with_data(data));
+            async move { client.post_speech(query).await }
+        };
+        Ok(resumable(policy, byte_rate, query.data, connect))
+    }
+
+    /// Like [`WitClient::post_dictation`], reconnecting when the connection
+    /// drops mid-stream, see [`WitClient::post_speech_resumable`].
+    pub fn post_dictation_resumable(
+        &self,
+        query: DictationQuery,
+        policy: ResumePolicy,
+    ) -> Result<ResumableStream<Dictation>, ApiError> {
+        query.validate()?;
+        let byte_rate = resumable_byte_rate(&query.format)?;
+        let client = self.clone();
+        let template = query.with_data(AudioSource::Buffered(Bytes::new()));
+        let connect = move |data| {
+            let (client, query) = (client.clone(), template.with_data(data));
+            async move { client.post_dictation(query).await }
+        };
+        Ok(resumable(policy, byte_rate, query.data, connect))
+    }
+}
+
+/// The byte rate of audio that can be resumed, failing with
+/// [`ApiError::Validation`] for other audio.
+fn resumable_byte_rate(format: &AudioFormat) -> Result<u32, ApiError> {
+    format
+        .headerless_byte_rate()
+        .ok_or_else(|| ApiError::Validation("only raw and μ-law audio can be resumed".to_string()))
+}
+
+/// The last chunks of audio sent, up to a number of bytes.
+#[derive(Default)]
+struct Tail {
+    chunks: VecDeque<Bytes>,
+    len: usize,
+    capacity: usize,
+}
+
+impl Tail {
+    fn push(&mut self, chunk: Bytes) {
+        self.len += chunk.len();
+        self.chunks.push_back(chunk);
+        while let Some(front) = self.chunks.front()
+            && self.len - front.len() >= self.capacity
+        {
+            self.len -= front.len();
+            self.chunks.pop_front();
+        }
+    }
+}
+
+/// The audio shared by the successive connections of a resumable stream.
+#[derive(Clone)]
+struct Shared {
+    source: Arc<AsyncMutex<AudioStream>>,
+    tail: Arc<Mutex<Tail>>,
+    connection: Arc<AtomicU64>,
+}
+
+impl Shared {
+    fn lock_tail(&self) -> std::sync::MutexGuard<'_, Tail> {
+        self.tail
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The audio of a new connection: the tail, then the live audio. The
+    /// audio of previous connections ends as soon as this one starts.
+    fn body(&self) -> AudioSource {
+        let connection = self.connection.fetch_add(1, Ordering::SeqCst) + 1;
+        let current = move |shared: &Shared| shared.connection.load(Ordering::SeqCst) == connection;
+        let body = stream::unfold(
+            (self.clone(), None::<VecDeque<Bytes>>),
+            move |(shared, mut replay)| async move {
+                if !current(&shared) {
+                    return None;
+                }
+                if replay.is_none() {
+                    // Waits for the previous connection to let go of the
+                    // source, so the chunk it may have been reading is
+                    // replayed too.
+                    let _source = shared.source.lock().await;
+                    replay = Some(shared.lock_tail().chunks.clone());
+                }
+                if let Some(chunk) = replay.as_mut().and_then(VecDeque::pop_front) {
+                    return Some((Ok(chunk), (shared, replay)));
+                }
+                let mut source = shared.source.lock().await;
+                if !current(&shared) {
+                    return None;
+                }
+                let chunk = source.next().await?;
+                if let Ok(chunk) = &chunk {
+                    shared.lock_tail().push(chunk.clone());
+                }
+                drop(source);
+                Some((chunk, (shared, replay)))
+            },
+        );
+        AudioSource::Stream(body.boxed())
+    }
+}
+
+/// Connects with `connect` and forwards the events of each connection,
+/// reconnecting with the replayed tail when one fails with a request error.
+fn resumable<T, F, Fut>(
+    policy: ResumePolicy,
+    byte_rate: u32,
+    data: AudioSource,
+    connect: F,
+) -> ResumableStream<T>
+where
+    T: Send + 'static,
+    F: Fn(AudioSource) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<WitStream<T>, ApiError>> + Send,
+{
+    let source = match data {
+        AudioSource::Buffered(audio) => stream::once(future::ready(Ok(audio))).boxed(),
+        AudioSource::Stream(stream) => stream,
+    };
+    let capacity = (policy.tail.as_secs_f64() * f64::from(byte_rate)) as usize;
+    let shared = Shared {
+        source: Arc::new(AsyncMutex::new(source)),
+        tail: Arc::new(Mutex::new(Tail {
+            capacity,
+            ..Tail::default()
+        })),
+        connection: Arc::default(),
+    };
+
+    struct State<T, F> {
+        connect: F,
+        shared: Shared,
+        current: Option<WitStream<T>>,
+        failures: u32,
+        done: bool,
+    }
+    let state = State {
+        connect,
+        shared,
+        current: None,
+        failures: 0,
+        done: false,
+    };
+    let inner = stream::unfold(state, move |mut state| async move {
+        if state.done {
+            return None;
+        }
+        loop {
+            let Some(current) = &mut state.current else {
+                let replayed = state.shared.lock_tail().len;
+                match (state.connect)(state.shared.body()).await {
+                    Ok(stream) => {
+                        state.current = Some(stream);
+                        if state.failures > 0 {
+                            let reconnected = Resumable::Reconnected {
+                                attempt: state.failures,
+                                replayed: Duration::from_secs_f64(
+                                    replayed as f64 / f64::from(byte_rate),
+                                ),
+                            };
+                            return Some((Ok(reconnected), state));
+                        }
+                    }
+                    Err(e) if retriable(&e) && state.failures < policy.max_attempts => {
+                        state.failures += 1;
+                        tokio::time::sleep(policy.backoff(state.failures)).await;
+                    }
+                    Err(e) => {
+                        state.done = true;
+                        return Some((Err(e), state));
+                    }
+                }
+                continue;
+            };
+            match current.next().await {
+                Some(Ok(event)) => {
+                    state.failures = 0;
+                    return Some((Ok(Resumable::Event(event)), state));
+                }
+                Some(Err(e)) if retriable(&e) && state.failures < policy.max_attempts => {
+                    state.current = None;
+                    state.failures += 1;
+                    tokio::time::sleep(policy.backoff(state.failures)).await;
+                }
+                Some(Err(e)) => {
+                    state.done = true;
+                    return Some((Err(e), state));
+                }
+                None => return None,
+            }
+        }
+    });
+    ResumableStream {
+        inner: inner.boxed(),
+    }
+}
+
+/// Whether an error is a dropped or refused connection, worth reconnecting
+/// after.
+fn retriable(error: &ApiError) -> bool {
+    error.kind() == ErrorKind::Request
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU32;
+
+    use super::*;
+
+    /// A dropped connection, as reqwest reports it.
+    fn request_error() -> ApiError {
+        let error = reqwest::Client::new().get("not a url").build().unwrap_err();
+        ApiError::RequestError(error)
+    }
+
+    fn chunks(chunks: &[&'static [u8]]) -> AudioSource {
+        let chunks: Vec<_> = chunks
+            .iter()
+            .map(|chunk| Ok(Bytes::from_static(chunk)))
+            .collect();
+        AudioSource::Stream(stream::iter(chunks).boxed())
+    }
+
+    /// Reads up to `limit` chunks of a connection's audio.
+    async fn read(data: AudioSource, limit: usize) -> Vec<u8> {
+        let AudioSource::Stream(body) = data else {
+            panic!("resumable connections stream their audio");
+        };
+        let chunks: Vec<_> = body.take(limit).collect().await;
+        chunks
+            .into_iter()
+            .flat_map(|chunk| chunk.unwrap())
+            .collect()
+    }
+
+    fn events<T: Send + 'static>(events: Vec<Result<T, ApiError>>) -> WitStream<T> {
+        WitStream::new(stream::iter(events), Arc::default())
+    }
+
+    fn policy() -> ResumePolicy {
+        ResumePolicy::new()
+            .with_tail(Duration::from_secs(1))
+            .with_backoff(Duration::ZERO, Duration::ZERO)
+    }
+
+    #[test]
+    fn the_tail_keeps_at_least_its_capacity() {
+        let mut tail = Tail {
+            capacity: 6,
+            ..Tail::default()
+        };
+        for chunk in [b"aaaa", b"bbbb", b"cccc"] {
+            tail.push(Bytes::from_static(chunk));
+        }
+        assert_eq!(tail.chunks, [&b"bbbb"[..], &b"cccc"[..]]);
+        assert_eq!(tail.len, 8);
+
+        let mut empty = Tail::default();
+        empty.push(Bytes::from_static(b"aaaa"));
+        assert!(empty.chunks.is_empty());
+        assert_eq!(empty.len, 0);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_its_maximum() {
+        let policy =
+            ResumePolicy::new().with_backoff(Duration::from_millis(250), Duration::from_secs(1));
+        let waits: Vec<_> = (1..=4).map(|attempt| policy.backoff(attempt)).collect();
+        assert_eq!(waits, [250, 500, 1000, 1000].map(Duration::from_millis));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn dropped_connections_replay_the_tail() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let connect = {
+            let calls = calls.clone();
+            move |data| {
+                let attempt = calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    Ok(if attempt == 0 {
+                        let heard = read(data, 2).await;
+                        events(vec![Ok(heard), Err(request_error())])
+                    } else {
+                        events(vec![Ok(read(data, usize::MAX).await)])
+                    })
+                }
+            }
+        };
+        // At 4 bytes a second, the one second tail holds one chunk.
+        let data = chunks(&[b"aaaa", b"bbbb", b"cccc", b"dddd"]);
+        let items: Vec<_> = resumable(policy(), 4, data, connect).collect().await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let items: Vec<_> = items.into_iter().map(Result::unwrap).collect();
+        assert!(matches!(&items[0], Resumable::Event(heard) if heard == b"aaaabbbb"));
+        assert!(matches!(
+            items[1],
+            Resumable::Reconnected {
+                attempt: 1,
+                replayed,
+            } if replayed == Duration::from_secs(1)
+        ));
+        assert!(matches!(&items[2], Resumable::Event(heard) if heard == b"bbbbccccdddd"));
+        assert_eq!(items.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn reconnection_gives_up_after_max_attempts() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let connect = {
+            let calls = calls.clone();
+            move |_| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async { Err::<WitStream<()>, _>(request_error()) }
+            }
+        };
+        let items: Vec<_> = resumable(policy().with_max_attempts(2), 4, chunks(&[]), connect)
+            .collect()
+            .await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(items.len(), 1);
+        assert!(matches!(&items[0], Err(e) if e.kind() == ErrorKind::Request));
+    }
+
+    #[tokio::test]
+    async fn rejected_requests_are_not_retried() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let connect = {
+            let calls = calls.clone();
+            move |_| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async { Err::<WitStream<()>, _>(ApiError::Validation("no".to_string())) }
+            }
+        };
+        let items: Vec<_> = resumable(policy(), 4, chunks(&[]), connect).collect().await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(matches!(&items[..], [Err(ApiError::Validation(_))]));
+    }
+}
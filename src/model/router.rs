@@ -0,0 +1,245 @@
+//! Dispatching understood text and speech to handlers by intent.
+
+use std::collections::HashMap;
+
+use crate::model::intents::Intent;
+use crate::model::message::{Entity, EntityLookup, Message, Trait, TraitLookup};
+use crate::model::speech::SpeechUnderstanding;
+
+/// An understanding handed to the handlers of an [`IntentRouter`], whether
+/// it came from `/message` or from `/speech`.
+///
+/// Its entities and traits are looked up through [`EntityLookup`] and
+/// [`TraitLookup`], as on the understanding itself.
+#[derive(Debug, Clone, Copy)]
+pub enum Utterance<'a> {
+    /// The understanding of a text, from `/message`.
+    Message(&'a Message),
+    /// The final understanding of an utterance, from `/speech`.
+    Speech(&'a SpeechUnderstanding),
+}
+
+impl<'a> Utterance<'a> {
+    /// The understood text.
+    pub fn text(&self) -> &'a str {
+        match self {
+            Utterance::Message(message) => &message.text,
+            Utterance::Speech(understanding) => &understanding.text,
+        }
+    }
+
+    /// The intents matching the text.
+    pub fn intents(&self) -> &'a [Intent] {
+        match self {
+            Utterance::Message(message) => &message.intents,
+            Utterance::Speech(understanding) => &understanding.intents,
+        }
+    }
+}
+
+impl EntityLookup for Utterance<'_> {
+    fn entity_map(&self) -> &HashMap<String, Vec<Entity>> {
+        match self {
+            Utterance::Message(message) => &message.entities,
+            Utterance::Speech(understanding) => &understanding.entities,
+        }
+    }
+}
+
+impl TraitLookup for Utterance<'_> {
+    fn trait_map(&self) -> &HashMap<String, Vec<Trait>> {
+        match self {
+            Utterance::Message(message) => &message.traits,
+            Utterance::Speech(understanding) => &understanding.traits,
+        }
+    }
+}
+
+/// A callback handling an utterance routed to its intent.
+pub type RouteHandler<T> = Box<dyn FnMut(&Intent, Utterance<'_>) -> T + Send>;
+
+/// A callback handling an utterance no route takes.
+pub type FallbackHandler<T> = Box<dyn FnMut(Utterance<'_>) -> T + Send>;
+
+struct Route<T> {
+    min_confidence: f32,
+    handler: RouteHandler<T>,
+}
+
+/// Handlers registered per intent, each with the confidence its intent
+/// needs to reach, returning a `T` for the utterances they handle.
+///
+/// An utterance goes to the route of its most confident intent that has a
+/// route and reaches that route's minimum confidence. Anything else goes to
+/// the handler registered with [`IntentRouter::fallback`], if any.
+pub struct IntentRouter<T> {
+    routes: HashMap<String, Route<T>>,
+    fallback: Option<FallbackHandler<T>>,
+}
+
+impl<T> Default for IntentRouter<T> {
+    fn default() -> Self {
+        Self {
+            routes: HashMap::new(),
+            fallback: None,
+        }
+    }
+}
+
+impl<T> IntentRouter<T> {
+    /// Creates a router without routes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Routes utterances matching `intent` with at least `min_confidence` to
+    /// `handler`, replacing any route registered for that intent.
+    pub fn on<F>(mut self, intent: &str, min_confidence: f32, handler: F) -> Self
+    where
+        F: FnMut(&Intent, Utterance<'_>) -> T + Send + 'static,
+    {
+        let route = Route {
+            min_confidence,
+            handler: Box::new(handler),
+        };
+        self.routes.insert(intent.to_string(), route);
+        self
+    }
+
+    /// Sends utterances no route takes to `handler`.
+    pub fn fallback<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(Utterance<'_>) -> T + Send + 'static,
+    {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// The intents routes are registered for, in no particular order.
+    pub fn intents(&self) -> impl Iterator<Item = &str> {
+        self.routes.keys().map(String::as_str)
+    }
+
+    /// Runs the handler an understood text is routed to, `None` when no route
+    /// takes it and there is no fallback.
+    pub fn dispatch(&mut self, message: &Message) -> Option<T> {
+        self.dispatch_utterance(Utterance::Message(message))
+    }
+
+    /// Runs the handler an understood utterance is routed to, `None` when no
+    /// route takes it and there is no fallback.
+    pub fn dispatch_speech(&mut self, understanding: &SpeechUnderstanding) -> Option<T> {
+        self.dispatch_utterance(Utterance::Speech(understanding))
+    }
+
+    /// Runs the handler an utterance is routed to, whatever endpoint
+    /// understood it.
+    pub fn dispatch_utterance(&mut self, utterance: Utterance<'_>) -> Option<T> {
+        match self.route(utterance.intents()) {
+            Some(intent) => {
+                let route = self.routes.get_mut(&intent.name)?;
+                Some((route.handler)(intent, utterance))
+            }
+            None => self.fallback.as_mut().map(|fallback| fallback(utterance)),
+        }
+    }
+
+    /// The most confident of `intents` taken by a route.
+    fn route<'a>(&self, intents: &'a [Intent]) -> Option<&'a Intent> {
+        intents
+            .iter()
+            .filter(|intent| {
+                self.routes
+                    .get(&intent.name)
+                    .is_some_and(|route| intent.confidence >= route.min_confidence)
+            })
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn message(intents: &[(&str, f32)]) -> Message {
+        let intents: Vec<_> = intents
+            .iter()
+            .map(|(name, confidence)| json!({ "id": name, "name": name, "confidence": confidence }))
+            .collect();
+        serde_json::from_value(json!({ "text": "turn it on", "intents": intents })).unwrap()
+    }
+
+    fn router() -> IntentRouter<String> {
+        IntentRouter::new()
+            .on("lights_on", 0.5, |intent, _| {
+                format!("on {}", intent.confidence)
+            })
+            .on("lights_off", 0.9, |intent, _| {
+                format!("off {}", intent.confidence)
+            })
+    }
+
+    #[test]
+    fn the_most_confident_routed_intent_wins() {
+        let mut router = router();
+        let message = message(&[("weather", 0.95), ("lights_off", 0.5), ("lights_on", 0.75)]);
+        assert_eq!(router.dispatch(&message).as_deref(), Some("on 0.75"));
+    }
+
+    #[test]
+    fn intents_below_their_route_minimum_are_skipped() {
+        let mut router = router();
+        let message = message(&[("lights_off", 0.875), ("lights_on", 0.625)]);
+        assert_eq!(router.dispatch(&message).as_deref(), Some("on 0.625"));
+        assert_eq!(
+            router.dispatch(&self::message(&[("lights_off", 0.875)])),
+            None
+        );
+    }
+
+    #[test]
+    fn unrouted_utterances_reach_the_fallback() {
+        let mut router = router().fallback(|utterance| format!("? {}", utterance.text()));
+        assert_eq!(
+            router.dispatch(&message(&[("weather", 1.0)])).as_deref(),
+            Some("? turn it on")
+        );
+        assert_eq!(
+            router.dispatch(&message(&[])).as_deref(),
+            Some("? turn it on")
+        );
+    }
+
+    #[test]
+    fn speech_is_routed_like_text() {
+        let understanding: SpeechUnderstanding = serde_json::from_value(json!({
+            "text": "lights off",
+            "intents": [{ "id": "1", "name": "lights_off", "confidence": 0.96875 }],
+            "entities": {},
+            "traits": {},
+            "is_final": true
+        }))
+        .unwrap();
+        let mut router = router();
+        assert_eq!(
+            router.dispatch_speech(&understanding).as_deref(),
+            Some("off 0.96875")
+        );
+    }
+
+    #[test]
+    fn later_routes_replace_earlier_ones() {
+        let mut router = router().on("lights_on", 0.0, |_, _| "replaced".to_string());
+        let mut intents: Vec<_> = router.intents().collect();
+        intents.sort();
+        assert_eq!(intents, ["lights_off", "lights_on"]);
+        assert_eq!(
+            router
+                .dispatch(&message(&[("lights_on", 0.125)]))
+                .as_deref(),
+            Some("replaced")
+        );
+    }
+}
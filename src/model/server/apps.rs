@@ -0,0 +1,266 @@
+//! The settings and training state of an app.
+
+#[cfg(any(feature = "tokio", feature = "blocking"))]
+use std::ops::ControlFlow;
+use std::time::Duration;
+#[cfg(any(feature = "tokio", feature = "blocking"))]
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use reqwest::Method;
+use serde::Deserialize;
+#[cfg(feature = "serde-serialize")]
+use serde::Serialize;
+
+use crate::error::ApiError;
+use crate::model::client::path_segment;
+use crate::model::id::Id;
+use crate::model::server::ServerClient;
+use crate::model::server::operation::{Operation, endpoints};
+
+/// Where an app is in retraining its model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+#[serde(rename_all = "lowercase")]
+pub enum TrainingStatus {
+    /// The model is trained on every utterance of the app.
+    Done,
+    /// Utterances changed and training will start soon.
+    Scheduled,
+    /// The model is being trained.
+    Ongoing,
+    /// A status this crate does not know yet.
+    #[serde(other)]
+    Unknown,
+}
+
+/// An app as described by the management API.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct App {
+    /// The unique identifier of the app.
+    pub id: Id,
+    /// The name of the app.
+    pub name: String,
+    /// The language of the app, such as `en`.
+    pub lang: String,
+    /// Whether the app is private.
+    #[serde(default)]
+    pub private: bool,
+    /// When the app was created.
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    /// Where the app is in retraining its model.
+    pub training_status: TrainingStatus,
+    /// When the model was last trained.
+    #[serde(default)]
+    pub last_trained_at: Option<DateTime<Utc>>,
+    /// How long the last training took, in seconds.
+    #[serde(default)]
+    pub last_training_duration_secs: Option<u64>,
+    /// When the scheduled training will start.
+    #[serde(default)]
+    pub will_train_at: Option<DateTime<Utc>>,
+}
+
+/// How waiting for an app to finish training went, see
+/// [`ServerClient::wait_until_trained`].
+#[derive(Debug, Clone)]
+pub struct TrainingReport {
+    /// The app as it was when training was done.
+    pub app: App,
+    /// How long the wait took.
+    pub waited: Duration,
+    /// How many times the app was fetched.
+    pub polls: u32,
+}
+
+impl ServerClient {
+    endpoints! {
+        /// Fetches an app by identifier.
+        fn get_app / get_blocking_app(app_id: &str) -> App {
+            Operation::new(Method::GET, format!("apps/{}", path_segment(app_id)))
+        }
+    }
+
+    /// Fetches where an app is in retraining its model.
+    pub async fn training_status(&self, app_id: &str) -> Result<TrainingStatus, ApiError> {
+        Ok(self.get_app(app_id).await?.training_status)
+    }
+
+    /// Blocking version of [`ServerClient::training_status`].
+    #[cfg(feature = "blocking")]
+    pub fn get_blocking_training_status(&self, app_id: &str) -> Result<TrainingStatus, ApiError> {
+        Ok(self.get_blocking_app(app_id)?.training_status)
+    }
+
+    /// Fetches an app every `poll_interval` until its training status is
+    /// [`TrainingStatus::Done`], to run validation queries only once the
+    /// utterances just pushed are learnt.
+    ///
+    /// The last wait is shortened so that the app is fetched once more right
+    /// at `timeout`, after which this fails with [`ApiError::TrainingTimeout`]
+    /// if training is still not done.
+    #[cfg(feature = "tokio")]
+    pub async fn wait_until_trained(
+        &self,
+        app_id: &str,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<TrainingReport, ApiError> {
+        let mut wait = TrainingWait::new(poll_interval, timeout);
+        loop {
+            match wait.next(self.get_app(app_id).await?)? {
+                ControlFlow::Break(report) => return Ok(report),
+                ControlFlow::Continue(sleep) => tokio::time::sleep(sleep).await,
+            }
+        }
+    }
+
+    /// Blocking version of [`ServerClient::wait_until_trained`].
+    #[cfg(feature = "blocking")]
+    pub fn wait_blocking_until_trained(
+        &self,
+        app_id: &str,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<TrainingReport, ApiError> {
+        let mut wait = TrainingWait::new(poll_interval, timeout);
+        loop {
+            match wait.next(self.get_blocking_app(app_id)?)? {
+                ControlFlow::Break(report) => return Ok(report),
+                ControlFlow::Continue(sleep) => std::thread::sleep(sleep),
+            }
+        }
+    }
+}
+
+/// The polls of [`ServerClient::wait_until_trained`], whose flavours only
+/// differ in how they fetch the app and sleep.
+#[cfg(any(feature = "tokio", feature = "blocking"))]
+struct TrainingWait {
+    start: Instant,
+    polls: u32,
+    poll_interval: Duration,
+    timeout: Duration,
+}
+
+#[cfg(any(feature = "tokio", feature = "blocking"))]
+impl TrainingWait {
+    fn new(poll_interval: Duration, timeout: Duration) -> Self {
+        Self {
+            start: Instant::now(),
+            polls: 0,
+            poll_interval,
+            timeout,
+        }
+    }
+
+    /// Decides what follows a poll of `app`: the report once training is
+    /// done, how long to sleep before polling again, or an error once the
+    /// timeout is reached.
+    fn next(&mut self, app: App) -> Result<ControlFlow<TrainingReport, Duration>, ApiError> {
+        self.next_after(app, self.start.elapsed())
+    }
+
+    /// Like [`TrainingWait::next`], `waited` after the wait started.
+    fn next_after(
+        &mut self,
+        app: App,
+        waited: Duration,
+    ) -> Result<ControlFlow<TrainingReport, Duration>, ApiError> {
+        self.polls += 1;
+        if app.training_status == TrainingStatus::Done {
+            let polls = self.polls;
+            return Ok(ControlFlow::Break(TrainingReport { app, waited, polls }));
+        }
+        match self.timeout.saturating_sub(waited) {
+            Duration::ZERO => Err(ApiError::TrainingTimeout {
+                status: app.training_status,
+                waited,
+            }),
+            remaining => Ok(ControlFlow::Continue(self.poll_interval.min(remaining))),
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "tokio", feature = "blocking")))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn app(status: &str) -> App {
+        serde_json::from_value(json!({
+            "id": "1",
+            "name": "app",
+            "lang": "en",
+            "training_status": status,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn the_last_sleep_ends_at_the_timeout() {
+        let mut wait = TrainingWait::new(Duration::from_secs(10), Duration::from_secs(3));
+        let next = wait.next_after(app("ongoing"), Duration::ZERO).unwrap();
+        assert_eq!(next.continue_value(), Some(Duration::from_secs(3)));
+        let next = wait
+            .next_after(app("ongoing"), Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(next.continue_value(), Some(Duration::from_secs(2)));
+        let error = wait
+            .next_after(app("scheduled"), Duration::from_secs(3))
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ApiError::TrainingTimeout {
+                status: TrainingStatus::Scheduled,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn training_done_ends_the_wait() {
+        let mut wait = TrainingWait::new(Duration::from_secs(1), Duration::from_secs(5));
+        let next = wait.next_after(app("ongoing"), Duration::ZERO).unwrap();
+        assert_eq!(next.continue_value(), Some(Duration::from_secs(1)));
+        let next = wait
+            .next_after(app("done"), Duration::from_secs(9))
+            .unwrap();
+        let ControlFlow::Break(report) = next else {
+            panic!("training is done");
+        };
+        assert_eq!(report.polls, 2);
+        assert_eq!(report.waited, Duration::from_secs(9));
+    }
+
+    #[cfg(all(feature = "tokio", feature = "test-util"))]
+    #[tokio::test]
+    async fn a_long_poll_interval_still_polls_at_the_timeout() {
+        use crate::test_util::MockWit;
+
+        let mock = MockWit::start().await;
+        mock.mock_json(
+            "GET",
+            "apps/1",
+            json!({
+                "id": "1",
+                "name": "app",
+                "lang": "en",
+                "training_status": "ongoing",
+            }),
+        )
+        .await;
+        let started = Instant::now();
+        let error = mock
+            .server_client()
+            .wait_until_trained("1", Duration::from_secs(60), Duration::from_millis(200))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ApiError::TrainingTimeout { .. }));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(mock.server().received_requests().await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,405 @@
+//! Management of the entities of an app.
+
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ApiError, ConflictError};
+use crate::model::batch::BatchOutcome;
+use crate::model::client::path_segment;
+pub use crate::model::entities::Keyword;
+use crate::model::id::Id;
+use crate::model::server::operation::{Batch, Operation, endpoints};
+use crate::model::server::{ServerClient, Versioned};
+
+/// A role an entity can play.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct EntityRole {
+    /// The unique identifier of the role.
+    pub id: Id,
+    /// The name of the role.
+    pub name: String,
+}
+
+/// An entity as described by the management API.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct DetailedEntity {
+    /// The unique identifier of the entity.
+    pub id: Id,
+    /// The name of the entity.
+    pub name: String,
+    /// The roles the entity can play.
+    #[serde(default)]
+    pub roles: Vec<EntityRole>,
+    /// The lookup strategies of the entity, `free-text` and/or `keywords`.
+    #[serde(default)]
+    pub lookups: Vec<String>,
+    /// The keywords of the entity.
+    #[serde(default)]
+    pub keywords: Vec<Keyword>,
+}
+
+/// The desired state of an entity, sent when creating or updating it.
+#[derive(Debug, Clone, Serialize)]
+pub struct EntityDefinition {
+    /// The name of the entity.
+    pub name: String,
+    /// The names of the roles the entity can play.
+    pub roles: Vec<String>,
+    /// The lookup strategies of the entity, `free-text` and/or `keywords`.
+    pub lookups: Vec<String>,
+    /// The keywords of the entity.
+    pub keywords: Vec<Keyword>,
+}
+
+impl Operation<DetailedEntity> {
+    /// Fetches an entity.
+    pub(crate) fn get_entity(name: &str) -> Self {
+        Self::new(Method::GET, entity_path(name))
+    }
+
+    /// Replaces an entity with the given definition.
+    pub(crate) fn update_entity(name: &str, definition: &EntityDefinition) -> Self {
+        Self::new(Method::PUT, entity_path(name)).with_json(definition)
+    }
+
+    /// Adds a keyword, with its synonyms, to a keywords entity.
+    pub(crate) fn add_keyword(entity: &str, keyword: &Keyword) -> Self {
+        Self::new(Method::POST, keywords_path(entity)).with_json(keyword)
+    }
+
+    /// Removes a keyword, and its synonyms, from a keywords entity.
+    pub(crate) fn delete_keyword(entity: &str, keyword: &str) -> Self {
+        Self::new(Method::DELETE, keyword_path(entity, keyword))
+    }
+
+    /// Adds a synonym to a keyword of a keywords entity.
+    pub(crate) fn add_synonym(entity: &str, keyword: &str, synonym: &str) -> Self {
+        Self::new(
+            Method::POST,
+            format!("{}/synonyms", keyword_path(entity, keyword)),
+        )
+        .with_json(&serde_json::json!({ "synonym": synonym }))
+    }
+
+    /// Removes a synonym from a keyword of a keywords entity.
+    pub(crate) fn delete_synonym(entity: &str, keyword: &str, synonym: &str) -> Self {
+        Self::new(Method::DELETE, synonym_path(entity, keyword, synonym))
+    }
+}
+
+impl ServerClient {
+    endpoints! {
+        /// Fetches an entity.
+        fn get_entity / get_blocking_entity(name: &str) -> DetailedEntity {
+            Operation::get_entity(name)
+        }
+
+        /// Fetches an entity along with the content hash used by [`Self::update_entity_checked`].
+        fn get_entity_versioned / get_blocking_entity_versioned(
+            name: &str
+        ) -> Versioned<DetailedEntity> {
+            Operation::hashed(Method::GET, entity_path(name))
+        }
+
+        /// Replaces an entity with the given definition.
+        fn update_entity / update_blocking_entity(
+            name: &str,
+            definition: &EntityDefinition
+        ) -> DetailedEntity {
+            Operation::update_entity(name, definition)
+        }
+
+        /// Deletes an entity.
+        fn delete_entity / delete_blocking_entity(name: &str) -> () {
+            Operation::discarding(Method::DELETE, entity_path(name))
+        }
+
+        /// Adds a keyword, with its synonyms, to a keywords entity.
+        fn add_keyword / add_blocking_keyword(entity: &str, keyword: &Keyword) -> DetailedEntity {
+            Operation::add_keyword(entity, keyword)
+        }
+
+        /// Removes a keyword, and its synonyms, from a keywords entity.
+        fn delete_keyword / delete_blocking_keyword(
+            entity: &str,
+            keyword: &str
+        ) -> DetailedEntity {
+            Operation::delete_keyword(entity, keyword)
+        }
+
+        /// Adds a synonym to a keyword of a keywords entity.
+        fn add_synonym / add_blocking_synonym(
+            entity: &str,
+            keyword: &str,
+            synonym: &str
+        ) -> DetailedEntity {
+            Operation::add_synonym(entity, keyword, synonym)
+        }
+
+        /// Removes a synonym from a keyword of a keywords entity.
+        fn delete_synonym / delete_blocking_synonym(
+            entity: &str,
+            keyword: &str,
+            synonym: &str
+        ) -> DetailedEntity {
+            Operation::delete_synonym(entity, keyword, synonym)
+        }
+
+        /// Deletes every keyword of a keywords entity, such as before a full
+        /// re-import, running up to `concurrency` deletions at a time.
+        ///
+        /// `progress` is called after each deletion with the number of keywords
+        /// handled so far and the total. The indices of the outcome refer to the
+        /// keywords of the entity as it was fetched, and each success holds the
+        /// keyword that was deleted. The blocking version runs each group of
+        /// concurrent deletions on threads of their own.
+        fn clear_entity_keywords / clear_blocking_entity_keywords(
+            entity: &str,
+            concurrency: usize,
+            progress: impl FnMut(usize, usize)
+        ) -> BatchOutcome<Keyword> {
+            Operation::get_entity(entity).then(move |current| {
+                let deletions = current.keywords.into_iter().map(|keyword| {
+                    let deletion = Operation::delete_keyword(entity, &keyword.keyword);
+                    (keyword, deletion.discard_response())
+                });
+                Batch::new(deletions)
+                    .with_concurrency(concurrency)
+                    .with_progress(progress)
+            })
+        }
+    }
+
+    /// Replaces an entity, unless it changed since `expected_hash` was captured.
+    ///
+    /// The entity is fetched again right before writing; if its content hash no
+    /// longer matches, nothing is written and [`ApiError::Conflict`] is returned.
+    /// This narrows, but cannot fully close, the window in which a concurrent
+    /// writer goes unnoticed, as Wit.ai offers no conditional writes.
+    pub async fn update_entity_checked(
+        &self,
+        name: &str,
+        definition: &EntityDefinition,
+        expected_hash: u64,
+    ) -> Result<DetailedEntity, ApiError> {
+        let current = self.get_entity_versioned(name).await?;
+        check_hash(name, &current, expected_hash)?;
+        self.update_entity(name, definition).await
+    }
+
+    /// Blocking version of [`ServerClient::update_entity_checked`].
+    #[cfg(feature = "blocking")]
+    pub fn update_blocking_entity_checked(
+        &self,
+        name: &str,
+        definition: &EntityDefinition,
+        expected_hash: u64,
+    ) -> Result<DetailedEntity, ApiError> {
+        let current = self.get_blocking_entity_versioned(name)?;
+        check_hash(name, &current, expected_hash)?;
+        self.update_blocking_entity(name, definition)
+    }
+
+    /// Adds several keywords to a keywords entity one after the other,
+    /// carrying on when some of them fail.
+    ///
+    /// Each success holds the keyword that was added.
+    pub async fn add_keywords(&self, entity: &str, keywords: &[Keyword]) -> BatchOutcome<Keyword> {
+        add_keywords(entity, keywords).send(self).await
+    }
+
+    /// Blocking version of [`ServerClient::add_keywords`].
+    #[cfg(feature = "blocking")]
+    pub fn add_blocking_keywords(
+        &self,
+        entity: &str,
+        keywords: &[Keyword],
+    ) -> BatchOutcome<Keyword> {
+        add_keywords(entity, keywords).send_blocking(self)
+    }
+}
+
+/// The requests adding each keyword to an entity.
+fn add_keywords(entity: &str, keywords: &[Keyword]) -> Batch<Keyword> {
+    Batch::new(keywords.iter().map(|keyword| {
+        let addition = Operation::add_keyword(entity, keyword);
+        (keyword.clone(), addition.discard_response())
+    }))
+}
+
+/// The path of an entity.
+fn entity_path(name: &str) -> String {
+    format!("entities/{}", path_segment(name))
+}
+
+/// The path of the keywords of an entity.
+fn keywords_path(entity: &str) -> String {
+    format!("{}/keywords", entity_path(entity))
+}
+
+/// The path of a keyword of an entity.
+fn keyword_path(entity: &str, keyword: &str) -> String {
+    format!("{}/{}", keywords_path(entity), path_segment(keyword))
+}
+
+/// The path of a synonym of a keyword of an entity.
+fn synonym_path(entity: &str, keyword: &str, synonym: &str) -> String {
+    format!(
+        "{}/synonyms/{}",
+        keyword_path(entity, keyword),
+        path_segment(synonym)
+    )
+}
+
+/// Fails with [`ApiError::Conflict`] when an entity no longer has the
+/// content hash it was read with.
+fn check_hash(
+    name: &str,
+    current: &Versioned<DetailedEntity>,
+    expected_hash: u64,
+) -> Result<(), ApiError> {
+    if current.hash == expected_hash {
+        return Ok(());
+    }
+    Err(ConflictError {
+        resource: format!("entities/{name}"),
+        expected: expected_hash,
+        found: current.hash,
+    }
+    .into())
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util::MockWit;
+
+    /// An entity with three keywords, where deleting `green` fails.
+    async fn mock_entity() -> MockWit {
+        let mock = MockWit::start().await;
+        let entity = json!({
+            "id": "1",
+            "name": "color",
+            "keywords": [
+                { "keyword": "blue", "synonyms": [] },
+                { "keyword": "green", "synonyms": [] },
+                { "keyword": "red", "synonyms": [] },
+            ],
+        });
+        mock.mock_json("GET", "entities/color", entity.clone())
+            .await;
+        for keyword in ["blue", "red"] {
+            let endpoint = format!("entities/color/keywords/{keyword}");
+            mock.mock_json("DELETE", &endpoint, entity.clone()).await;
+        }
+        mock.mock_error(
+            "DELETE",
+            "entities/color/keywords/green",
+            500,
+            "unknown",
+            "oops",
+        )
+        .await;
+        mock
+    }
+
+    fn assert_cleared(outcome: BatchOutcome<Keyword>, progress: Vec<(usize, usize)>) {
+        let deleted: Vec<_> = outcome
+            .successes
+            .iter()
+            .map(|(index, keyword)| (*index, keyword.keyword.as_str()))
+            .collect();
+        assert_eq!(deleted, [(0, "blue"), (2, "red")]);
+        assert_eq!(outcome.failures.len(), 1);
+        assert_eq!(outcome.failures[0].index, 1);
+        assert_eq!(progress, [(1, 3), (2, 3), (3, 3)]);
+    }
+
+    #[tokio::test]
+    async fn clear_entity_keywords_reports_progress_in_order() {
+        let mock = mock_entity().await;
+        let mut progress = Vec::new();
+        let outcome = mock
+            .server_client()
+            .clear_entity_keywords("color", 2, |done, total| progress.push((done, total)))
+            .await;
+        assert_cleared(outcome.unwrap(), progress);
+    }
+
+    #[cfg(feature = "blocking")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn blocking_clear_entity_keywords_makes_the_same_deletions() {
+        let mock = mock_entity().await;
+        let mut progress = Vec::new();
+        let outcome =
+            mock.server_client()
+                .clear_blocking_entity_keywords("color", 2, |done, total| {
+                    progress.push((done, total))
+                });
+        assert_cleared(outcome.unwrap(), progress);
+    }
+
+    #[cfg(feature = "blocking")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn blocking_deletions_overlap() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        use serde_json::Value;
+        use wiremock::matchers::method;
+        use wiremock::{Mock, Request, Respond, ResponseTemplate};
+
+        /// Answers deletions after a delay, noting the most that were ever in
+        /// flight at once: those that arrived minus those the batch reported done.
+        struct InFlight {
+            body: Value,
+            arrived: AtomicUsize,
+            done: Arc<AtomicUsize>,
+            most: Arc<AtomicUsize>,
+        }
+
+        impl Respond for InFlight {
+            fn respond(&self, _: &Request) -> ResponseTemplate {
+                let arrived = self.arrived.fetch_add(1, Ordering::SeqCst) + 1;
+                let in_flight = arrived - self.done.load(Ordering::SeqCst);
+                self.most.fetch_max(in_flight, Ordering::SeqCst);
+                ResponseTemplate::new(200)
+                    .set_body_json(&self.body)
+                    .set_delay(Duration::from_millis(200))
+            }
+        }
+
+        let mock = MockWit::start().await;
+        let entity = json!({
+            "id": "1",
+            "name": "color",
+            "keywords": [{ "keyword": "blue" }, { "keyword": "green" }, { "keyword": "red" }],
+        });
+        mock.mock_json("GET", "entities/color", entity.clone())
+            .await;
+        let done = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        Mock::given(method("DELETE"))
+            .respond_with(InFlight {
+                body: entity,
+                arrived: AtomicUsize::new(0),
+                done: done.clone(),
+                most: most.clone(),
+            })
+            .mount(mock.server())
+            .await;
+
+        let outcome =
+            mock.server_client()
+                .clear_blocking_entity_keywords("color", 3, |finished, _| {
+                    done.store(finished, Ordering::SeqCst)
+                });
+        assert_eq!(outcome.unwrap().success_count(), 3);
+        assert!(most.load(Ordering::SeqCst) > 1);
+    }
+}
@@ -0,0 +1,169 @@
+//! Importing keywords from spreadsheets exported as CSV or TSV.
+
+use std::collections::HashMap;
+use std::io::{self, Read};
+
+use crate::error::ApiError;
+use crate::model::batch::BatchOutcome;
+use crate::model::server::ServerClient;
+use crate::model::server::entities::Keyword;
+
+/// How a keywords file is laid out.
+///
+/// By default the file is comma separated, starts with a header row, holds
+/// the keyword in its first column and one synonym in each following column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    pub(crate) delimiter: u8,
+    pub(crate) has_headers: bool,
+    pub(crate) keyword_column: usize,
+    pub(crate) synonyms_column: Option<usize>,
+    pub(crate) synonyms_separator: char,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            has_headers: true,
+            keyword_column: 0,
+            synonyms_column: None,
+            synonyms_separator: '|',
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Options for a comma separated file.
+    pub fn csv() -> Self {
+        Self::default()
+    }
+
+    /// Options for a tab separated file.
+    pub fn tsv() -> Self {
+        Self::default().with_delimiter(b'\t')
+    }
+
+    /// Sets the byte separating columns.
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Sets whether the first row holds column names rather than a keyword.
+    pub fn with_headers(mut self, has_headers: bool) -> Self {
+        self.has_headers = has_headers;
+        self
+    }
+
+    /// Sets the column holding the keyword, counted from 0.
+    pub fn with_keyword_column(mut self, column: usize) -> Self {
+        self.keyword_column = column;
+        self
+    }
+
+    /// Reads every synonym from a single column, separated by `separator`,
+    /// instead of one synonym per column.
+    pub fn with_synonyms_column(mut self, column: usize, separator: char) -> Self {
+        self.synonyms_column = Some(column);
+        self.synonyms_separator = separator;
+        self
+    }
+}
+
+/// Parses a keywords file, merging the rows of a same keyword and dropping
+/// duplicate synonyms.
+///
+/// Keywords keep the order of their first row. Blank rows are skipped, while
+/// rows with synonyms but no keyword are rejected.
+pub fn parse_keywords(reader: impl Read, options: &CsvOptions) -> Result<Vec<Keyword>, ApiError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .has_headers(options.has_headers)
+        .flexible(true)
+        .from_reader(reader);
+
+    let mut keywords: Vec<Keyword> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for record in reader.records() {
+        let record = record.map_err(io::Error::from)?;
+        let keyword = record
+            .get(options.keyword_column)
+            .unwrap_or_default()
+            .trim();
+        let synonyms: Vec<&str> = match options.synonyms_column {
+            Some(column) => record
+                .get(column)
+                .unwrap_or_default()
+                .split(options.synonyms_separator)
+                .collect(),
+            None => record
+                .iter()
+                .enumerate()
+                .filter(|&(column, _)| column != options.keyword_column)
+                .map(|(_, value)| value)
+                .collect(),
+        };
+        let synonyms = synonyms
+            .into_iter()
+            .map(str::trim)
+            .filter(|synonym| !synonym.is_empty());
+
+        if keyword.is_empty() {
+            if synonyms.count() == 0 {
+                continue;
+            }
+            let line = record.position().map_or(0, |position| position.line());
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {line} has synonyms but no keyword"),
+            )
+            .into());
+        }
+
+        let index = *positions.entry(keyword.to_string()).or_insert_with(|| {
+            keywords.push(Keyword {
+                keyword: keyword.to_string(),
+                synonyms: Vec::new(),
+            });
+            keywords.len() - 1
+        });
+        let entry = &mut keywords[index];
+        for synonym in synonyms {
+            if !entry.synonyms.iter().any(|known| known == synonym) {
+                entry.synonyms.push(synonym.to_string());
+            }
+        }
+    }
+    Ok(keywords)
+}
+
+impl ServerClient {
+    /// Adds the keywords of a CSV or TSV file to a keywords entity.
+    ///
+    /// The file is parsed with [`parse_keywords`] before anything is uploaded,
+    /// so a malformed file is rejected as a whole. Keywords are then added with
+    /// [`ServerClient::add_keywords`], and the indices of the outcome refer to
+    /// the parsed keywords rather than to the rows of the file.
+    pub async fn import_keywords_from_csv(
+        &self,
+        entity: &str,
+        reader: impl Read,
+        options: CsvOptions,
+    ) -> Result<BatchOutcome<Keyword>, ApiError> {
+        let keywords = parse_keywords(reader, &options)?;
+        Ok(self.add_keywords(entity, &keywords).await)
+    }
+
+    /// Blocking version of [`ServerClient::import_keywords_from_csv`].
+    #[cfg(feature = "blocking")]
+    pub fn import_blocking_keywords_from_csv(
+        &self,
+        entity: &str,
+        reader: impl Read,
+        options: CsvOptions,
+    ) -> Result<BatchOutcome<Keyword>, ApiError> {
+        let keywords = parse_keywords(reader, &options)?;
+        Ok(self.add_blocking_keywords(entity, &keywords))
+    }
+}
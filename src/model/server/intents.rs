@@ -0,0 +1,73 @@
+//! Management of the intents of an app.
+
+use reqwest::Method;
+use serde::Deserialize;
+#[cfg(feature = "serde-serialize")]
+use serde::Serialize;
+
+use crate::model::client::path_segment;
+use crate::model::entities::split_entity_key;
+use crate::model::id::Id;
+pub use crate::model::intents::IntentEntity;
+use crate::model::intents::IntentRef;
+use crate::model::server::ServerClient;
+use crate::model::server::operation::{Operation, endpoints};
+
+/// An intent as described by the management API.
+///
+/// Wit.ai does not report how often an intent matches, so there are no
+/// usage counts.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct DetailedIntent {
+    /// The unique identifier of the intent.
+    pub id: Id,
+    /// The name of the intent.
+    pub name: String,
+    /// The entities found in the utterances the intent was trained with.
+    #[serde(default)]
+    pub entities: Vec<IntentEntity>,
+}
+
+impl DetailedIntent {
+    /// The identity of this intent, without its details.
+    pub fn to_ref(&self) -> IntentRef {
+        IntentRef {
+            id: self.id.clone(),
+            name: self.name.clone(),
+        }
+    }
+
+    /// Whether the intent was trained with the entity named `name`, with or
+    /// without its role.
+    pub fn has_entity(&self, name: &str) -> bool {
+        self.entities
+            .iter()
+            .any(|entity| entity.name == name || split_entity_key(&entity.name).0 == name)
+    }
+}
+
+impl ServerClient {
+    endpoints! {
+        /// Lists the intents of the app.
+        fn list_intents / list_blocking_intents() -> Vec<IntentRef> {
+            Operation::new(Method::GET, "intents")
+        }
+
+        /// Fetches an intent by name.
+        fn get_intent / get_blocking_intent(name: &str) -> IntentRef {
+            Operation::new(Method::GET, format!("intents/{}", path_segment(name)))
+        }
+
+        /// Fetches an intent by name along with the entities it was trained
+        /// with.
+        fn get_intent_full / get_blocking_intent_full(name: &str) -> DetailedIntent {
+            Operation::new(Method::GET, format!("intents/{}", path_segment(name)))
+        }
+
+        /// Deletes an intent, along with its training utterances.
+        fn delete_intent / delete_blocking_intent(name: &str) -> () {
+            Operation::discarding(Method::DELETE, format!("intents/{}", path_segment(name)))
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! App management endpoints, which require a server access token.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use serde_json::Value;
+
+use crate::model::client::WitClient;
+
+pub mod apps;
+pub mod entities;
+#[cfg(feature = "csv")]
+pub mod import;
+pub mod intents;
+mod operation;
+pub mod sync;
+pub mod traits;
+
+/// A client for the app management endpoints.
+///
+/// It shares its connection pool and configuration with the [`WitClient`] it
+/// was created from, which must use a server access token.
+#[derive(Debug, Clone)]
+pub struct ServerClient {
+    pub(crate) client: WitClient,
+}
+
+impl ServerClient {
+    /// Creates a management client authenticated with a server access token.
+    pub fn new(token: &str) -> Self {
+        Self::from(WitClient::new(token))
+    }
+
+    /// Returns a management client authenticated with another server token,
+    /// sharing this client's connection pool.
+    pub fn with_token_override(&self, token: &str) -> Self {
+        Self::from(self.client.with_token_override(token))
+    }
+}
+
+impl From<WitClient> for ServerClient {
+    fn from(client: WitClient) -> Self {
+        Self { client }
+    }
+}
+
+/// A management resource together with the content hash it had when it was read.
+///
+/// The hash is used by guarded updates, such as
+/// [`ServerClient::update_entity_checked`], to detect concurrent modifications.
+#[derive(Debug, Clone)]
+pub struct Versioned<T> {
+    /// The resource as it was read.
+    pub value: T,
+    /// The content hash of the resource as it was read.
+    pub hash: u64,
+}
+
+/// Hashes a JSON value. Object keys are sorted, so the hash does not depend on
+/// the order Wit.ai sends fields in.
+pub(crate) fn content_hash(value: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.to_string().hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,312 @@
+//! Management requests described independently of how they are sent.
+//!
+//! Every endpoint builds an [`Operation`] once, and the async and blocking
+//! methods only differ in the transport executing it, so the two flavours
+//! cannot drift apart. Endpoints made of several requests combine operations
+//! into a [`Workflow`]: a [`Batch`] of them, or one whose response decides
+//! what is sent next with [`Operation::then`].
+
+use futures::{StreamExt, stream};
+use reqwest::Method;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::error::ApiError;
+use crate::model::batch::BatchOutcome;
+use crate::model::server::{ServerClient, Versioned, content_hash};
+
+/// A single management request and how to read its response.
+pub(crate) struct Operation<T> {
+    method: Method,
+    path: String,
+    body: Option<serde_json::Result<Value>>,
+    parse: fn(&str) -> Result<T, ApiError>,
+}
+
+impl<T: DeserializeOwned> Operation<T> {
+    /// A request whose JSON response is deserialised as `T`.
+    pub(crate) fn new(method: Method, path: impl Into<String>) -> Self {
+        Self {
+            method,
+            path: path.into(),
+            body: None,
+            parse: parse_json::<T>,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Operation<Versioned<T>> {
+    /// A request whose JSON response is deserialised as `T` and hashed.
+    pub(crate) fn hashed(method: Method, path: impl Into<String>) -> Self {
+        Self {
+            method,
+            path: path.into(),
+            body: None,
+            parse: parse_hashed::<T>,
+        }
+    }
+}
+
+impl Operation<()> {
+    /// A request whose response is ignored.
+    pub(crate) fn discarding(method: Method, path: impl Into<String>) -> Self {
+        Self {
+            method,
+            path: path.into(),
+            body: None,
+            parse: |_| Ok(()),
+        }
+    }
+}
+
+impl<T> Operation<T> {
+    /// Sends `body` as the JSON body of the request.
+    pub(crate) fn with_json(mut self, body: &impl Serialize) -> Self {
+        self.body = Some(serde_json::to_value(body));
+        self
+    }
+
+    /// The same request, ignoring its response.
+    pub(crate) fn discard_response(self) -> Operation<()> {
+        Operation {
+            method: self.method,
+            path: self.path,
+            body: self.body,
+            parse: |_| Ok(()),
+        }
+    }
+
+    /// Sends this request, then the workflow `next` builds from its response.
+    pub(crate) fn then<N, C: FnOnce(T) -> N>(self, next: C) -> Then<T, C> {
+        Then { first: self, next }
+    }
+}
+
+/// Requests standing for a `T` each, sent up to `concurrency` at a time and
+/// carrying on when some of them fail.
+///
+/// Outcomes are recorded in the order of the requests, and `progress` is
+/// called after each with the number of requests handled so far and the
+/// total.
+pub(crate) struct Batch<T, P = fn(usize, usize)> {
+    steps: Vec<(T, Operation<()>)>,
+    concurrency: usize,
+    progress: P,
+}
+
+impl<T> Batch<T> {
+    /// A batch sending its requests one after the other.
+    pub(crate) fn new(steps: impl IntoIterator<Item = (T, Operation<()>)>) -> Self {
+        Self {
+            steps: steps.into_iter().collect(),
+            concurrency: 1,
+            progress: |_, _| {},
+        }
+    }
+}
+
+impl<T, P: FnMut(usize, usize)> Batch<T, P> {
+    /// Sends up to `concurrency` requests at a time.
+    pub(crate) fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Reports progress to `progress`.
+    pub(crate) fn with_progress<Q: FnMut(usize, usize)>(self, progress: Q) -> Batch<T, Q> {
+        Batch {
+            steps: self.steps,
+            concurrency: self.concurrency,
+            progress,
+        }
+    }
+}
+
+/// A request whose response builds the workflow sent next, see
+/// [`Operation::then`].
+pub(crate) struct Then<F, C> {
+    first: Operation<F>,
+    next: C,
+}
+
+/// Management requests that can be sent by both the async and the blocking
+/// transports.
+pub(crate) trait Workflow {
+    /// What the workflow returns once every request is sent.
+    type Output;
+
+    /// Sends the requests of the workflow.
+    async fn run(self, server: &ServerClient) -> Result<Self::Output, ApiError>;
+
+    /// Sends the requests of the workflow, blocking until they are done.
+    #[cfg(feature = "blocking")]
+    fn run_blocking(self, server: &ServerClient) -> Result<Self::Output, ApiError>;
+}
+
+impl<T: Send> Workflow for Operation<T> {
+    type Output = T;
+
+    async fn run(self, server: &ServerClient) -> Result<T, ApiError> {
+        server.execute(self).await
+    }
+
+    #[cfg(feature = "blocking")]
+    fn run_blocking(self, server: &ServerClient) -> Result<T, ApiError> {
+        server.execute_blocking(self)
+    }
+}
+
+impl<T: Send, P: FnMut(usize, usize)> Batch<T, P> {
+    /// Sends the requests of the batch.
+    pub(crate) async fn send(mut self, server: &ServerClient) -> BatchOutcome<T> {
+        let total = self.steps.len();
+        let mut results = stream::iter(self.steps.into_iter().enumerate())
+            .map(|(index, (value, operation))| async move {
+                (index, server.execute(operation).await.map(|()| value))
+            })
+            .buffered(self.concurrency);
+        let mut outcome = BatchOutcome::new();
+        while let Some((index, result)) = results.next().await {
+            outcome.push(index, result);
+            (self.progress)(outcome.len(), total);
+        }
+        outcome
+    }
+
+    /// Sends the requests of the batch in groups of `concurrency`, each on a
+    /// thread of its own, blocking until they are done.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn send_blocking(mut self, server: &ServerClient) -> BatchOutcome<T> {
+        let total = self.steps.len();
+        let run = |(index, (value, operation)): (usize, (T, Operation<()>))| {
+            (index, server.execute_blocking(operation).map(|()| value))
+        };
+        let mut steps = self.steps.into_iter().enumerate();
+        let mut outcome = BatchOutcome::new();
+        loop {
+            let group: Vec<_> = steps.by_ref().take(self.concurrency).collect();
+            let results: Vec<_> = match group.len() {
+                0 => break,
+                1 => group.into_iter().map(run).collect(),
+                _ => std::thread::scope(|scope| {
+                    let threads: Vec<_> = group
+                        .into_iter()
+                        .map(|step| scope.spawn(move || run(step)))
+                        .collect();
+                    threads
+                        .into_iter()
+                        .map(|thread| {
+                            thread
+                                .join()
+                                .unwrap_or_else(|e| std::panic::resume_unwind(e))
+                        })
+                        .collect()
+                }),
+            };
+            for (index, result) in results {
+                outcome.push(index, result);
+                (self.progress)(outcome.len(), total);
+            }
+        }
+        outcome
+    }
+}
+
+impl<T: Send, P: FnMut(usize, usize)> Workflow for Batch<T, P> {
+    type Output = BatchOutcome<T>;
+
+    async fn run(self, server: &ServerClient) -> Result<BatchOutcome<T>, ApiError> {
+        Ok(self.send(server).await)
+    }
+
+    #[cfg(feature = "blocking")]
+    fn run_blocking(self, server: &ServerClient) -> Result<BatchOutcome<T>, ApiError> {
+        Ok(self.send_blocking(server))
+    }
+}
+
+impl<F: Send, N: Workflow, C: FnOnce(F) -> N> Workflow for Then<F, C> {
+    type Output = N::Output;
+
+    async fn run(self, server: &ServerClient) -> Result<N::Output, ApiError> {
+        let response = self.first.run(server).await?;
+        (self.next)(response).run(server).await
+    }
+
+    #[cfg(feature = "blocking")]
+    fn run_blocking(self, server: &ServerClient) -> Result<N::Output, ApiError> {
+        let response = self.first.run_blocking(server)?;
+        (self.next)(response).run_blocking(server)
+    }
+}
+
+fn parse_json<T: DeserializeOwned>(body: &str) -> Result<T, ApiError> {
+    Ok(serde_json::from_str(body)?)
+}
+
+fn parse_hashed<T: DeserializeOwned>(body: &str) -> Result<Versioned<T>, ApiError> {
+    let value: Value = serde_json::from_str(body)?;
+    Ok(Versioned {
+        hash: content_hash(&value),
+        value: serde_json::from_value(value)?,
+    })
+}
+
+impl ServerClient {
+    /// Sends an operation.
+    pub(crate) async fn execute<T>(&self, operation: Operation<T>) -> Result<T, ApiError> {
+        let mut request = self
+            .client
+            .prepare_request(operation.method, self.client.url(&operation.path));
+        if let Some(body) = operation.body {
+            request = request.json(&body?);
+        }
+        let body = self.client.send(request).await?.text().await?;
+        (operation.parse)(&body)
+    }
+
+    /// Sends an operation, blocking until its response is read.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn execute_blocking<T: Send>(&self, operation: Operation<T>) -> Result<T, ApiError> {
+        self.client.blocking(|client| {
+            let mut request = self.client.prepare_blocking_request(
+                client,
+                operation.method,
+                self.client.url(&operation.path),
+            );
+            if let Some(body) = operation.body {
+                request = request.json(&body?);
+            }
+            let body = self.client.send_blocking(request)?.text()?;
+            (operation.parse)(&body)
+        })
+    }
+}
+
+/// Declares management endpoints backed by an [`Operation`] or any other
+/// [`Workflow`], generating an async method and its blocking counterpart for
+/// each.
+macro_rules! endpoints {
+    ($(
+        $(#[doc = $doc:literal])*
+        fn $name:ident / $blocking:ident($($arg:ident: $ty:ty),*) -> $out:ty {
+            $operation:expr
+        }
+    )*) => {
+        $(
+            $(#[doc = $doc])*
+            pub async fn $name(&self, $($arg: $ty),*) -> Result<$out, $crate::error::ApiError> {
+                $crate::model::server::operation::Workflow::run($operation, self).await
+            }
+
+            #[cfg(feature = "blocking")]
+            #[doc = concat!("Blocking version of [`ServerClient::", stringify!($name), "`].")]
+            pub fn $blocking(&self, $($arg: $ty),*) -> Result<$out, $crate::error::ApiError> {
+                $crate::model::server::operation::Workflow::run_blocking($operation, self)
+            }
+        )*
+    };
+}
+
+pub(crate) use endpoints;
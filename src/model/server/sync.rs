@@ -0,0 +1,263 @@
+//! Bringing the keywords of an entity to a desired state.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::model::batch::BatchOutcome;
+use crate::model::server::ServerClient;
+use crate::model::server::entities::{DetailedEntity, EntityDefinition, Keyword};
+use crate::model::server::operation::{Batch, Operation, endpoints};
+
+/// A single change made to the keywords of an entity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeywordChange {
+    /// Adds a keyword along with its synonyms.
+    AddKeyword(Keyword),
+    /// Removes a keyword along with its synonyms.
+    DeleteKeyword(String),
+    /// Adds a synonym to an existing keyword.
+    AddSynonym {
+        /// The keyword the synonym belongs to.
+        keyword: String,
+        /// The synonym to add.
+        synonym: String,
+    },
+    /// Removes a synonym from an existing keyword.
+    DeleteSynonym {
+        /// The keyword the synonym belongs to.
+        keyword: String,
+        /// The synonym to remove.
+        synonym: String,
+    },
+}
+
+/// The changes turning the `current` keywords of an entity into the
+/// `desired` ones: removals first, then additions, each in keyword order.
+///
+/// Keywords present on both sides only get their synonyms adjusted. Keyword
+/// and synonym order is not significant, so it is never changed.
+pub fn plan_keyword_sync(current: &[Keyword], desired: &[Keyword]) -> Vec<KeywordChange> {
+    let synonyms = |keywords: &[Keyword]| -> HashMap<String, BTreeSet<String>> {
+        let mut map: HashMap<String, BTreeSet<String>> = HashMap::new();
+        for keyword in keywords {
+            map.entry(keyword.keyword.clone())
+                .or_default()
+                .extend(keyword.synonyms.iter().cloned());
+        }
+        map
+    };
+    let (current, desired) = (synonyms(current), synonyms(desired));
+    let mut names: Vec<&String> = current.keys().chain(desired.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    let (mut removals, mut additions) = (Vec::new(), Vec::new());
+    for name in names {
+        match (current.get(name), desired.get(name)) {
+            (Some(_), None) => removals.push(KeywordChange::DeleteKeyword(name.clone())),
+            (None, Some(wanted)) => additions.push(KeywordChange::AddKeyword(Keyword {
+                keyword: name.clone(),
+                synonyms: wanted.iter().cloned().collect(),
+            })),
+            (Some(have), Some(wanted)) => {
+                removals.extend(have.difference(wanted).map(|synonym| {
+                    KeywordChange::DeleteSynonym {
+                        keyword: name.clone(),
+                        synonym: synonym.clone(),
+                    }
+                }));
+                additions.extend(wanted.difference(have).map(|synonym| {
+                    KeywordChange::AddSynonym {
+                        keyword: name.clone(),
+                        synonym: synonym.clone(),
+                    }
+                }));
+            }
+            (None, None) => {}
+        }
+    }
+    removals.extend(additions);
+    removals
+}
+
+impl From<DetailedEntity> for EntityDefinition {
+    fn from(entity: DetailedEntity) -> Self {
+        Self {
+            name: entity.name,
+            roles: entity.roles.into_iter().map(|role| role.name).collect(),
+            lookups: entity.lookups,
+            keywords: entity.keywords,
+        }
+    }
+}
+
+impl ServerClient {
+    endpoints! {
+        /// Replaces every keyword of a keywords entity in a single request,
+        /// keeping its roles and lookups.
+        fn entity_set_keywords / entity_set_blocking_keywords(
+            entity: &str,
+            keywords: Vec<Keyword>
+        ) -> DetailedEntity {
+            Operation::get_entity(entity).then(move |current| {
+                let definition = EntityDefinition {
+                    keywords,
+                    ..current.into()
+                };
+                Operation::update_entity(entity, &definition)
+            })
+        }
+
+        /// Brings the keywords of an entity to `desired` with the fewest
+        /// requests, as planned by [`plan_keyword_sync`], carrying on when some
+        /// of them fail.
+        ///
+        /// The indices of the outcome refer to the planned changes, and each
+        /// success holds the change that was made.
+        fn sync_keywords / sync_blocking_keywords(
+            entity: &str,
+            desired: &[Keyword]
+        ) -> BatchOutcome<KeywordChange> {
+            Operation::get_entity(entity).then(move |current| {
+                let changes = plan_keyword_sync(&current.keywords, desired);
+                Batch::new(changes.into_iter().map(|change| {
+                    let operation = change_operation(entity, &change);
+                    (change, operation)
+                }))
+            })
+        }
+    }
+}
+
+/// The request making a change to the keywords of an entity.
+fn change_operation(entity: &str, change: &KeywordChange) -> Operation<()> {
+    match change {
+        KeywordChange::AddKeyword(keyword) => Operation::add_keyword(entity, keyword),
+        KeywordChange::DeleteKeyword(keyword) => Operation::delete_keyword(entity, keyword),
+        KeywordChange::AddSynonym { keyword, synonym } => {
+            Operation::add_synonym(entity, keyword, synonym)
+        }
+        KeywordChange::DeleteSynonym { keyword, synonym } => {
+            Operation::delete_synonym(entity, keyword, synonym)
+        }
+    }
+    .discard_response()
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use serde_json::{Value, json};
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, ResponseTemplate};
+
+    use super::*;
+    use crate::test_util::MockWit;
+
+    fn entity(keywords: Value) -> Value {
+        json!({
+            "id": "1",
+            "name": "color",
+            "roles": [{ "id": "2", "name": "color" }],
+            "lookups": ["keywords"],
+            "keywords": keywords,
+        })
+    }
+
+    fn keyword(keyword: &str, synonyms: &[&str]) -> Keyword {
+        Keyword {
+            keyword: keyword.to_string(),
+            synonyms: synonyms.iter().map(|synonym| synonym.to_string()).collect(),
+        }
+    }
+
+    /// An entity with `red` and `blue`, where deleting `blue` fails.
+    async fn mock_entity() -> MockWit {
+        let mock = MockWit::start().await;
+        let current = entity(json!([
+            { "keyword": "blue", "synonyms": ["blue"] },
+            { "keyword": "red", "synonyms": ["red", "crimson"] },
+        ]));
+        mock.mock_json("GET", "entities/color", current.clone())
+            .await;
+        for (http_method, endpoint) in [
+            ("POST", "entities/color/keywords"),
+            ("POST", "entities/color/keywords/red/synonyms"),
+            ("DELETE", "entities/color/keywords/red/synonyms/crimson"),
+        ] {
+            mock.mock_json(http_method, endpoint, current.clone()).await;
+        }
+        mock.mock_error(
+            "DELETE",
+            "entities/color/keywords/blue",
+            404,
+            "not-found",
+            "gone",
+        )
+        .await;
+        mock
+    }
+
+    fn desired() -> Vec<Keyword> {
+        vec![
+            keyword("green", &["green"]),
+            keyword("red", &["red", "scarlet"]),
+        ]
+    }
+
+    fn assert_synced(outcome: BatchOutcome<KeywordChange>) {
+        let made: Vec<_> = outcome.successes.iter().map(|(index, _)| *index).collect();
+        assert_eq!(made, [1, 2, 3]);
+        assert_eq!(outcome.failures.len(), 1);
+        assert_eq!(outcome.failures[0].index, 0);
+        assert_eq!(
+            outcome.successes[1].1,
+            KeywordChange::AddKeyword(keyword("green", &["green"]))
+        );
+    }
+
+    #[tokio::test]
+    async fn sync_keywords_carries_on_after_failures() {
+        let mock = mock_entity().await;
+        let outcome = mock
+            .server_client()
+            .sync_keywords("color", &desired())
+            .await;
+        assert_synced(outcome.unwrap());
+    }
+
+    #[cfg(feature = "blocking")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn blocking_sync_keywords_makes_the_same_changes() {
+        let mock = mock_entity().await;
+        let outcome = mock
+            .server_client()
+            .sync_blocking_keywords("color", &desired());
+        assert_synced(outcome.unwrap());
+        let requests = mock.server().received_requests().await.unwrap();
+        assert_eq!(requests.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn set_keywords_keeps_roles_and_lookups() {
+        let mock = MockWit::start().await;
+        mock.mock_json("GET", "entities/color", entity(json!([])))
+            .await;
+        let definition = json!({
+            "name": "color",
+            "roles": ["color"],
+            "lookups": ["keywords"],
+            "keywords": [{ "keyword": "red", "synonyms": ["red"] }],
+        });
+        Mock::given(method("PUT"))
+            .and(path("/entities/color"))
+            .and(body_json(definition))
+            .respond_with(ResponseTemplate::new(200).set_body_json(entity(json!([]))))
+            .expect(1)
+            .mount(mock.server())
+            .await;
+        let keywords = vec![keyword("red", &["red"])];
+        mock.server_client()
+            .entity_set_keywords("color", keywords)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,309 @@
+//! Management of the traits of an app and of the values they can take.
+
+use std::collections::BTreeSet;
+
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use crate::model::batch::BatchOutcome;
+use crate::model::client::path_segment;
+use crate::model::id::Id;
+use crate::model::message::TraitThresholds;
+use crate::model::server::ServerClient;
+use crate::model::server::operation::{Batch, Operation, endpoints};
+
+/// A trait of an app, as listed by the management API.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct TraitRef {
+    /// The unique identifier of the trait.
+    pub id: Id,
+    /// The name of the trait.
+    pub name: String,
+}
+
+/// A value a trait can take.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct TraitValue {
+    /// The unique identifier of the value.
+    pub id: Id,
+    /// The value, such as `positive`.
+    pub value: String,
+}
+
+/// A trait as described by the management API.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct DetailedTrait {
+    /// The unique identifier of the trait.
+    pub id: Id,
+    /// The name of the trait.
+    pub name: String,
+    /// The values the trait can take.
+    #[serde(default)]
+    pub values: Vec<TraitValue>,
+}
+
+impl DetailedTrait {
+    /// The values the trait can take, as text.
+    pub fn value_names(&self) -> impl Iterator<Item = &str> {
+        self.values.iter().map(|value| value.value.as_str())
+    }
+}
+
+/// The desired state of a trait, sent when creating it and read from
+/// version-controlled configuration files to sync its values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraitDefinition {
+    /// The name of the trait.
+    pub name: String,
+    /// The values the trait can take.
+    pub values: Vec<String>,
+    /// The confidence below which detected values of the trait are ignored.
+    ///
+    /// Wit.ai stores no detection thresholds, so this one is never sent: it
+    /// is applied at runtime through [`TraitThresholds`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f32>,
+}
+
+impl From<DetailedTrait> for TraitDefinition {
+    fn from(value: DetailedTrait) -> Self {
+        Self {
+            name: value.name,
+            values: value.values.into_iter().map(|value| value.value).collect(),
+            threshold: None,
+        }
+    }
+}
+
+impl<'a> FromIterator<&'a TraitDefinition> for TraitThresholds {
+    /// The thresholds set by the definitions that have one.
+    fn from_iter<I: IntoIterator<Item = &'a TraitDefinition>>(definitions: I) -> Self {
+        let mut thresholds = Self::new();
+        for definition in definitions {
+            if let Some(threshold) = definition.threshold {
+                thresholds.set_threshold(&definition.name, threshold);
+            }
+        }
+        thresholds
+    }
+}
+
+/// A single change made to the values of a trait.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraitValueChange {
+    /// Adds a value.
+    AddValue(String),
+    /// Removes a value.
+    DeleteValue(String),
+}
+
+/// The changes turning the `current` values of a trait into the `desired`
+/// ones: removals first, then additions, each in value order.
+pub fn plan_trait_values_sync(current: &[String], desired: &[String]) -> Vec<TraitValueChange> {
+    let current: BTreeSet<&String> = current.iter().collect();
+    let desired: BTreeSet<&String> = desired.iter().collect();
+    let removals = current
+        .difference(&desired)
+        .map(|value| TraitValueChange::DeleteValue((*value).clone()));
+    let additions = desired
+        .difference(&current)
+        .map(|value| TraitValueChange::AddValue((*value).clone()));
+    removals.chain(additions).collect()
+}
+
+impl Operation<DetailedTrait> {
+    /// Fetches a trait by name, along with its values.
+    pub(crate) fn get_trait(name: &str) -> Self {
+        Self::new(Method::GET, trait_path(name))
+    }
+
+    /// Adds a value to a trait.
+    pub(crate) fn add_trait_value(name: &str, value: &str) -> Self {
+        Self::new(Method::POST, format!("{}/values", trait_path(name)))
+            .with_json(&serde_json::json!({ "value": value }))
+    }
+}
+
+impl Operation<()> {
+    /// Removes a value from a trait.
+    pub(crate) fn delete_trait_value(name: &str, value: &str) -> Self {
+        Self::discarding(
+            Method::DELETE,
+            format!("{}/values/{}", trait_path(name), path_segment(value)),
+        )
+    }
+}
+
+impl ServerClient {
+    endpoints! {
+        /// Lists the traits of the app.
+        fn list_traits / list_blocking_traits() -> Vec<TraitRef> {
+            Operation::new(Method::GET, "traits")
+        }
+
+        /// Fetches a trait by name, along with its values.
+        fn get_trait / get_blocking_trait(name: &str) -> DetailedTrait {
+            Operation::get_trait(name)
+        }
+
+        /// Creates a trait with the given values. Its threshold is not sent,
+        /// see [`TraitDefinition::threshold`].
+        fn create_trait / create_blocking_trait(definition: &TraitDefinition) -> DetailedTrait {
+            Operation::new(Method::POST, "traits").with_json(&serde_json::json!({
+                "name": definition.name,
+                "values": definition.values,
+            }))
+        }
+
+        /// Deletes a trait.
+        fn delete_trait / delete_blocking_trait(name: &str) -> () {
+            Operation::discarding(Method::DELETE, trait_path(name))
+        }
+
+        /// Adds a value to a trait.
+        fn add_trait_value / add_blocking_trait_value(name: &str, value: &str) -> DetailedTrait {
+            Operation::add_trait_value(name, value)
+        }
+
+        /// Removes a value from a trait.
+        fn delete_trait_value / delete_blocking_trait_value(name: &str, value: &str) -> () {
+            Operation::delete_trait_value(name, value)
+        }
+
+        /// Brings the values of a trait to `values` with the fewest requests, as
+        /// planned by [`plan_trait_values_sync`], carrying on when some of them
+        /// fail.
+        ///
+        /// The indices of the outcome refer to the planned changes, and each
+        /// success holds the change that was made.
+        fn set_trait_values / set_blocking_trait_values(
+            name: &str,
+            values: &[String]
+        ) -> BatchOutcome<TraitValueChange> {
+            Operation::get_trait(name).then(move |current| {
+                let current = TraitDefinition::from(current).values;
+                let changes = plan_trait_values_sync(&current, values);
+                Batch::new(changes.into_iter().map(|change| {
+                    let operation = match &change {
+                        TraitValueChange::AddValue(value) => {
+                            Operation::add_trait_value(name, value).discard_response()
+                        }
+                        TraitValueChange::DeleteValue(value) => {
+                            Operation::delete_trait_value(name, value)
+                        }
+                    };
+                    (change, operation)
+                }))
+            })
+        }
+    }
+}
+
+/// The path of a trait.
+fn trait_path(name: &str) -> String {
+    format!("traits/{}", path_segment(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn definitions() -> Vec<TraitDefinition> {
+        serde_json::from_value(json!([
+            { "name": "mood", "values": ["happy", "sad"], "threshold": 0.75 },
+            { "name": "polite", "values": ["yes", "no"] },
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn thresholds_are_collected_from_definitions() {
+        let thresholds: TraitThresholds = definitions().iter().collect();
+        assert_eq!(thresholds.threshold("mood"), 0.75);
+        assert_eq!(thresholds.threshold("polite"), 0.0);
+        let encoded = serde_json::to_value(&definitions()[1]).unwrap();
+        assert_eq!(
+            encoded,
+            json!({ "name": "polite", "values": ["yes", "no"] })
+        );
+    }
+
+    #[cfg(feature = "test-util")]
+    mod server {
+        use wiremock::matchers::{body_json, method, path};
+        use wiremock::{Mock, ResponseTemplate};
+
+        use super::*;
+        use crate::test_util::MockWit;
+
+        /// A trait with `happy` and `sad`, where removing `sad` fails.
+        async fn mock_trait() -> MockWit {
+            let mock = MockWit::start().await;
+            let current = json!({
+                "id": "1",
+                "name": "mood",
+                "values": [{ "id": "2", "value": "happy" }, { "id": "3", "value": "sad" }],
+            });
+            mock.mock_json("GET", "traits/mood", current.clone()).await;
+            mock.mock_json("POST", "traits/mood/values", current).await;
+            mock.mock_error("DELETE", "traits/mood/values/sad", 500, "unknown", "oops")
+                .await;
+            mock
+        }
+
+        fn values() -> Vec<String> {
+            vec!["angry".to_string(), "happy".to_string()]
+        }
+
+        fn assert_set(outcome: BatchOutcome<TraitValueChange>) {
+            assert_eq!(outcome.failures.len(), 1);
+            assert_eq!(outcome.failures[0].index, 0);
+            let added = TraitValueChange::AddValue("angry".to_string());
+            assert_eq!(outcome.successes, [(1, added)]);
+        }
+
+        #[tokio::test]
+        async fn set_trait_values_carries_on_after_failures() {
+            let mock = mock_trait().await;
+            let outcome = mock
+                .server_client()
+                .set_trait_values("mood", &values())
+                .await;
+            assert_set(outcome.unwrap());
+        }
+
+        #[cfg(feature = "blocking")]
+        #[tokio::test(flavor = "multi_thread")]
+        async fn blocking_set_trait_values_makes_the_same_changes() {
+            let mock = mock_trait().await;
+            let outcome = mock
+                .server_client()
+                .set_blocking_trait_values("mood", &values());
+            assert_set(outcome.unwrap());
+        }
+
+        #[tokio::test]
+        async fn thresholds_are_not_sent() {
+            let mock = MockWit::start().await;
+            Mock::given(method("POST"))
+                .and(path("/traits"))
+                .and(body_json(
+                    json!({ "name": "mood", "values": ["happy", "sad"] }),
+                ))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "id": "1",
+                    "name": "mood",
+                })))
+                .expect(1)
+                .mount(mock.server())
+                .await;
+            let created = mock.server_client().create_trait(&definitions()[0]).await;
+            assert_eq!(created.unwrap().name, "mood");
+        }
+    }
+}
@@ -0,0 +1,1625 @@
+//! Speech understanding through the streaming `/speech` endpoint.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use futures::stream::{AbortHandle, Abortable};
+use futures::{Stream, StreamExt, future, stream};
+use reqwest::Body;
+#[cfg(feature = "compression")]
+use reqwest::header::CONTENT_ENCODING;
+use reqwest::header::CONTENT_TYPE;
+use serde::Deserialize;
+#[cfg(feature = "serde-serialize")]
+use serde::Serialize;
+use serde_json::Value;
+
+use url::Url;
+
+use crate::constants::{DYNAMIC_ENTITIES_MAX_BYTES, SPEECH_MAX_DURATION};
+use crate::error::{ApiError, WavError};
+use crate::model::chunking::{ChunkMetrics, ChunkStrategy, Chunking};
+use crate::model::client::{WitClient, query_request_id};
+use crate::model::context::Context;
+use crate::model::entities::DynamicEntities;
+use crate::model::intents::Intent;
+use crate::model::locale::Locale;
+use crate::model::message::{Entity, EntityLookup, Trait, TraitLookup, count_values};
+use crate::model::params::{Param, QueryParams};
+use crate::model::stream::{SpeechStream, StreamCounters};
+use crate::model::vad::VadConfig;
+use crate::utils::audio::{Normalization, WavSampleFormat, normalize_pcm16, parse_wav_header};
+#[cfg(feature = "audio-dsp")]
+use crate::utils::audio::{PcmSample, Resampler, resample};
+#[cfg(feature = "compression")]
+use crate::utils::compression::{Compression, Compressor};
+use crate::utils::json::{json_stream, parse_event};
+
+/// The container format of the uploaded audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// A WAV file, all parameters are read from its header.
+    Wav,
+    /// An MP3 file.
+    Mp3,
+    /// An Ogg file.
+    Ogg,
+    /// Opus audio in an Ogg container, as produced by most VoIP stacks.
+    OggOpus,
+    /// A FLAC file.
+    Flac,
+    /// Raw μ-law samples.
+    Ulaw,
+    /// Headerless samples, described by the raw encoding, bits, rate and endian parameters.
+    Raw,
+}
+
+/// How headerless samples are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawEncoding {
+    /// Signed integers.
+    SignedInteger,
+    /// Unsigned integers.
+    UnsignedInteger,
+    /// IEEE floating point numbers.
+    FloatingPoint,
+    /// μ-law companded samples.
+    MuLaw,
+    /// A-law companded samples.
+    ALaw,
+}
+
+impl Display for RawEncoding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RawEncoding::SignedInteger => "signed-integer",
+            RawEncoding::UnsignedInteger => "unsigned-integer",
+            RawEncoding::FloatingPoint => "floating-point",
+            RawEncoding::MuLaw => "mu-law",
+            RawEncoding::ALaw => "a-law",
+        })
+    }
+}
+
+/// The byte order of headerless samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    /// Little-endian samples.
+    Little,
+    /// Big-endian samples.
+    Big,
+}
+
+impl Display for Endian {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Endian::Little => "little",
+            Endian::Big => "big",
+        })
+    }
+}
+
+/// The audio parameters shared by the speech and dictation queries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AudioFormat {
+    pub encoding: Encoding,
+    pub raw_encoding: Option<RawEncoding>,
+    pub bits: Option<u8>,
+    pub sample_rate: Option<u32>,
+    pub endian: Option<Endian>,
+    pub content_type_override: Option<String>,
+}
+
+impl AudioFormat {
+    pub(crate) fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            raw_encoding: None,
+            bits: None,
+            sample_rate: None,
+            endian: None,
+            content_type_override: None,
+        }
+    }
+
+    /// Checks that the parameters describe audio Wit.ai accepts, failing with
+    /// [`ApiError::Validation`] naming what is wrong otherwise.
+    pub(crate) fn validate(&self) -> Result<(), ApiError> {
+        if self.content_type_override.is_some() {
+            return Ok(());
+        }
+        match self.encoding {
+            Encoding::Ulaw => match self.sample_rate {
+                Some(rate) if !ULAW_SAMPLE_RATES.contains(&rate) => Err(ApiError::Validation(
+                    format!("μ-law audio must be sampled at 8000 or 16000 Hz, not {rate} Hz"),
+                )),
+                _ => Ok(()),
+            },
+            Encoding::Raw => {
+                let missing: Vec<&str> = [
+                    ("encoding", self.raw_encoding.is_none()),
+                    ("bits", self.bits.is_none()),
+                    ("sample rate", self.sample_rate.is_none()),
+                    ("endianness", self.endian.is_none()),
+                ]
+                .into_iter()
+                .filter_map(|(name, missing)| missing.then_some(name))
+                .collect();
+                if missing.is_empty() {
+                    return Ok(());
+                }
+                Err(ApiError::Validation(format!(
+                    "raw audio needs its {} to be set",
+                    missing.join(", ")
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Where the samples of uncompressed audio lie and how fast they play:
+    /// raw samples, μ-law samples and WAV files. Other encodings and queries
+    /// with an overridden content type give `None`.
+    fn sample_layout(&self, audio: &[u8]) -> Option<SampleLayout> {
+        if self.content_type_override.is_some() {
+            return None;
+        }
+        let layout = match self.encoding {
+            Encoding::Raw => SampleLayout {
+                data: 0..audio.len(),
+                frame: usize::from(self.bits?.div_ceil(8)),
+                frames_per_second: self.sample_rate?,
+                wav_big_endian: None,
+            },
+            Encoding::Ulaw => SampleLayout {
+                data: 0..audio.len(),
+                frame: 1,
+                frames_per_second: self.sample_rate.unwrap_or(ULAW_SAMPLE_RATES[0]),
+                wav_big_endian: None,
+            },
+            Encoding::Wav => {
+                let header = parse_wav_header(audio).ok()?;
+                SampleLayout {
+                    frame: usize::from(header.channels) * usize::from(header.bits.div_ceil(8)),
+                    frames_per_second: header.sample_rate,
+                    wav_big_endian: Some(header.big_endian),
+                    data: header.data,
+                }
+            }
+            _ => return None,
+        };
+        (layout.frame > 0 && layout.frames_per_second > 0).then_some(layout)
+    }
+
+    /// How many bytes of headerless audio, raw or μ-law, play per second.
+    /// Other encodings give `None`.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn headerless_byte_rate(&self) -> Option<u32> {
+        let layout = self.sample_layout(&[])?;
+        let frame = u32::try_from(layout.frame).ok()?;
+        layout
+            .wav_big_endian
+            .is_none()
+            .then_some(layout.frames_per_second * frame)
+    }
+
+    /// Estimates how long buffered audio lasts from its size and parameters.
+    ///
+    /// Only uncompressed audio can be estimated: raw samples, μ-law samples
+    /// and WAV files. Other encodings, streamed audio and queries with an
+    /// overridden content type give `None`.
+    pub(crate) fn estimated_duration(&self, data: &AudioSource) -> Option<Duration> {
+        let AudioSource::Buffered(audio) = data else {
+            return None;
+        };
+        let layout = self.sample_layout(audio)?;
+        Some(layout.offset(layout.data.len() / layout.frame))
+    }
+
+    /// Splits buffered uncompressed audio into pieces of `segment_len`, each
+    /// starting `overlap` before the previous one ends, returning each piece
+    /// with its offset from the start of the audio.
+    ///
+    /// Pieces are cut on frame boundaries. WAV pieces get a copy of the
+    /// header with their own sizes, so each is a valid file.
+    pub(crate) fn split(
+        &self,
+        data: &AudioSource,
+        segment_len: Duration,
+        overlap: Duration,
+    ) -> Result<Vec<(Duration, Bytes)>, ApiError> {
+        let layout = match data {
+            AudioSource::Buffered(audio) => self.sample_layout(audio).map(|layout| (audio, layout)),
+            AudioSource::Stream(_) => None,
+        };
+        let Some((audio, layout)) = layout else {
+            return Err(ApiError::Validation(
+                "only buffered raw, μ-law and WAV audio can be split".to_string(),
+            ));
+        };
+        let frames_in = |duration: Duration| {
+            (duration.as_secs_f64() * f64::from(layout.frames_per_second)) as usize
+        };
+        let (segment, shared) = (frames_in(segment_len), frames_in(overlap));
+        if segment <= shared {
+            return Err(ApiError::Validation(format!(
+                "segments of {segment_len:?} cannot overlap by {overlap:?}"
+            )));
+        }
+
+        let frames = layout.data.len() / layout.frame;
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        loop {
+            let end = (start + segment).min(frames);
+            let samples =
+                &audio[layout.data.start + start * layout.frame..][..(end - start) * layout.frame];
+            pieces.push((layout.offset(start), layout.piece(audio, samples)));
+            if end == frames {
+                return Ok(pieces);
+            }
+            start += segment - shared;
+        }
+    }
+
+    /// Fails with [`ApiError::AudioTooLong`] when buffered audio is estimated
+    /// to last longer than `max_duration`, so it is not uploaded in vain.
+    pub(crate) fn check_duration(
+        &self,
+        data: &AudioSource,
+        max_duration: Duration,
+    ) -> Result<(), ApiError> {
+        match self.estimated_duration(data) {
+            Some(duration) if duration > max_duration => Err(ApiError::AudioTooLong {
+                max_duration,
+                message: format!(
+                    "the audio lasts about {:.1}s and was not sent",
+                    duration.as_secs_f64()
+                ),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Normalises buffered audio made of signed 16-bit samples, either raw
+    /// or in a WAV file. Other audio, and streamed audio whose loudest
+    /// sample is not known upfront, is returned unchanged.
+    pub(crate) fn normalize(
+        &self,
+        data: AudioSource,
+        normalization: Option<Normalization>,
+    ) -> AudioSource {
+        let (Some(target), AudioSource::Buffered(audio)) = (normalization, &data) else {
+            return data;
+        };
+        match self.encoding {
+            Encoding::Raw
+                if self.raw_encoding == Some(RawEncoding::SignedInteger)
+                    && self.bits == Some(16) =>
+            {
+                let big_endian = self.endian == Some(Endian::Big);
+                AudioSource::Buffered(normalize_pcm16(audio, big_endian, target))
+            }
+            Encoding::Wav => match parse_wav_header(audio) {
+                Ok(header) if header.format == WavSampleFormat::Pcm && header.bits == 16 => {
+                    let mut wav = BytesMut::from(&audio[..]);
+                    let samples =
+                        normalize_pcm16(&audio[header.data.clone()], header.big_endian, target);
+                    wav[header.data].copy_from_slice(&samples);
+                    AudioSource::Buffered(wav.freeze())
+                }
+                _ => data,
+            },
+            _ => data,
+        }
+    }
+
+    /// Reads the header of a WAV file, returning the raw parameters of its
+    /// samples along with the samples themselves.
+    ///
+    /// Only mono audio can be described as raw samples; other files are kept
+    /// whole as [`Encoding::Wav`] for Wit.ai to decode.
+    pub(crate) fn from_wav(wav: Bytes) -> Result<(Self, Bytes), ApiError> {
+        let header = parse_wav_header(&wav)?;
+        if header.channels != 1 {
+            return Ok((Self::new(Encoding::Wav), wav));
+        }
+        let bits = u8::try_from(header.bits)
+            .map_err(|_| WavError(format!("unsupported sample size of {} bits", header.bits)))?;
+        let raw_encoding = match header.format {
+            WavSampleFormat::Pcm if bits <= 8 => RawEncoding::UnsignedInteger,
+            WavSampleFormat::Pcm => RawEncoding::SignedInteger,
+            WavSampleFormat::Float => RawEncoding::FloatingPoint,
+            WavSampleFormat::ALaw => RawEncoding::ALaw,
+            WavSampleFormat::MuLaw => RawEncoding::MuLaw,
+        };
+        let format = Self {
+            raw_encoding: Some(raw_encoding),
+            bits: Some(bits),
+            sample_rate: Some(header.sample_rate),
+            endian: Some(if header.big_endian {
+                Endian::Big
+            } else {
+                Endian::Little
+            }),
+            ..Self::new(Encoding::Raw)
+        };
+        Ok((format, wav.slice(header.data)))
+    }
+}
+
+/// Where the samples of uncompressed audio lie within it and how fast they
+/// play, see [`AudioFormat::sample_layout`].
+struct SampleLayout {
+    data: std::ops::Range<usize>,
+    frame: usize,
+    frames_per_second: u32,
+    wav_big_endian: Option<bool>,
+}
+
+impl SampleLayout {
+    /// How long `frames` frames play for.
+    fn offset(&self, frames: usize) -> Duration {
+        Duration::from_secs_f64(frames as f64 / f64::from(self.frames_per_second))
+    }
+
+    /// Turns some of the samples of `audio` into audio of the same format.
+    fn piece(&self, audio: &[u8], samples: &[u8]) -> Bytes {
+        let Some(big_endian) = self.wav_big_endian else {
+            return Bytes::copy_from_slice(samples);
+        };
+        let header = &audio[..self.data.start];
+        let mut wav = BytesMut::with_capacity(header.len() + samples.len());
+        wav.extend_from_slice(header);
+        wav.extend_from_slice(samples);
+        let size = |size: usize| {
+            let size = size as u32;
+            if big_endian {
+                size.to_be_bytes()
+            } else {
+                size.to_le_bytes()
+            }
+        };
+        let total = wav.len();
+        wav[4..8].copy_from_slice(&size(total - 8));
+        wav[self.data.start - 4..self.data.start].copy_from_slice(&size(samples.len()));
+        wav.freeze()
+    }
+}
+
+/// The sample rates accepted for μ-law audio, telephony's 8kHz and wideband 16kHz.
+const ULAW_SAMPLE_RATES: [u32; 2] = [8_000, 16_000];
+
+/// Formats the `Content-Type` header value. Raw audio missing any of its
+/// parameters, or μ-law audio at an unsupported rate, cannot be described and
+/// yields [`fmt::Error`].
+impl Display for AudioFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(content_type) = &self.content_type_override {
+            return f.write_str(content_type);
+        }
+        match self.encoding {
+            Encoding::Wav => f.write_str("audio/wav"),
+            Encoding::Mp3 => f.write_str("audio/mpeg3"),
+            Encoding::Ogg => f.write_str("audio/ogg"),
+            Encoding::OggOpus => f.write_str("audio/ogg;codecs=opus"),
+            Encoding::Flac => f.write_str("audio/flac"),
+            Encoding::Ulaw => match self.sample_rate {
+                None => f.write_str("audio/ulaw"),
+                Some(rate) if ULAW_SAMPLE_RATES.contains(&rate) => {
+                    write!(f, "audio/ulaw;rate={rate}")
+                }
+                Some(_) => Err(fmt::Error),
+            },
+            Encoding::Raw => {
+                let (Some(raw_encoding), Some(bits), Some(rate), Some(endian)) =
+                    (self.raw_encoding, self.bits, self.sample_rate, self.endian)
+                else {
+                    return Err(fmt::Error);
+                };
+                write!(
+                    f,
+                    "audio/raw;encoding={raw_encoding};bits={bits};rate={rate};endian={endian}"
+                )
+            }
+        }
+    }
+}
+
+/// A stream of audio chunks, for sources such as a live microphone.
+pub type AudioStream = Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>>;
+
+/// The audio sent to Wit.ai, either all at once or as it is produced.
+pub enum AudioSource {
+    /// Audio that is already fully available.
+    Buffered(Bytes),
+    /// Audio that is streamed while it is being produced.
+    Stream(AudioStream),
+}
+
+impl fmt::Debug for AudioSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioSource::Buffered(data) => f.debug_tuple("Buffered").field(&data.len()).finish(),
+            AudioSource::Stream(_) => f.write_str("Stream"),
+        }
+    }
+}
+
+impl AudioSource {
+    /// Counts the bytes of this audio into `counters` as they are sent.
+    pub(crate) fn counted(self, counters: Arc<StreamCounters>) -> Self {
+        match self {
+            AudioSource::Buffered(data) => {
+                counters.count_upload(data.len());
+                AudioSource::Buffered(data)
+            }
+            AudioSource::Stream(stream) => AudioSource::Stream(
+                stream
+                    .inspect(move |chunk| {
+                        if let Ok(chunk) = chunk {
+                            counters.count_upload(chunk.len());
+                        }
+                    })
+                    .boxed(),
+            ),
+        }
+    }
+
+    /// Converts PCM audio to mono 16kHz signed 16-bit little-endian samples
+    /// as it is sent, see [`resample`](crate::utils::audio::resample).
+    ///
+    /// Send the result as [`Encoding::Raw`] audio with
+    /// [`RawEncoding::SignedInteger`], 16 bits, 16000 Hz and [`Endian::Little`].
+    #[cfg(feature = "audio-dsp")]
+    pub fn resampled(self, sample: PcmSample, channels: u16, sample_rate: u32) -> Self {
+        match self {
+            AudioSource::Buffered(data) => {
+                AudioSource::Buffered(resample(&data, sample, channels, sample_rate))
+            }
+            AudioSource::Stream(stream) => {
+                let resampler = Resampler::new(sample, channels, sample_rate);
+                let chunks = stream::unfold(Some((stream, resampler)), |state| async move {
+                    let (mut stream, mut resampler) = state?;
+                    match stream.next().await {
+                        Some(Ok(chunk)) => {
+                            let output = resampler.push(&chunk).freeze();
+                            Some((Ok(output), Some((stream, resampler))))
+                        }
+                        Some(Err(e)) => Some((Err(e), None)),
+                        None => Some((Ok(resampler.finish().freeze()), None)),
+                    }
+                });
+                AudioSource::Stream(chunks.boxed())
+            }
+        }
+    }
+
+    /// Compresses the audio as it is sent, when `compression` is set,
+    /// returning the `Content-Encoding` to send it with.
+    #[cfg(feature = "compression")]
+    pub(crate) fn compressed(
+        self,
+        compression: Option<Compression>,
+    ) -> Result<(Self, Option<&'static str>), ApiError> {
+        let Some(compression) = compression else {
+            return Ok((self, None));
+        };
+        let data = match self {
+            AudioSource::Buffered(data) => AudioSource::Buffered(compression.compress(&data)?),
+            AudioSource::Stream(stream) => {
+                let compressor = Compressor::new(compression);
+                let chunks = stream::unfold(Some((stream, compressor)), |state| async move {
+                    let (mut stream, mut compressor) = state?;
+                    match stream.next().await {
+                        Some(Ok(chunk)) => match compressor.push(&chunk) {
+                            Ok(output) => Some((Ok(output), Some((stream, compressor)))),
+                            Err(e) => Some((Err(e), None)),
+                        },
+                        Some(Err(e)) => Some((Err(e), None)),
+                        None => Some((compressor.finish(), None)),
+                    }
+                });
+                AudioSource::Stream(chunks.boxed())
+            }
+        };
+        Ok((data, Some(compression.content_encoding())))
+    }
+
+    /// Makes a streamed upload stoppable through the returned handle.
+    ///
+    /// Once stopped, the audio stream is dropped and the upload fails with
+    /// [`std::io::ErrorKind::ConnectionAborted`] rather than ending cleanly, so
+    /// Wit.ai does not process a truncated utterance. Buffered audio is sent in
+    /// one go and cannot be stopped.
+    pub(crate) fn abortable(self) -> (Self, Option<AbortHandle>) {
+        match self {
+            AudioSource::Buffered(data) => (AudioSource::Buffered(data), None),
+            AudioSource::Stream(stream) => {
+                let (handle, registration) = AbortHandle::new_pair();
+                let aborted = handle.clone();
+                let stream = Abortable::new(stream, registration).chain(
+                    stream::once(async move { aborted.is_aborted() }).filter_map(|aborted| {
+                        future::ready(aborted.then(|| {
+                            Err(std::io::Error::new(
+                                std::io::ErrorKind::ConnectionAborted,
+                                "the stream was dropped or cancelled",
+                            ))
+                        }))
+                    }),
+                );
+                (AudioSource::Stream(stream.boxed()), Some(handle))
+            }
+        }
+    }
+}
+
+impl From<Bytes> for AudioSource {
+    fn from(value: Bytes) -> Self {
+        AudioSource::Buffered(value)
+    }
+}
+
+impl From<Vec<u8>> for AudioSource {
+    fn from(value: Vec<u8>) -> Self {
+        AudioSource::Buffered(value.into())
+    }
+}
+
+impl From<AudioSource> for Body {
+    fn from(value: AudioSource) -> Self {
+        match value {
+            AudioSource::Buffered(data) => Body::from(data),
+            AudioSource::Stream(stream) => Body::wrap_stream(stream),
+        }
+    }
+}
+
+/// A request to the `/speech` endpoint.
+#[derive(Debug)]
+pub struct SpeechQuery {
+    pub(crate) format: AudioFormat,
+    pub(crate) data: AudioSource,
+    pub(crate) n: Option<u8>,
+    pub(crate) tag: Option<String>,
+    pub(crate) context: Option<Context>,
+    pub(crate) locale: Option<Locale>,
+    pub(crate) dynamic_entities: Option<DynamicEntities>,
+    pub(crate) entities_limit: usize,
+    pub(crate) chunking: Chunking,
+    pub(crate) vad: VadConfig,
+    pub(crate) request_id: Option<String>,
+    pub(crate) stop_after_final_understanding: bool,
+    pub(crate) normalization: Option<Normalization>,
+    #[cfg(feature = "compression")]
+    pub(crate) compression: Option<Compression>,
+    pub(crate) confidence: ConfidenceFilter,
+}
+
+impl SpeechQuery {
+    /// Creates a query sending `data`, encoded as `encoding`.
+    pub fn new(encoding: Encoding, data: impl Into<AudioSource>) -> Self {
+        Self {
+            format: AudioFormat::new(encoding),
+            data: data.into(),
+            n: None,
+            tag: None,
+            context: None,
+            locale: None,
+            dynamic_entities: None,
+            entities_limit: DYNAMIC_ENTITIES_MAX_BYTES,
+            chunking: Chunking::default(),
+            vad: VadConfig::default(),
+            request_id: None,
+            stop_after_final_understanding: false,
+            normalization: None,
+            #[cfg(feature = "compression")]
+            compression: None,
+            confidence: ConfidenceFilter::default(),
+        }
+    }
+
+    /// Creates a query sending the samples of a WAV file as raw audio, with
+    /// the raw parameters read from its header.
+    ///
+    /// Multi-channel files are sent whole as [`Encoding::Wav`] instead. Fails
+    /// with [`ApiError::InvalidWav`] when the header is malformed or describes
+    /// samples Wit.ai cannot decode.
+    pub fn from_wav_bytes(data: impl Into<Bytes>) -> Result<Self, ApiError> {
+        let (format, samples) = AudioFormat::from_wav(data.into())?;
+        Ok(Self {
+            format,
+            ..Self::new(Encoding::Raw, samples)
+        })
+    }
+
+    /// Describes headerless samples, required with [`Encoding::Raw`].
+    pub fn with_raw_params(
+        mut self,
+        raw_encoding: RawEncoding,
+        bits: u8,
+        sample_rate: u32,
+        endian: Endian,
+    ) -> Self {
+        self.format.raw_encoding = Some(raw_encoding);
+        self.format.bits = Some(bits);
+        self.format.sample_rate = Some(sample_rate);
+        self.format.endian = Some(endian);
+        self
+    }
+
+    /// Sets the sample rate of [`Encoding::Ulaw`] audio, 8000 or 16000 Hz.
+    ///
+    /// Without it Wit.ai assumes 8kHz. μ-law samples are single bytes, so
+    /// there is no endianness to set. Any other rate makes the query fail
+    /// with [`ApiError::Validation`], from [`SpeechQuery::validate`] or when it
+    /// is sent.
+    pub fn with_ulaw_rate(mut self, sample_rate: u32) -> Self {
+        self.format.sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// Sends `content_type` verbatim as the `Content-Type` header, ignoring the
+    /// encoding parameters. The audio itself is sent unchanged.
+    pub fn with_content_type_override(mut self, content_type: String) -> Self {
+        self.format.content_type_override = Some(content_type);
+        self
+    }
+
+    /// Scales quiet audio to the given loudness before uploading it.
+    ///
+    /// Only buffered audio made of signed 16-bit samples, raw or in a WAV
+    /// file, is normalised; anything else is sent unchanged.
+    pub fn with_normalization(mut self, target: Normalization) -> Self {
+        self.normalization = Some(target);
+        self
+    }
+
+    /// Compresses the audio before uploading it, see
+    /// [`Compression`](crate::utils::compression::Compression). Streamed
+    /// audio is compressed chunk by chunk as it is sent.
+    #[cfg(feature = "compression")]
+    pub fn with_transfer_encoding(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Drops understandings, partial or final, whose top intent is less
+    /// confident than `min_confidence`. Understandings without any intent
+    /// count as having no confidence at all.
+    pub fn with_min_confidence(mut self, min_confidence: f32) -> Self {
+        self.confidence.min = Some(min_confidence);
+        self
+    }
+
+    /// Reports the understandings dropped by [`Self::with_min_confidence`] as
+    /// [`SpeechResponse::LowConfidence`] events instead of dropping them.
+    pub fn with_low_confidence_events(mut self) -> Self {
+        self.confidence.report = true;
+        self
+    }
+
+    /// Sets the maximum number of intents returned.
+    pub fn with_n(mut self, n: u8) -> Self {
+        self.n = Some(n);
+        self
+    }
+
+    /// Targets a specific tagged version of the app.
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    /// Sends information about the user to help resolve the query.
+    pub fn with_context(mut self, context: Context) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// Pins the language the audio is recognised in, such as `fr_FR`,
+    /// instead of relying on the app's default.
+    ///
+    /// It is sent as the locale of the context, overriding the one set with
+    /// `with_context`.
+    pub fn with_locale(mut self, locale: Locale) -> Self {
+        self.locale = Some(locale);
+        self
+    }
+
+    /// Extends the app's keywords entities for this query only.
+    pub fn with_dynamic_entities(mut self, entities: impl Into<DynamicEntities>) -> Self {
+        self.dynamic_entities = Some(entities.into());
+        self
+    }
+
+    /// Fails the query with [`ApiError::DynamicEntitiesTooLarge`] when its
+    /// dynamic entities take more than `bytes` once serialised, instead of
+    /// the default [`DYNAMIC_ENTITIES_MAX_BYTES`].
+    pub fn with_max_dynamic_entities_bytes(mut self, bytes: usize) -> Self {
+        self.entities_limit = bytes;
+        self
+    }
+
+    /// Ends the upload and the event stream as soon as the first final
+    /// understanding arrives, for interactions expecting a single command.
+    ///
+    /// Buffered audio is sent in one go and cannot be cut short, unless it
+    /// is split with [`SpeechQuery::with_chunk_strategy`].
+    pub fn with_stop_after_final_understanding(mut self, stop: bool) -> Self {
+        self.stop_after_final_understanding = stop;
+        self
+    }
+
+    /// Sends `request_id` as the `X-Request-Id` header of this query,
+    /// overriding the one set on the client.
+    pub fn with_request_id(mut self, request_id: &str) -> Self {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+
+    /// Tunes how Wit.ai detects the end of speech.
+    pub fn with_vad(mut self, vad: VadConfig) -> Self {
+        self.vad = vad;
+        self
+    }
+
+    /// Splits buffered audio into chunks while uploading it. Streamed audio
+    /// keeps the chunks it is produced in. Blocking calls ignore the strategy.
+    pub fn with_chunk_strategy(mut self, strategy: ChunkStrategy) -> Self {
+        self.chunking.strategy = Some(strategy);
+        self
+    }
+
+    /// Calls `hook` with the size and timing of every chunk uploaded under the
+    /// chunk strategy.
+    pub fn with_chunk_metrics<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&ChunkMetrics) + Send + Sync + 'static,
+    {
+        self.chunking.hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// A copy of the query sending `data` instead of its own audio.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn with_data(&self, data: AudioSource) -> Self {
+        Self {
+            format: self.format.clone(),
+            data,
+            n: self.n,
+            tag: self.tag.clone(),
+            context: self.context.clone(),
+            locale: self.locale.clone(),
+            dynamic_entities: self.dynamic_entities.clone(),
+            entities_limit: self.entities_limit,
+            chunking: self.chunking.clone(),
+            vad: self.vad,
+            request_id: self.request_id.clone(),
+            stop_after_final_understanding: self.stop_after_final_understanding,
+            normalization: self.normalization,
+            #[cfg(feature = "compression")]
+            compression: self.compression,
+            confidence: self.confidence,
+        }
+    }
+
+    /// Checks the audio of the query, as is done before sending it, failing
+    /// with [`ApiError::Validation`] when its parameters are incomplete and
+    /// with [`ApiError::AudioTooLong`] when it is estimated to last longer
+    /// than [`SPEECH_MAX_DURATION`].
+    pub fn validate(&self) -> Result<(), ApiError> {
+        self.format.validate()?;
+        self.format.check_duration(&self.data, SPEECH_MAX_DURATION)
+    }
+
+    /// How long the audio of the query lasts, estimated from its size and
+    /// parameters, see [`SpeechQuery::validate`].
+    ///
+    /// Only buffered raw, μ-law and WAV audio can be estimated.
+    pub fn estimated_duration(&self) -> Option<Duration> {
+        self.format.estimated_duration(&self.data)
+    }
+
+    /// The URL of the `/speech` endpoint with the parameters of this query.
+    pub fn to_url(&self) -> Result<Url, ApiError> {
+        let params = QueryParams::new()
+            .with_opt(Param::N, self.n)
+            .with_opt(Param::Tag, self.tag.as_ref())
+            .with_json(
+                Param::Context,
+                Context::pinned(self.context.as_ref(), self.locale.as_ref()).as_ref(),
+            )?
+            .with_entities(self.dynamic_entities.as_ref(), self.entities_limit)?;
+        self.vad.apply(params).to_url("speech")
+    }
+}
+
+/// Formats the `Content-Type` header value of the query.
+impl Display for SpeechQuery {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.format.fmt(f)
+    }
+}
+
+/// A single recognised word.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct Token {
+    /// The recognised word.
+    pub token: String,
+    /// When the word starts, in milliseconds from the start of the audio.
+    #[serde(default)]
+    pub start: u64,
+    /// When the word ends, in milliseconds from the start of the audio.
+    #[serde(default)]
+    pub end: u64,
+    /// How confident the recogniser is about this word.
+    #[serde(default)]
+    pub confidence: f32,
+}
+
+impl Token {
+    /// When the word starts, from the start of the audio.
+    pub fn start_time(&self) -> Duration {
+        Duration::from_millis(self.start)
+    }
+
+    /// When the word ends, from the start of the audio.
+    pub fn end_time(&self) -> Duration {
+        Duration::from_millis(self.end)
+    }
+
+    /// How long the word was spoken for.
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.end.saturating_sub(self.start))
+    }
+}
+
+/// Details about the recognised speech.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct Speech {
+    /// How confident the recogniser is about the whole transcription.
+    pub confidence: f32,
+    /// The recognised words.
+    #[serde(default)]
+    pub tokens: Vec<Token>,
+}
+
+impl Speech {
+    /// The time between the start of the first word and the end of the last one.
+    ///
+    /// Returns `None` when no words were recognised.
+    pub fn duration(&self) -> Option<Duration> {
+        let start = self.tokens.iter().map(|t| t.start).min()?;
+        let end = self.tokens.iter().map(|t| t.end).max()?;
+        Some(Duration::from_millis(end.saturating_sub(start)))
+    }
+}
+
+/// A transcription of the audio received so far.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct Transcription {
+    /// The transcribed text.
+    pub text: String,
+    /// Details about the recognised speech.
+    pub speech: Option<Speech>,
+    /// The locale the recogniser used for this event, when Wit.ai reports it.
+    #[serde(default)]
+    pub locale: Option<Locale>,
+}
+
+/// The understanding of the audio received so far.
+///
+/// Like [`Message`](crate::model::message::Message), its [`Debug`] output is a
+/// summary unless the alternate form (`{:#?}`) is used.
+#[derive(Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct SpeechUnderstanding {
+    /// The transcribed text.
+    pub text: String,
+    /// The intents matching the text, most confident first.
+    #[serde(default)]
+    pub intents: Vec<Intent>,
+    /// The entities found in the text, keyed by `name:role`.
+    #[serde(default)]
+    pub entities: HashMap<String, Vec<Entity>>,
+    /// The traits of the text, keyed by trait name.
+    #[serde(default)]
+    pub traits: HashMap<String, Vec<Trait>>,
+    /// Details about the recognised speech.
+    pub speech: Option<Speech>,
+    /// The locale the recogniser used for this event, when Wit.ai reports it.
+    #[serde(default)]
+    pub locale: Option<Locale>,
+    /// The fields Wit.ai sent that this model does not cover yet, as they
+    /// were received.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl EntityLookup for SpeechUnderstanding {
+    fn entity_map(&self) -> &HashMap<String, Vec<Entity>> {
+        &self.entities
+    }
+}
+
+impl TraitLookup for SpeechUnderstanding {
+    fn trait_map(&self) -> &HashMap<String, Vec<Trait>> {
+        &self.traits
+    }
+}
+
+impl fmt::Debug for SpeechUnderstanding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return f
+                .debug_struct("SpeechUnderstanding")
+                .field("text", &self.text)
+                .field("intents", &self.intents)
+                .field("entities", &self.entities)
+                .field("traits", &self.traits)
+                .field("speech", &self.speech)
+                .field("locale", &self.locale)
+                .field("extra", &self.extra)
+                .finish();
+        }
+        f.debug_struct("SpeechUnderstanding")
+            .field("text", &self.text)
+            .field("top_intent", &self.intents.first().map(|i| &i.name))
+            .field("intents", &self.intents.len())
+            .field("entities", &count_values(&self.entities))
+            .field("traits", &count_values(&self.traits))
+            .field("locale", &self.locale)
+            .finish()
+    }
+}
+
+/// An event streamed back by the `/speech` endpoint.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SpeechResponse {
+    /// A transcription that may still change.
+    PartialTranscription(Transcription),
+    /// The final transcription of the audio.
+    FinalTranscription(Transcription),
+    /// An understanding that may still change.
+    PartialUnderstanding(SpeechUnderstanding),
+    /// The final understanding of the audio.
+    FinalUnderstanding(SpeechUnderstanding),
+    /// An understanding whose top intent is less confident than the minimum
+    /// set with [`SpeechQuery::with_min_confidence`], reported in its place
+    /// when [`SpeechQuery::with_low_confidence_events`] is set. Never sent by
+    /// Wit.ai itself, but tagged `LOW_CONFIDENCE` so that it round-trips like
+    /// the other events.
+    LowConfidence {
+        /// The understanding that was held back.
+        understanding: SpeechUnderstanding,
+        /// Whether it was the final understanding.
+        is_final: bool,
+    },
+}
+
+impl SpeechResponse {
+    /// The transcribed text carried by the event.
+    pub fn text(&self) -> &str {
+        match self {
+            SpeechResponse::PartialTranscription(t) | SpeechResponse::FinalTranscription(t) => {
+                &t.text
+            }
+            SpeechResponse::PartialUnderstanding(u)
+            | SpeechResponse::FinalUnderstanding(u)
+            | SpeechResponse::LowConfidence {
+                understanding: u, ..
+            } => &u.text,
+        }
+    }
+
+    /// Whether the event is final.
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            SpeechResponse::FinalTranscription(_)
+                | SpeechResponse::FinalUnderstanding(_)
+                | SpeechResponse::LowConfidence { is_final: true, .. }
+        )
+    }
+
+    /// Details about the recognised speech, when Wit.ai sent them.
+    pub fn speech(&self) -> Option<&Speech> {
+        match self {
+            SpeechResponse::PartialTranscription(t) | SpeechResponse::FinalTranscription(t) => {
+                t.speech.as_ref()
+            }
+            SpeechResponse::PartialUnderstanding(u)
+            | SpeechResponse::FinalUnderstanding(u)
+            | SpeechResponse::LowConfidence {
+                understanding: u, ..
+            } => u.speech.as_ref(),
+        }
+    }
+
+    /// The locale the recogniser used for this event, `None` when Wit.ai did not say.
+    pub fn locale(&self) -> Option<&Locale> {
+        match self {
+            SpeechResponse::PartialTranscription(t) | SpeechResponse::FinalTranscription(t) => {
+                t.locale.as_ref()
+            }
+            SpeechResponse::PartialUnderstanding(u)
+            | SpeechResponse::FinalUnderstanding(u)
+            | SpeechResponse::LowConfidence {
+                understanding: u, ..
+            } => u.locale.as_ref(),
+        }
+    }
+}
+
+/// A change of recognition locale between two streamed events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocaleSwitch {
+    /// The locale reported before the switch.
+    pub from: Locale,
+    /// The locale reported from now on.
+    pub to: Locale,
+}
+
+/// Follows the locale reported by successive streamed events.
+///
+/// Events that do not report a locale are assumed to keep the current one.
+#[derive(Debug, Clone, Default)]
+pub struct LocaleTracker {
+    current: Option<Locale>,
+}
+
+impl LocaleTracker {
+    /// Creates a tracker that has not seen any locale yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The last locale reported, if any.
+    pub fn current(&self) -> Option<&Locale> {
+        self.current.as_ref()
+    }
+
+    /// Records the locale of an event, returning the switch if it differs from the previous one.
+    pub fn observe(&mut self, locale: Option<&Locale>) -> Option<LocaleSwitch> {
+        let locale = locale?;
+        let previous = self.current.replace(locale.clone());
+        match previous {
+            Some(from) if from != *locale => Some(LocaleSwitch {
+                from,
+                to: locale.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl WitClient {
+    /// Streams audio to the `/speech` endpoint, yielding transcriptions and
+    /// understandings as Wit.ai produces them.
+    pub async fn post_speech(&self, mut query: SpeechQuery) -> Result<SpeechStream, ApiError> {
+        if let Some(context) = self.query_context(query.context.as_ref()) {
+            query.context = Some(context);
+        }
+        query.validate()?;
+        let mut content_type = String::new();
+        fmt::write(&mut content_type, format_args!("{query}"))?;
+
+        let url = query.to_url()?;
+        let established = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let data = query.format.normalize(query.data, query.normalization);
+        let mut data = query.chunking.apply(data, established.clone());
+        if query.stop_after_final_understanding
+            && let AudioSource::Stream(stream) = data
+        {
+            let stopped = stopped.clone();
+            data = AudioSource::Stream(
+                stream
+                    .take_while(move |_| future::ready(!stopped.load(Ordering::Relaxed)))
+                    .boxed(),
+            );
+        }
+
+        #[cfg(feature = "compression")]
+        let (data, content_encoding) = data.compressed(query.compression)?;
+        let (data, upload) = data.abortable();
+
+        let counters = Arc::new(StreamCounters::default());
+        let request = query_request_id(self.prepare_post_request(url), query.request_id.as_ref())
+            .header(CONTENT_TYPE, &content_type);
+        #[cfg(feature = "compression")]
+        let request = match content_encoding {
+            Some(encoding) => request.header(CONTENT_ENCODING, encoding),
+            None => request,
+        };
+        let request = request.body(Body::from(data.counted(counters.clone())));
+        let response = self
+            .send(request)
+            .await
+            .map_err(|e| e.for_audio(&content_type, SPEECH_MAX_DURATION))?;
+        let body = {
+            let counters = counters.clone();
+            response
+                .bytes_stream()
+                .inspect(move |chunk| counters.count_download(chunk))
+                .boxed()
+        };
+        let events = {
+            let counters = counters.clone();
+            json_stream(body, parse_event).inspect(move |event| {
+                established.store(true, Ordering::Relaxed);
+                counters.count_event(event);
+            })
+        };
+        let events = if query.stop_after_final_understanding {
+            until_final_understanding(events.boxed(), stopped).boxed()
+        } else {
+            events.boxed()
+        };
+        let confidence = query.confidence;
+        let events = events.filter_map(move |event| future::ready(confidence.apply(event)));
+        Ok(SpeechStream::new(events, counters).with_upload(upload))
+    }
+}
+
+/// Holds back understandings that are not confident enough.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ConfidenceFilter {
+    min: Option<f32>,
+    report: bool,
+}
+
+impl ConfidenceFilter {
+    /// Passes `event` through, drops it or turns it into a
+    /// [`SpeechResponse::LowConfidence`] event.
+    pub(crate) fn apply(
+        self,
+        event: Result<SpeechResponse, ApiError>,
+    ) -> Option<Result<SpeechResponse, ApiError>> {
+        let Some(min) = self.min else {
+            return Some(event);
+        };
+        let (understanding, is_final) = match event {
+            Ok(SpeechResponse::PartialUnderstanding(u)) => (u, false),
+            Ok(SpeechResponse::FinalUnderstanding(u)) => (u, true),
+            other => return Some(other),
+        };
+        let confidence = understanding.intents.first().map_or(0.0, |i| i.confidence);
+        Some(Ok(match (confidence >= min, is_final) {
+            (true, false) => SpeechResponse::PartialUnderstanding(understanding),
+            (true, true) => SpeechResponse::FinalUnderstanding(understanding),
+            (false, _) if self.report => SpeechResponse::LowConfidence {
+                understanding,
+                is_final,
+            },
+            (false, _) => return None,
+        }))
+    }
+}
+
+/// Ends `events` right after the first final understanding, raising `stopped`
+/// so the upload ends too.
+fn until_final_understanding(
+    events: impl Stream<Item = Result<SpeechResponse, ApiError>> + Unpin,
+    stopped: Arc<AtomicBool>,
+) -> impl Stream<Item = Result<SpeechResponse, ApiError>> {
+    stream::unfold(Some(events), move |events| {
+        let stopped = stopped.clone();
+        async move {
+            let mut events = events?;
+            let event = events.next().await?;
+            if let Ok(SpeechResponse::FinalUnderstanding(_)) = event {
+                stopped.store(true, Ordering::Relaxed);
+                return Some((event, None));
+            }
+            Some((event, Some(events)))
+        }
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::model::entities::{DynamicEntity, Keyword};
+
+    fn query() -> SpeechQuery {
+        SpeechQuery::new(Encoding::Wav, Bytes::from_static(b"RIFF"))
+    }
+
+    /// The value of a query string parameter, decoded.
+    fn param(url: &Url, name: &str) -> Option<String> {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+
+    #[test]
+    fn dynamic_entities_are_keyed_by_role() {
+        let query = query().with_dynamic_entities(vec![
+            DynamicEntity::new("contact")
+                .with_role("callee")
+                .with_keyword(Keyword::new("Alice", &["Alice", "Ali"])),
+            DynamicEntity::new("color").with_keyword(Keyword::new("blue", &[])),
+        ]);
+        let url = query.to_url().unwrap();
+        assert_eq!(
+            param(&url, "entities").unwrap(),
+            r#"{"contact:callee":[{"keyword":"Alice","synonyms":["Alice","Ali"]}],"color":[{"keyword":"blue","synonyms":[]}]}"#
+        );
+        assert_eq!(
+            url.query(),
+            Some(concat!(
+                "entities=%7B%22contact%3Acallee%22%3A%5B%7B%22keyword%22%3A%22Alice%22%2C",
+                "%22synonyms%22%3A%5B%22Alice%22%2C%22Ali%22%5D%7D%5D%2C%22color%22%3A%5B",
+                "%7B%22keyword%22%3A%22blue%22%2C%22synonyms%22%3A%5B%5D%7D%5D%7D"
+            ))
+        );
+    }
+
+    #[test]
+    fn context_is_encoded_once_in_whole_seconds() {
+        let reference_time =
+            chrono::DateTime::parse_from_rfc3339("2024-03-04T09:30:00.123+01:00").unwrap();
+        let context = Context::new()
+            .with_reference_time(reference_time)
+            .with_timezone("Europe/Paris")
+            .with_coords(48.5, 2.25);
+        let url = query().with_context(context).to_url().unwrap();
+        assert_eq!(
+            url.as_str(),
+            concat!(
+                "https://api.wit.ai/speech?context=%7B%22reference_time%22%3A%222024-03-04T09%3A30%3A00%2B01%3A00%22",
+                "%2C%22timezone%22%3A%22Europe%2FParis%22%2C%22coords%22%3A%7B%22lat%22%3A48.5%2C%22long%22%3A2.25%7D%7D"
+            )
+        );
+        assert_eq!(
+            param(&url, "context").unwrap(),
+            r#"{"reference_time":"2024-03-04T09:30:00+01:00","timezone":"Europe/Paris","coords":{"lat":48.5,"long":2.25}}"#
+        );
+    }
+
+    #[test]
+    fn pinned_locales_override_the_context() {
+        let context = Context::new().with_locale("en_GB".parse().unwrap());
+        let query = query()
+            .with_context(context)
+            .with_locale("fr_FR".parse().unwrap());
+        let url = query.to_url().unwrap();
+        assert_eq!(param(&url, "context").unwrap(), r#"{"locale":"fr_FR"}"#);
+    }
+
+    #[test]
+    fn ulaw_content_types_carry_the_rate() {
+        let ulaw = || SpeechQuery::new(Encoding::Ulaw, Bytes::new());
+        assert_eq!(ulaw().to_string(), "audio/ulaw");
+        assert_eq!(
+            ulaw().with_ulaw_rate(8_000).to_string(),
+            "audio/ulaw;rate=8000"
+        );
+        assert_eq!(
+            ulaw().with_ulaw_rate(16_000).to_string(),
+            "audio/ulaw;rate=16000"
+        );
+        assert!(matches!(
+            ulaw().with_ulaw_rate(11_025).validate(),
+            Err(ApiError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn reported_locales_are_typed() {
+        let event = |text: &str, locale: &str| -> Transcription {
+            let payload = format!(r#"{{"text":"{text}","locale":"{locale}"}}"#);
+            serde_json::from_str(&payload).unwrap()
+        };
+        let english = event("hello", "en_US");
+        assert_eq!(english.locale, Some("en-us".parse().unwrap()));
+
+        let mut tracker = LocaleTracker::new();
+        let events = [english, event("hi", "en_US"), event("bonjour", "fr_FR")];
+        let switches: Vec<_> = events
+            .into_iter()
+            .filter_map(|event| {
+                let event = SpeechResponse::PartialTranscription(event);
+                tracker.observe(event.locale())
+            })
+            .collect();
+        assert_eq!(switches.len(), 1);
+        assert_eq!(switches[0].from, "en_US");
+        assert_eq!(switches[0].to, "fr_FR");
+        assert_eq!(tracker.current().unwrap().language(), "fr");
+    }
+
+    /// The kinds of speech events, as the values of their `type` field.
+    #[cfg(feature = "test-util")]
+    pub(crate) fn kinds(events: &[SpeechResponse]) -> Vec<&'static str> {
+        events
+            .iter()
+            .map(|event| match event {
+                SpeechResponse::PartialTranscription(_) => "PARTIAL_TRANSCRIPTION",
+                SpeechResponse::FinalTranscription(_) => "FINAL_TRANSCRIPTION",
+                SpeechResponse::PartialUnderstanding(_) => "PARTIAL_UNDERSTANDING",
+                SpeechResponse::FinalUnderstanding(_) => "FINAL_UNDERSTANDING",
+                SpeechResponse::LowConfidence { .. } => "LOW_CONFIDENCE",
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn unconfident_understandings_are_held_back() {
+        use futures::TryStreamExt;
+
+        use crate::test_util::{MockWit, payloads};
+
+        let mock = MockWit::start().await;
+        mock.mock_speech(&payloads::speech_events()).await;
+        let client = mock.client();
+
+        let dropped = client
+            .post_speech(query().with_min_confidence(0.999))
+            .await
+            .unwrap();
+        let dropped: Vec<_> = dropped.try_collect().await.unwrap();
+        assert_eq!(
+            kinds(&dropped),
+            ["PARTIAL_TRANSCRIPTION", "FINAL_TRANSCRIPTION"]
+        );
+
+        let reported = client
+            .post_speech(
+                query()
+                    .with_min_confidence(0.999)
+                    .with_low_confidence_events(),
+            )
+            .await
+            .unwrap();
+        let reported: Vec<_> = reported.try_collect().await.unwrap();
+        assert_eq!(kinds(&reported)[2..], ["LOW_CONFIDENCE", "LOW_CONFIDENCE"]);
+        assert!(matches!(
+            reported[3],
+            SpeechResponse::LowConfidence { is_final: true, .. }
+        ));
+    }
+
+    #[test]
+    fn durations_are_estimated_from_uncompressed_audio() {
+        let raw = SpeechQuery::new(Encoding::Raw, Bytes::from(vec![0; 64_000])).with_raw_params(
+            RawEncoding::SignedInteger,
+            16,
+            16_000,
+            Endian::Little,
+        );
+        assert_eq!(raw.estimated_duration(), Some(Duration::from_secs(2)));
+
+        let ulaw = SpeechQuery::new(Encoding::Ulaw, Bytes::from(vec![0; 4_000]));
+        assert_eq!(ulaw.estimated_duration(), Some(Duration::from_millis(500)));
+        let ulaw = ulaw.with_ulaw_rate(16_000);
+        assert_eq!(ulaw.estimated_duration(), Some(Duration::from_millis(250)));
+
+        // A second of 8kHz stereo 16-bit samples, the header not counted.
+        let samples = Bytes::from(vec![0; 32_000]);
+        let wav = crate::utils::audio::pcm_to_wav(&samples, 8_000, 16, 2);
+        let wav = SpeechQuery::new(Encoding::Wav, wav);
+        assert_eq!(wav.estimated_duration(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn compressed_streamed_or_overridden_audio_is_not_estimated() {
+        let data = || Bytes::from(vec![0; 1_000]);
+        assert_eq!(
+            SpeechQuery::new(Encoding::Mp3, data()).estimated_duration(),
+            None
+        );
+        let streamed = AudioSource::Stream(futures::stream::empty().boxed());
+        assert_eq!(
+            SpeechQuery::new(Encoding::Ulaw, streamed).estimated_duration(),
+            None
+        );
+        let overridden = SpeechQuery::new(Encoding::Ulaw, data())
+            .with_content_type_override("audio/basic".to_string());
+        assert_eq!(overridden.estimated_duration(), None);
+        let unparsed = SpeechQuery::new(Encoding::Raw, data());
+        assert_eq!(unparsed.estimated_duration(), None);
+    }
+
+    #[test]
+    fn overlong_audio_is_rejected_before_upload() {
+        // 21 seconds of 8kHz μ-law.
+        let long = || Bytes::from(vec![0; 8_000 * 21]);
+        let error = SpeechQuery::new(Encoding::Ulaw, long())
+            .validate()
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ApiError::AudioTooLong { max_duration, .. } if max_duration == SPEECH_MAX_DURATION
+        ));
+        let dictation = crate::model::dictation::DictationQuery::new(Encoding::Ulaw, long());
+        assert!(dictation.validate().is_ok());
+        assert!(
+            SpeechQuery::new(Encoding::Ulaw, Bytes::from(vec![0; 8_000 * 20]))
+                .validate()
+                .is_ok()
+        );
+    }
+
+    /// The offsets and lengths, in milliseconds, of the pieces 8kHz μ-law
+    /// audio lasting `audio` is split into.
+    fn pieces(audio: Duration, segment_len: Duration, overlap: Duration) -> Vec<(u128, u128)> {
+        let samples = Bytes::from(vec![0; (audio.as_millis() * 8) as usize]);
+        let format = AudioFormat::new(Encoding::Ulaw);
+        format
+            .split(&AudioSource::Buffered(samples), segment_len, overlap)
+            .unwrap()
+            .into_iter()
+            .map(|(offset, piece)| (offset.as_millis(), piece.len() as u128 / 8))
+            .collect()
+    }
+
+    #[test]
+    fn audio_is_split_into_overlapping_pieces() {
+        let secs = Duration::from_secs;
+        assert_eq!(
+            pieces(secs(10), secs(4), secs(1)),
+            [(0, 4_000), (3_000, 4_000), (6_000, 4_000)]
+        );
+        assert_eq!(
+            pieces(secs(9), secs(4), secs(1)),
+            [(0, 4_000), (3_000, 4_000), (6_000, 3_000)]
+        );
+        assert_eq!(pieces(secs(2), secs(4), secs(1)), [(0, 2_000)]);
+    }
+
+    #[test]
+    fn wav_pieces_are_valid_files() {
+        let samples = Bytes::from(vec![0; 16_000 * 2 * 3]);
+        let wav = crate::utils::audio::pcm_to_wav(&samples, 16_000, 16, 1);
+        let pieces = AudioFormat::new(Encoding::Wav)
+            .split(
+                &AudioSource::Buffered(wav),
+                Duration::from_secs(2),
+                Duration::from_millis(500),
+            )
+            .unwrap();
+        assert_eq!(pieces.len(), 2);
+        for (offset, piece) in pieces {
+            let header = parse_wav_header(&piece).unwrap();
+            assert_eq!(header.data.end, piece.len());
+            let expected = if offset.is_zero() { 64_000 } else { 48_000 };
+            assert_eq!(header.data.len(), expected);
+        }
+    }
+
+    #[test]
+    fn unsplittable_audio_is_rejected() {
+        let format = AudioFormat::new(Encoding::Ulaw);
+        let audio = AudioSource::Buffered(Bytes::from(vec![0; 8_000]));
+        let second = Duration::from_secs(1);
+        assert!(matches!(
+            format.split(&audio, second, second),
+            Err(ApiError::Validation(_))
+        ));
+        let mp3 = AudioFormat::new(Encoding::Mp3);
+        assert!(matches!(
+            mp3.split(&audio, second * 2, second),
+            Err(ApiError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn opus_and_flac_content_types() {
+        let content_type = |encoding| SpeechQuery::new(encoding, Bytes::new()).to_string();
+        assert_eq!(content_type(Encoding::OggOpus), "audio/ogg;codecs=opus");
+        assert_eq!(content_type(Encoding::Flac), "audio/flac");
+        assert_eq!(content_type(Encoding::Ogg), "audio/ogg");
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn opus_and_flac_are_posted_as_is() {
+        use futures::TryStreamExt;
+
+        use crate::test_util::{MockWit, payloads};
+
+        let mock = MockWit::start().await;
+        mock.mock_speech(&payloads::speech_events()).await;
+        let client = mock.client();
+        let audio = [
+            (
+                Encoding::OggOpus,
+                &b"OggS\0\x02opus"[..],
+                "audio/ogg;codecs=opus",
+            ),
+            (Encoding::Flac, &b"fLaC\0\0\0\x22"[..], "audio/flac"),
+        ];
+        for (encoding, data, _) in audio {
+            let query = SpeechQuery::new(encoding, Bytes::from_static(data));
+            let events: Vec<_> = client
+                .post_speech(query)
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(events.len(), 4);
+        }
+
+        let requests = mock.server().received_requests().await.unwrap();
+        assert_eq!(requests.len(), audio.len());
+        for (request, (_, data, content_type)) in requests.iter().zip(audio) {
+            assert_eq!(request.body, data);
+            assert_eq!(request.headers["content-type"], content_type);
+        }
+    }
+
+    #[cfg(feature = "serde-serialize")]
+    #[test]
+    fn speech_responses_round_trip() {
+        use serde_json::json;
+
+        let speech = json!({
+            "confidence": 0.875,
+            "tokens": [{ "token": "hi", "start": 10, "end": 250, "confidence": 0.75 }]
+        });
+        let payloads = [
+            json!({ "type": "PARTIAL_TRANSCRIPTION", "text": "h", "speech": speech }),
+            json!({ "type": "FINAL_TRANSCRIPTION", "text": "hi", "speech": speech }),
+            json!({
+                "type": "FINAL_UNDERSTANDING",
+                "text": "hi",
+                "intents": [{ "id": "1", "name": "greet", "confidence": 0.95 }],
+                "entities": {},
+                "traits": {},
+                "speech": speech,
+                "is_final": true
+            }),
+        ];
+        for payload in payloads {
+            let response: SpeechResponse = serde_json::from_value(payload.clone()).unwrap();
+            let encoded = serde_json::to_value(&response).unwrap();
+            assert_eq!(encoded["type"], payload["type"]);
+            assert_eq!(encoded["speech"], payload["speech"]);
+            let decoded: SpeechResponse = serde_json::from_value(encoded.clone()).unwrap();
+            assert_eq!(serde_json::to_value(&decoded).unwrap(), encoded);
+        }
+    }
+
+    #[cfg(feature = "serde-serialize")]
+    #[test]
+    fn low_confidence_events_round_trip() {
+        use serde_json::json;
+
+        let understanding = json!({
+            "text": "hi",
+            "intents": [{ "id": "1", "name": "greet", "confidence": 0.25 }],
+            "entities": {},
+            "traits": {},
+            "is_final": true
+        });
+        let event = SpeechResponse::LowConfidence {
+            understanding: serde_json::from_value(understanding).unwrap(),
+            is_final: true,
+        };
+        let encoded = serde_json::to_value(&event).unwrap();
+        assert_eq!(encoded["type"], "LOW_CONFIDENCE");
+        assert_eq!(encoded["is_final"], true);
+        let decoded: SpeechResponse = serde_json::from_value(encoded.clone()).unwrap();
+        assert!(matches!(
+            decoded,
+            SpeechResponse::LowConfidence { is_final: true, .. }
+        ));
+        assert_eq!(decoded.text(), "hi");
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), encoded);
+    }
+}
@@ -0,0 +1,214 @@
+//! A builder for the SSML accepted by the `/synthesize` endpoint.
+
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+/// How strongly a piece of text is emphasised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmphasisLevel {
+    /// Spoken louder and slower than the surrounding text.
+    Strong,
+    /// Spoken somewhat louder and slower, the default emphasis.
+    Moderate,
+    /// Spoken softer and faster, to de-emphasise it.
+    Reduced,
+}
+
+impl Display for EmphasisLevel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EmphasisLevel::Strong => "strong",
+            EmphasisLevel::Moderate => "moderate",
+            EmphasisLevel::Reduced => "reduced",
+        })
+    }
+}
+
+/// The phonetic alphabet of a `<phoneme>` pronunciation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhoneticAlphabet {
+    /// The International Phonetic Alphabet, such as `təˈmɑːtəʊ`.
+    Ipa,
+    /// X-SAMPA, the ASCII transcription of the IPA, such as `t@"mA:t@U`.
+    XSampa,
+}
+
+impl Display for PhoneticAlphabet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PhoneticAlphabet::Ipa => "ipa",
+            PhoneticAlphabet::XSampa => "x-sampa",
+        })
+    }
+}
+
+/// The attributes of a `<prosody>` element, each a percentage of the voice's default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Prosody {
+    /// The speaking rate, `50` for half as fast.
+    pub rate: Option<u16>,
+    /// The pitch, `120` to speak a fifth higher.
+    pub pitch: Option<u16>,
+    /// The volume, `200` for twice as loud.
+    pub volume: Option<u16>,
+}
+
+/// A piece of SSML.
+///
+/// Text added through the builder is escaped and elements are always closed,
+/// so the rendered document is well-formed. Render it with [`Ssml::render`] or
+/// pass it to [`SynthesizeQuery::with_ssml`](crate::model::synthesize::SynthesizeQuery::with_ssml).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ssml {
+    body: String,
+}
+
+impl Ssml {
+    /// Creates an empty piece of SSML.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends plain text.
+    pub fn text(mut self, text: &str) -> Self {
+        self.body.push_str(&escape(text));
+        self
+    }
+
+    /// Appends emphasised content.
+    pub fn emphasis(self, level: EmphasisLevel, content: impl Into<Ssml>) -> Self {
+        self.element(&format!("emphasis level=\"{level}\""), "emphasis", content)
+    }
+
+    /// Appends a pause, rounded to the millisecond.
+    pub fn pause(mut self, duration: Duration) -> Self {
+        self.body
+            .push_str(&format!("<break time=\"{}ms\"/>", duration.as_millis()));
+        self
+    }
+
+    /// Appends content spoken with a different rate, pitch or volume.
+    pub fn prosody(self, prosody: Prosody, content: impl Into<Ssml>) -> Self {
+        let mut tag = String::from("prosody");
+        for (name, value) in [
+            ("rate", prosody.rate),
+            ("pitch", prosody.pitch),
+            ("volume", prosody.volume),
+        ] {
+            if let Some(value) = value {
+                tag.push_str(&format!(" {name}=\"{value}%\""));
+            }
+        }
+        self.element(&tag, "prosody", content)
+    }
+
+    /// Appends text to be read as a given type, such as `date` or `characters`.
+    pub fn say_as(self, interpret_as: &str, text: &str) -> Self {
+        let tag = format!("say-as interpret-as=\"{}\"", escape(interpret_as));
+        self.element(&tag, "say-as", text)
+    }
+
+    /// Appends text pronounced as the given phonetic transcription.
+    pub fn phoneme(self, alphabet: PhoneticAlphabet, ph: &str, text: &str) -> Self {
+        let tag = format!("phoneme alphabet=\"{alphabet}\" ph=\"{}\"", escape(ph));
+        self.element(&tag, "phoneme", text)
+    }
+
+    /// Renders the complete `<speak>` document.
+    pub fn render(&self) -> String {
+        format!("<speak>{}</speak>", self.body)
+    }
+
+    fn element(mut self, open: &str, close: &str, content: impl Into<Ssml>) -> Self {
+        self.body
+            .push_str(&format!("<{open}>{}</{close}>", content.into().body));
+        self
+    }
+}
+
+impl From<&str> for Ssml {
+    fn from(value: &str) -> Self {
+        Ssml::new().text(value)
+    }
+}
+
+impl Display for Ssml {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render())
+    }
+}
+
+/// Escapes the characters that have a meaning in XML.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markup_characters_are_escaped() {
+        assert_eq!(
+            escape(r#"Tom & "Jerry" <'s>"#),
+            "Tom &amp; &quot;Jerry&quot; &lt;&apos;s&gt;"
+        );
+        assert_eq!(escape("plain text"), "plain text");
+        assert_eq!(Ssml::from("1 < 2").render(), "<speak>1 &lt; 2</speak>");
+    }
+
+    #[test]
+    fn attributes_are_escaped() {
+        let ssml =
+            Ssml::new()
+                .say_as("a\"b", "x")
+                .phoneme(PhoneticAlphabet::XSampa, "t@\"mA:", "y");
+        assert_eq!(
+            ssml.render(),
+            "<speak><say-as interpret-as=\"a&quot;b\">x</say-as>\
+             <phoneme alphabet=\"x-sampa\" ph=\"t@&quot;mA:\">y</phoneme></speak>"
+        );
+    }
+
+    #[test]
+    fn nested_elements_are_closed_in_order() {
+        let inner = Ssml::new()
+            .text("really ")
+            .emphasis(EmphasisLevel::Strong, "very");
+        let ssml = Ssml::new()
+            .text("It is ")
+            .prosody(
+                Prosody {
+                    rate: Some(80),
+                    volume: Some(150),
+                    ..Prosody::default()
+                },
+                Ssml::new().emphasis(EmphasisLevel::Reduced, inner),
+            )
+            .pause(Duration::from_millis(250))
+            .text(" hot.");
+        assert_eq!(
+            ssml.render(),
+            "<speak>It is <prosody rate=\"80%\" volume=\"150%\">\
+             <emphasis level=\"reduced\">really <emphasis level=\"strong\">very</emphasis></emphasis>\
+             </prosody><break time=\"250ms\"/> hot.</speak>"
+        );
+    }
+
+    #[test]
+    fn empty_prosody_has_no_attributes() {
+        let ssml = Ssml::new().prosody(Prosody::default(), "same");
+        assert_eq!(ssml.render(), "<speak><prosody>same</prosody></speak>");
+    }
+}
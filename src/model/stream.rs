@@ -0,0 +1,340 @@
+//! The streams returned by the streaming audio endpoints.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+#[cfg(feature = "tokio")]
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::stream::{AbortHandle, Abortable};
+use futures::{Stream, StreamExt};
+
+use crate::error::ApiError;
+use crate::model::dictation::Dictation;
+use crate::model::speech::{SpeechResponse, SpeechUnderstanding};
+use crate::model::transcript::Transcript;
+use crate::model::transcription::TranscriptionEvent;
+
+type BoxedStream<T> = Pin<Box<dyn Stream<Item = Result<T, ApiError>> + Send>>;
+
+/// A stream of events from one of the streaming audio endpoints.
+///
+/// Dropping the stream, or calling [`WitStream::close`], drops the underlying
+/// HTTP connection right away. To stop it from elsewhere, for instance from the
+/// task capturing the microphone, use a [`CancelHandle`].
+///
+/// When the audio is streamed, dropping or cancelling also stops the upload:
+/// the audio stream is dropped, releasing its buffers, the next time the
+/// connection polls it, and the request fails instead of ending as if the
+/// audio were complete. This holds when the task owning the stream is
+/// cancelled too.
+pub struct WitStream<T> {
+    inner: BoxedStream<T>,
+    abort: Aborts,
+    counters: Arc<StreamCounters>,
+    _upload_guard: Option<AbortOnDrop>,
+}
+
+/// The handles stopping both directions of a stream.
+#[derive(Debug, Clone)]
+struct Aborts {
+    response: AbortHandle,
+    upload: Option<AbortHandle>,
+}
+
+impl Aborts {
+    fn abort(&self) {
+        self.response.abort();
+        if let Some(upload) = &self.upload {
+            upload.abort();
+        }
+    }
+}
+
+/// Stops an upload when dropped.
+struct AbortOnDrop(AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// The events streamed by [`WitClient::post_speech`](crate::model::client::WitClient::post_speech).
+pub type SpeechStream = WitStream<SpeechResponse>;
+
+/// The events streamed by [`WitClient::post_dictation`](crate::model::client::WitClient::post_dictation).
+pub type DictationStream = WitStream<Dictation>;
+
+impl<T: Send + 'static> WitStream<T> {
+    /// Wraps a stream whose traffic is being counted into `counters`.
+    pub(crate) fn new(
+        stream: impl Stream<Item = Result<T, ApiError>> + Send + 'static,
+        counters: Arc<StreamCounters>,
+    ) -> Self {
+        let (response, registration) = AbortHandle::new_pair();
+        Self {
+            inner: Abortable::new(stream, registration).boxed(),
+            abort: Aborts {
+                response,
+                upload: None,
+            },
+            counters,
+            _upload_guard: None,
+        }
+    }
+
+    /// Ties the upload stopped by `upload` to this stream, so that dropping
+    /// or cancelling the stream stops it.
+    pub(crate) fn with_upload(mut self, upload: Option<AbortHandle>) -> Self {
+        self._upload_guard = upload.clone().map(AbortOnDrop);
+        self.abort.upload = upload;
+        self
+    }
+
+    /// Ends the stream with [`ApiError::Timeout`] when no event arrives for `timeout`.
+    #[cfg(feature = "tokio")]
+    pub fn with_inactivity_timeout(self, timeout: Duration) -> Self {
+        let inner = futures::stream::unfold(Some(self.inner), move |inner| async move {
+            let mut inner = inner?;
+            match tokio::time::timeout(timeout, inner.next()).await {
+                Ok(Some(item)) => Some((item, Some(inner))),
+                Ok(None) => None,
+                Err(_) => Some((Err(ApiError::Timeout(timeout)), None)),
+            }
+        });
+        Self {
+            inner: inner.boxed(),
+            abort: self.abort,
+            counters: self.counters,
+            _upload_guard: self._upload_guard,
+        }
+    }
+}
+
+impl<T> WitStream<T> {
+    /// Returns a handle that ends this stream when cancelled.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle(self.abort.clone())
+    }
+
+    /// What went through the stream so far.
+    pub fn stats(&self) -> StreamStats {
+        self.counters.snapshot()
+    }
+
+    /// Returns a handle reading the stats of this stream, which stays usable
+    /// once the stream is consumed or dropped.
+    pub fn stats_handle(&self) -> StatsHandle {
+        StatsHandle(self.counters.clone())
+    }
+
+    /// Ends the stream, stops the upload and drops the HTTP connection.
+    pub fn close(self) {
+        self.abort.abort();
+    }
+}
+
+impl SpeechStream {
+    /// Keeps only the transcriptions of the stream, as [`Dictation`] events.
+    ///
+    /// Understanding events are dropped, so code written against
+    /// [`DictationStream`] works unchanged against the `/speech` endpoint.
+    pub fn transcriptions(self) -> DictationStream {
+        let inner = self.inner.filter_map(|event| async move {
+            match event {
+                Ok(SpeechResponse::PartialTranscription(t)) => Some(Ok(Dictation {
+                    text: t.text,
+                    is_final: false,
+                    speech: t.speech,
+                    locale: t.locale,
+                    alternatives: Vec::new(),
+                })),
+                Ok(SpeechResponse::FinalTranscription(t)) => Some(Ok(Dictation {
+                    text: t.text,
+                    is_final: true,
+                    speech: t.speech,
+                    locale: t.locale,
+                    alternatives: Vec::new(),
+                })),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            }
+        });
+        WitStream {
+            inner: inner.boxed(),
+            abort: self.abort,
+            counters: self.counters,
+            _upload_guard: self._upload_guard,
+        }
+    }
+}
+
+/// What a whole `/speech` stream produced, see
+/// [`SpeechStreamExt::collect_final`].
+#[derive(Debug, Clone, Default)]
+pub struct SpeechOutcome {
+    /// The final transcriptions of the stream.
+    pub transcript: Transcript,
+    /// The final understanding or, when the stream ended without one, the
+    /// last partial understanding, see [`SpeechOutcome::is_final`].
+    pub understanding: Option<SpeechUnderstanding>,
+    /// Whether [`SpeechOutcome::understanding`] is the final understanding.
+    pub is_final: bool,
+    /// How many partial transcriptions and understandings were received.
+    pub partials_count: usize,
+}
+
+/// Collecting a `/speech` stream into its outcome, for callers that only
+/// want the final answer.
+pub trait SpeechStreamExt: Stream<Item = Result<SpeechResponse, ApiError>> + Unpin + Sized {
+    /// Reads the stream to its end, stopping at the first error.
+    ///
+    /// Wit.ai may close the stream without a final understanding, such as
+    /// when the audio ends mid-sentence. The last partial understanding is
+    /// then returned in its place, and [`SpeechOutcome::is_final`] is false.
+    fn collect_final(mut self) -> impl Future<Output = Result<SpeechOutcome, ApiError>> + Send
+    where
+        Self: Send,
+    {
+        async move {
+            let mut outcome = SpeechOutcome::default();
+            while let Some(event) = self.next().await {
+                let event = event?;
+                match &event {
+                    SpeechResponse::PartialTranscription(_) => outcome.partials_count += 1,
+                    SpeechResponse::FinalTranscription(_) => outcome.transcript.push(&event),
+                    SpeechResponse::PartialUnderstanding(understanding)
+                    | SpeechResponse::LowConfidence {
+                        understanding,
+                        is_final: false,
+                    } => {
+                        outcome.partials_count += 1;
+                        if !outcome.is_final {
+                            outcome.understanding = Some(understanding.clone());
+                        }
+                    }
+                    SpeechResponse::FinalUnderstanding(understanding)
+                    | SpeechResponse::LowConfidence {
+                        understanding,
+                        is_final: true,
+                    } => {
+                        outcome.understanding = Some(understanding.clone());
+                        outcome.is_final = true;
+                    }
+                }
+            }
+            Ok(outcome)
+        }
+    }
+}
+
+impl<S> SpeechStreamExt for S where S: Stream<Item = Result<SpeechResponse, ApiError>> + Unpin {}
+
+impl<T> Stream for WitStream<T> {
+    type Item = Result<T, ApiError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = self.inner.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Err(
+            ApiError::SerializationError(_) | ApiError::ProtocolError { .. },
+        ))) = &poll
+        {
+            self.counters.parse_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        poll
+    }
+}
+
+/// Ends a [`WitStream`] from anywhere, such as another task.
+///
+/// Once cancelled, the stream yields no more events and its HTTP connection
+/// is dropped the next time it is polled, or when it is dropped.
+#[derive(Debug, Clone)]
+pub struct CancelHandle(Aborts);
+
+impl CancelHandle {
+    /// Cancels the stream and stops its upload.
+    pub fn cancel(&self) {
+        self.0.abort();
+    }
+
+    /// Whether the stream was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.response.is_aborted()
+    }
+}
+
+/// The traffic of a [`WitStream`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamStats {
+    /// The partial transcriptions and understandings received.
+    pub partials: u64,
+    /// The final transcriptions and understandings received.
+    pub finals: u64,
+    /// The audio bytes handed to the connection.
+    pub bytes_uploaded: u64,
+    /// The response bytes received.
+    pub bytes_downloaded: u64,
+    /// The events that could not be deserialised.
+    pub parse_errors: u64,
+}
+
+/// Reads the [`StreamStats`] of a [`WitStream`] from anywhere, during or
+/// after its consumption.
+#[derive(Debug, Clone)]
+pub struct StatsHandle(Arc<StreamCounters>);
+
+impl StatsHandle {
+    /// What went through the stream so far.
+    pub fn stats(&self) -> StreamStats {
+        self.0.snapshot()
+    }
+}
+
+/// The live counters behind [`StreamStats`].
+#[derive(Debug, Default)]
+pub(crate) struct StreamCounters {
+    partials: AtomicU64,
+    finals: AtomicU64,
+    bytes_uploaded: AtomicU64,
+    bytes_downloaded: AtomicU64,
+    parse_errors: AtomicU64,
+}
+
+impl StreamCounters {
+    fn snapshot(&self) -> StreamStats {
+        StreamStats {
+            partials: self.partials.load(Ordering::Relaxed),
+            finals: self.finals.load(Ordering::Relaxed),
+            bytes_uploaded: self.bytes_uploaded.load(Ordering::Relaxed),
+            bytes_downloaded: self.bytes_downloaded.load(Ordering::Relaxed),
+            parse_errors: self.parse_errors.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn count_upload(&self, len: usize) {
+        self.bytes_uploaded.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_download<E>(&self, chunk: &Result<Bytes, E>) {
+        if let Ok(chunk) = chunk {
+            self.count_downloaded(chunk.len());
+        }
+    }
+
+    pub(crate) fn count_downloaded(&self, len: usize) {
+        self.bytes_downloaded
+            .fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_event<T: TranscriptionEvent>(&self, event: &Result<T, ApiError>) {
+        match event {
+            Ok(event) if event.is_final() => self.finals.fetch_add(1, Ordering::Relaxed),
+            Ok(_) => self.partials.fetch_add(1, Ordering::Relaxed),
+            Err(_) => return,
+        };
+    }
+}
@@ -0,0 +1,496 @@
+//! Text to speech through the `/synthesize` endpoint.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use bytes::Bytes;
+use futures::StreamExt;
+use reqwest::header::ACCEPT;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::error::ApiError;
+use crate::model::batch::BatchOutcome;
+use crate::model::client::{WitClient, query_request_id};
+use crate::model::ssml::Ssml;
+use crate::model::stream::{StreamCounters, WitStream};
+use crate::model::voice::VoiceName;
+use crate::utils::audio::pcm_to_wav;
+use crate::utils::json::{json_stream, parse_event};
+use crate::utils::storage::{BufferStorage, DiskStorage};
+
+/// The longest text `/synthesize` accepts, SSML tags excluded.
+pub const MAX_SYNTHESIZE_CHARACTERS: usize = 280;
+
+/// The audio format of the synthesised speech.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SynthesizeCodec {
+    /// Headerless signed 16-bit little-endian samples at 16kHz, mono.
+    Pcm,
+    /// An MP3 file.
+    #[default]
+    Mp3,
+    /// A WAV file.
+    Wav,
+}
+
+impl SynthesizeCodec {
+    /// The value of the `Accept` header requesting this codec.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            SynthesizeCodec::Pcm => "audio/pcm16",
+            SynthesizeCodec::Mp3 => "audio/mpeg",
+            SynthesizeCodec::Wav => "audio/wav",
+        }
+    }
+}
+
+/// A request to the `/synthesize` endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct SynthesizeQuery {
+    /// The text to speak, either plain or SSML.
+    #[serde(rename = "q")]
+    pub(crate) text: String,
+    pub(crate) voice: VoiceName,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) style: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) speed: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) pitch: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) gain: Option<u16>,
+    #[serde(skip)]
+    pub(crate) codec: SynthesizeCodec,
+    #[serde(skip)]
+    pub(crate) request_id: Option<String>,
+}
+
+impl SynthesizeQuery {
+    /// Creates a query speaking `text` with the given voice.
+    pub fn new(text: &str, voice: impl Into<VoiceName>) -> Self {
+        Self {
+            text: text.to_string(),
+            voice: voice.into(),
+            style: None,
+            speed: None,
+            pitch: None,
+            gain: None,
+            codec: SynthesizeCodec::default(),
+            request_id: None,
+        }
+    }
+
+    /// Creates a query speaking an SSML document with the given voice.
+    pub fn with_ssml(ssml: Ssml, voice: impl Into<VoiceName>) -> Self {
+        Self::new(&ssml.render(), voice)
+    }
+
+    /// Sets the style of the voice, such as `soft` or `formal`.
+    pub fn with_style(mut self, style: &str) -> Self {
+        self.style = Some(style.to_string());
+        self
+    }
+
+    /// Sets the speed of the voice, as a percentage of its default.
+    pub fn with_speed(mut self, speed: u16) -> Self {
+        self.speed = Some(speed);
+        self
+    }
+
+    /// Sets the pitch of the voice, as a percentage of its default.
+    pub fn with_pitch(mut self, pitch: u16) -> Self {
+        self.pitch = Some(pitch);
+        self
+    }
+
+    /// Sets the volume of the voice, as a percentage of its default.
+    pub fn with_gain(mut self, gain: u16) -> Self {
+        self.gain = Some(gain);
+        self
+    }
+
+    /// Sets the audio format of the synthesised speech.
+    pub fn with_codec(mut self, codec: SynthesizeCodec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Sends `request_id` as the `X-Request-Id` header of this query,
+    /// overriding the one set on the client.
+    pub fn with_request_id(mut self, request_id: &str) -> Self {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+
+    /// The number of characters counted against [`MAX_SYNTHESIZE_CHARACTERS`],
+    /// which excludes SSML tags.
+    pub fn character_count(&self) -> usize {
+        let mut in_tag = false;
+        self.text
+            .chars()
+            .filter(|&c| match c {
+                '<' => {
+                    in_tag = true;
+                    false
+                }
+                '>' => {
+                    in_tag = false;
+                    false
+                }
+                _ => !in_tag,
+            })
+            .count()
+    }
+}
+
+/// The sample rate of [`SynthesizeCodec::Pcm`] audio.
+pub const PCM_SAMPLE_RATE: u32 = 16_000;
+
+/// Synthesised audio kept in a [`BufferStorage`], so that phrases spoken
+/// again are not synthesised again, see [`WitClient::with_synthesis_cache`].
+///
+/// Entries are keyed by a hash of everything sent to `/synthesize`: the
+/// text, voice, style, speed, pitch, gain and codec. The hash is fixed, so
+/// entries stay valid across Rust releases. They never expire, so clear the
+/// storage after changing voices on Wit.ai's side.
+///
+/// Any [`BufferStorage`] can back the cache: it is the same extension point
+/// as the one used to buffer audio before upload, so a backend written for
+/// one, such as an encrypted one, serves the other as well.
+#[derive(Clone)]
+pub struct SynthesisCache {
+    storage: Arc<dyn BufferStorage>,
+}
+
+impl fmt::Debug for SynthesisCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SynthesisCache").finish_non_exhaustive()
+    }
+}
+
+impl SynthesisCache {
+    /// Caches audio in `storage`, such as an [`EncryptedStorage`](crate::utils::storage::EncryptedStorage)
+    /// or a backend of your own.
+    pub fn new(storage: impl BufferStorage + 'static) -> Self {
+        Self {
+            storage: Arc::new(storage),
+        }
+    }
+
+    /// Caches audio in files inside `directory`, creating it if needed.
+    pub fn on_disk(directory: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(DiskStorage::new(directory)?))
+    }
+
+    /// The storage key of the audio synthesised for `query`.
+    pub fn key(query: &SynthesizeQuery) -> String {
+        let mut hash = Fnv1a::default();
+        hash.write(serde_json::to_string(query).unwrap_or_default().as_bytes());
+        hash.write(&[0]);
+        hash.write(query.codec.mime_type().as_bytes());
+        format!("synthesize-{:032x}", hash.0)
+    }
+
+    /// Forgets the audio synthesised for `query`.
+    pub fn remove(&self, query: &SynthesizeQuery) -> io::Result<()> {
+        self.storage.remove(&Self::key(query))
+    }
+
+    /// The cached audio of `query`. A failing storage is logged and treated
+    /// as a miss, so that it never prevents synthesis.
+    pub(crate) fn load(&self, query: &SynthesizeQuery) -> Option<Bytes> {
+        match self.storage.load(&Self::key(query)) {
+            Ok(data) => data.map(Bytes::from),
+            Err(e) => {
+                tracing::warn!(error = %e, "could not read the synthesis cache");
+                None
+            }
+        }
+    }
+
+    /// Caches the audio of `query`, logging failures.
+    pub(crate) fn store(&self, query: &SynthesizeQuery, data: &Bytes) {
+        if let Err(e) = self.storage.store(&Self::key(query), data) {
+            tracing::warn!(error = %e, "could not write the synthesis cache");
+        }
+    }
+}
+
+/// The 128-bit FNV-1a hash, whose output never changes with the toolchain
+/// unlike [`std::hash::DefaultHasher`].
+struct Fnv1a(u128);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0x6c62_272e_07bb_0142_62b8_2175_6295_c58d)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u128::from(byte);
+            self.0 = self
+                .0
+                .wrapping_mul(0x0000_0000_0100_0000_0000_0000_0000_013b);
+        }
+    }
+}
+
+/// Synthesised speech, along with the codec it is encoded with.
+#[derive(Debug, Clone)]
+pub struct SynthesizedAudio {
+    codec: SynthesizeCodec,
+    data: Bytes,
+    samples: Option<Vec<i16>>,
+}
+
+impl SynthesizedAudio {
+    /// Wraps audio returned by the `/synthesize` endpoint.
+    pub fn new(codec: SynthesizeCodec, data: Bytes) -> Self {
+        let samples = (codec == SynthesizeCodec::Pcm).then(|| {
+            data.chunks_exact(2)
+                .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+                .collect()
+        });
+        Self {
+            codec,
+            data,
+            samples,
+        }
+    }
+
+    /// The codec the audio is encoded with.
+    pub fn codec(&self) -> SynthesizeCodec {
+        self.codec
+    }
+
+    /// The encoded audio, as returned by Wit.ai.
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+
+    /// The samples of [`SynthesizeCodec::Pcm`] audio, `None` for other codecs.
+    pub fn samples(&self) -> Option<&[i16]> {
+        self.samples.as_deref()
+    }
+
+    /// The audio as a WAV file, `None` for MP3 audio.
+    pub fn to_wav(&self) -> Option<Bytes> {
+        match self.codec {
+            SynthesizeCodec::Pcm => Some(pcm_to_wav(&self.data, PCM_SAMPLE_RATE, 16, 1)),
+            SynthesizeCodec::Wav => Some(self.data.clone()),
+            SynthesizeCodec::Mp3 => None,
+        }
+    }
+
+    /// Writes the audio to a WAV file.
+    ///
+    /// MP3 audio cannot be written as WAV and fails with [`io::ErrorKind::InvalidInput`].
+    pub fn write_wav(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let wav = self.to_wav().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "MP3 audio cannot be written as WAV",
+            )
+        })?;
+        fs::write(path, wav)
+    }
+}
+
+/// A chunk of synthesised audio, in the codec of the query.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct AudioChunk {
+    /// The audio data.
+    #[serde(deserialize_with = "deserialize_base64")]
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(serialize_with = "serialize_base64")
+    )]
+    pub data: Bytes,
+}
+
+/// When a word is spoken.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct WordEvent {
+    /// The word, as written in the query.
+    pub text: String,
+    /// When the word starts, in milliseconds from the start of the audio.
+    pub start: u64,
+    /// When the word ends, in milliseconds from the start of the audio.
+    pub end: u64,
+}
+
+/// When a phoneme is spoken.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct PhonemeEvent {
+    /// The phoneme, in IPA.
+    pub phoneme: String,
+    /// When the phoneme starts, in milliseconds from the start of the audio.
+    pub start: u64,
+    /// When the phoneme ends, in milliseconds from the start of the audio.
+    pub end: u64,
+}
+
+/// When the mouth takes a given shape, for lip-sync.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct VisemeEvent {
+    /// The identifier of the mouth shape.
+    pub viseme: String,
+    /// When the shape is reached, in milliseconds from the start of the audio.
+    pub time: u64,
+}
+
+/// An event streamed by [`WitClient::post_synthesize_with_events`].
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SynthesisEvent {
+    /// The next chunk of audio.
+    Audio(AudioChunk),
+    /// A word starts being spoken.
+    Word(WordEvent),
+    /// A phoneme starts being spoken.
+    Phoneme(PhonemeEvent),
+    /// The mouth reaches a new shape.
+    Viseme(VisemeEvent),
+}
+
+/// The stream returned by [`WitClient::post_synthesize_with_events`].
+pub type SynthesisStream = WitStream<SynthesisEvent>;
+
+fn deserialize_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    STANDARD
+        .decode(encoded)
+        .map(Bytes::from)
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(feature = "serde-serialize")]
+fn serialize_base64<S: serde::Serializer>(data: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD.encode(data))
+}
+
+impl WitClient {
+    /// Synthesises speech, returning the audio in the query's codec.
+    ///
+    /// With a [`SynthesisCache`], audio already synthesised for the same
+    /// query is returned without calling Wit.ai.
+    pub async fn post_synthesize(&self, query: &SynthesizeQuery) -> Result<Bytes, ApiError> {
+        if let Some(data) = self.synthesis_cache.as_ref().and_then(|c| c.load(query)) {
+            return Ok(data);
+        }
+        let request = query_request_id(
+            self.prepare_post_request(self.url("synthesize")),
+            query.request_id.as_ref(),
+        )
+        .header(ACCEPT, query.codec.mime_type())
+        .json(query);
+        let data = self.send(request).await?.bytes().await?;
+        if let Some(cache) = &self.synthesis_cache {
+            cache.store(query, &data);
+        }
+        Ok(data)
+    }
+
+    /// Synthesises several queries one after the other, carrying on when some
+    /// of them fail.
+    pub async fn post_synthesize_batch(
+        &self,
+        queries: &[SynthesizeQuery],
+    ) -> BatchOutcome<SynthesizedAudio> {
+        let mut outcome = BatchOutcome::new();
+        for (index, query) in queries.iter().enumerate() {
+            let result = self.post_synthesize(query).await;
+            outcome.push(
+                index,
+                result.map(|data| SynthesizedAudio::new(query.codec, data)),
+            );
+        }
+        outcome
+    }
+
+    /// Synthesises speech along with timing events, for lip-sync and captioning.
+    ///
+    /// The audio is interleaved with the events as [`SynthesisEvent::Audio`]
+    /// chunks. Only voices advertising the `word_events`, `phoneme_events` or
+    /// `viseme_events` features produce the matching events.
+    pub async fn post_synthesize_with_events(
+        &self,
+        query: &SynthesizeQuery,
+    ) -> Result<SynthesisStream, ApiError> {
+        let request = query_request_id(
+            self.prepare_post_request(self.url("synthesize")),
+            query.request_id.as_ref(),
+        )
+        .header(ACCEPT, "application/json")
+        .json(query);
+        let response = self.send(request).await?;
+        let counters = Arc::new(StreamCounters::default());
+        let body = {
+            let counters = counters.clone();
+            response
+                .bytes_stream()
+                .inspect(move |chunk| counters.count_download(chunk))
+                .boxed()
+        };
+        Ok(WitStream::new(json_stream(body, parse_event), counters))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_matches_reference_vectors() {
+        let hash = |data: &[u8]| {
+            let mut hash = Fnv1a::default();
+            hash.write(data);
+            hash.0
+        };
+        assert_eq!(hash(b""), 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d);
+        assert_eq!(hash(b"a"), 0xd228_cb69_6f1a_8caf_7891_2b70_4e4a_8964);
+    }
+
+    #[test]
+    fn cache_keys_are_stable_and_depend_on_every_field() {
+        let query = SynthesizeQuery::new("Hello", "wit$Rebecca");
+        let key = SynthesisCache::key(&query);
+        assert_eq!(key, SynthesisCache::key(&query.clone()));
+        assert_eq!(key.len(), "synthesize-".len() + 32);
+
+        let others = [
+            query.clone().with_codec(SynthesizeCodec::Wav),
+            query.clone().with_speed(120),
+            SynthesizeQuery::new("Hello!", "wit$Rebecca"),
+        ];
+        for other in others {
+            assert_ne!(SynthesisCache::key(&other), key);
+        }
+    }
+
+    #[cfg(feature = "serde-serialize")]
+    #[test]
+    fn audio_chunks_round_trip_as_base64() {
+        let payload = serde_json::json!({ "type": "AUDIO", "data": "AAEC/w==" });
+        let event: SynthesisEvent = serde_json::from_value(payload.clone()).unwrap();
+        match &event {
+            SynthesisEvent::Audio(chunk) => {
+                assert_eq!(chunk.data, Bytes::from_static(&[0, 1, 2, 255]))
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+        assert_eq!(serde_json::to_value(&event).unwrap(), payload);
+    }
+}
@@ -0,0 +1,138 @@
+//! Checking an access token before relying on it.
+
+use std::fmt::{self, Display, Formatter};
+
+use reqwest::{Method, StatusCode};
+
+use crate::error::{ApiError, WitErrorResponse};
+use crate::model::client::WitClient;
+use crate::model::params::{Param, QueryParams};
+use crate::model::server::ServerClient;
+
+/// The text understood to check a client token, short enough to be cheap.
+const PROBE_TEXT: &str = "ping";
+
+/// What Wit.ai thinks of an access token, see [`WitClient::verify_token`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenStatus {
+    /// The token is accepted for the endpoints of the client.
+    Valid,
+    /// The token is unknown, revoked or malformed.
+    Invalid,
+    /// The token is valid but cannot use the endpoints of the client, such
+    /// as a client access token given to a [`ServerClient`].
+    WrongTokenType,
+}
+
+impl TokenStatus {
+    /// Whether the token can be used.
+    pub fn is_valid(&self) -> bool {
+        *self == TokenStatus::Valid
+    }
+
+    /// Reads the status of a probe response, `None` when it says nothing
+    /// about the token.
+    fn from_status(status: StatusCode) -> Option<Self> {
+        match status {
+            status if status.is_success() => Some(TokenStatus::Valid),
+            StatusCode::UNAUTHORIZED => Some(TokenStatus::Invalid),
+            StatusCode::FORBIDDEN => Some(TokenStatus::WrongTokenType),
+            _ => None,
+        }
+    }
+}
+
+impl Display for TokenStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TokenStatus::Valid => "the access token is valid",
+            TokenStatus::Invalid => {
+                "the access token was rejected, check it was copied whole from the app settings"
+            }
+            TokenStatus::WrongTokenType => {
+                "the access token cannot be used here, management endpoints need a server access token"
+            }
+        })
+    }
+}
+
+impl WitClient {
+    /// Checks the client's token with a minimal `/message` request, so an
+    /// application can fail at startup rather than on its first real query.
+    ///
+    /// A rejected token is reported as a [`TokenStatus`], not an error.
+    /// Errors are kept for failures that say nothing about the token, such
+    /// as the network being down.
+    pub async fn verify_token(&self) -> Result<TokenStatus, ApiError> {
+        let url = QueryParams::new()
+            .with(Param::Q, PROBE_TEXT)
+            .to_url("message")?;
+        let response = self.prepare_get_request(url).send().await?;
+        self.quota.observe(response.headers());
+        match TokenStatus::from_status(response.status()) {
+            Some(status) => Ok(status),
+            None => {
+                let status = response.status();
+                Err(WitErrorResponse::into_error(status, response.text().await?))
+            }
+        }
+    }
+
+    /// Blocking version of [`WitClient::verify_token`].
+    #[cfg(feature = "blocking")]
+    pub fn verify_blocking_token(&self) -> Result<TokenStatus, ApiError> {
+        let url = QueryParams::new()
+            .with(Param::Q, PROBE_TEXT)
+            .to_url("message")?;
+        self.verify_blocking(url)
+    }
+
+    #[cfg(feature = "blocking")]
+    fn verify_blocking(&self, url: impl AsRef<str> + Send) -> Result<TokenStatus, ApiError> {
+        self.blocking(|client| {
+            let response = self
+                .prepare_blocking_request(client, Method::GET, url)
+                .send()?;
+            self.quota.observe(response.headers());
+            match TokenStatus::from_status(response.status()) {
+                Some(status) => Ok(status),
+                None => {
+                    let status = response.status();
+                    Err(WitErrorResponse::into_error(status, response.text()?))
+                }
+            }
+        })
+    }
+}
+
+impl ServerClient {
+    /// Checks that the client's token is a server access token with a
+    /// minimal request listing a single app.
+    ///
+    /// A client access token is reported as [`TokenStatus::WrongTokenType`].
+    pub async fn verify_token(&self) -> Result<TokenStatus, ApiError> {
+        let response = self
+            .client
+            .prepare_request(Method::GET, self.probe_url())
+            .send()
+            .await?;
+        self.client.quota.observe(response.headers());
+        match TokenStatus::from_status(response.status()) {
+            Some(status) => Ok(status),
+            None => {
+                let status = response.status();
+                Err(WitErrorResponse::into_error(status, response.text().await?))
+            }
+        }
+    }
+
+    /// Blocking version of [`ServerClient::verify_token`].
+    #[cfg(feature = "blocking")]
+    pub fn verify_blocking_token(&self) -> Result<TokenStatus, ApiError> {
+        self.client.verify_blocking(self.probe_url())
+    }
+
+    fn probe_url(&self) -> String {
+        self.client.url("apps?limit=1")
+    }
+}
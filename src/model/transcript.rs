@@ -0,0 +1,426 @@
+//! Complete transcripts assembled from the final events of a stream, and
+//! their export to formats understood by other speech tooling.
+
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::ApiError;
+use crate::model::locale::Locale;
+use crate::model::speech::Token;
+use crate::model::transcription::TranscriptionEvent;
+
+/// A finalised piece of a transcript, one per final event.
+#[derive(Debug, Clone)]
+pub struct TranscriptSegment {
+    /// The transcribed text.
+    pub text: String,
+    /// How confident the recogniser is about the segment, when reported.
+    pub confidence: Option<f32>,
+    /// The recognised words, empty when Wit.ai did not send them.
+    pub words: Vec<Token>,
+}
+
+impl TranscriptSegment {
+    /// Captures a final event.
+    pub fn from_event(event: &impl TranscriptionEvent) -> Self {
+        Self {
+            text: event.text().to_string(),
+            confidence: event.confidence(),
+            words: event.tokens().to_vec(),
+        }
+    }
+
+    /// When the first word starts, `None` when Wit.ai sent no timings.
+    pub fn start(&self) -> Option<Duration> {
+        let start = self.words.iter().map(|w| w.start).min()?;
+        Some(Duration::from_millis(start))
+    }
+
+    /// When the last word ends, `None` when Wit.ai sent no timings.
+    pub fn end(&self) -> Option<Duration> {
+        let end = self.words.iter().map(|w| w.end).max()?;
+        Some(Duration::from_millis(end))
+    }
+}
+
+/// The finalised text of a whole recording.
+#[derive(Debug, Clone, Default)]
+pub struct Transcript {
+    /// The segments, in the order they were finalised.
+    pub segments: Vec<TranscriptSegment>,
+    /// The locale the recogniser last reported, if any.
+    pub locale: Option<Locale>,
+}
+
+impl Transcript {
+    /// Creates an empty transcript.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an event, keeping it only when it is final.
+    pub fn push(&mut self, event: &impl TranscriptionEvent) {
+        if let Some(locale) = event.locale() {
+            self.locale = Some(locale.clone());
+        }
+        if event.is_final() && !event.text().is_empty() {
+            self.segments.push(TranscriptSegment::from_event(event));
+        }
+    }
+
+    /// The text of every segment, separated by spaces.
+    pub fn text(&self) -> String {
+        let texts: Vec<&str> = self.segments.iter().map(|s| s.text.as_str()).collect();
+        texts.join(" ")
+    }
+
+    /// When the last segment ends, `None` when Wit.ai sent no timings.
+    pub fn duration(&self) -> Option<Duration> {
+        self.segments
+            .iter()
+            .filter_map(TranscriptSegment::end)
+            .max()
+    }
+
+    /// Joins the transcripts of overlapping pieces of a recording, each given
+    /// with the offset it starts at, into the transcript of the whole.
+    ///
+    /// Word timings are shifted by the offset of their piece. Where two
+    /// pieces overlap by `overlap`, words are kept from the earlier piece up
+    /// to the middle of the overlap and from the later one after it, so
+    /// words heard twice are only kept once. Segments without word timings
+    /// cannot be placed and are kept whole.
+    pub fn stitch(pieces: Vec<(Duration, Transcript)>, overlap: Duration) -> Transcript {
+        let cut = |offset: Duration, first: bool| {
+            if first {
+                0
+            } else {
+                (offset + overlap / 2).as_millis() as u64
+            }
+        };
+        let cuts: Vec<u64> = pieces
+            .iter()
+            .enumerate()
+            .map(|(i, (offset, _))| cut(*offset, i == 0))
+            .collect();
+
+        let mut stitched = Transcript::new();
+        for (i, (offset, transcript)) in pieces.into_iter().enumerate() {
+            let shift = offset.as_millis() as u64;
+            let (from, until) = (cuts[i], cuts.get(i + 1).copied().unwrap_or(u64::MAX));
+            for mut segment in transcript.segments {
+                if segment.words.is_empty() {
+                    stitched.segments.push(segment);
+                    continue;
+                }
+                let heard = segment.words.len();
+                segment.words.retain_mut(|word| {
+                    word.start += shift;
+                    word.end += shift;
+                    (from..until).contains(&word.start)
+                });
+                if segment.words.is_empty() {
+                    continue;
+                }
+                if segment.words.len() < heard {
+                    let words: Vec<&str> = segment.words.iter().map(|w| w.token.as_str()).collect();
+                    segment.text = words.join(" ");
+                }
+                stitched.segments.push(segment);
+            }
+            if transcript.locale.is_some() {
+                stitched.locale = transcript.locale;
+            }
+        }
+        stitched
+    }
+
+    /// Exports the transcript in the `verbose_json` layout of Whisper, which
+    /// subtitle editors and diarization pipelines commonly accept.
+    ///
+    /// Times are in seconds. Segments without timings start and end where
+    /// the previous one ended. Each segment carries the recogniser's
+    /// `confidence` next to `avg_logprob`, its natural logarithm, and words
+    /// carry their confidence as `probability`. Whisper's token ids and
+    /// decoding statistics have no Wit.ai counterpart and are left out.
+    pub fn to_whisper_json(&self) -> Value {
+        let mut cursor = 0.0;
+        let segments = self
+            .segments
+            .iter()
+            .enumerate()
+            .map(|(id, segment)| {
+                let start = segment.start().map_or(cursor, |start| start.as_secs_f64());
+                let end = segment.end().map_or(start, |end| end.as_secs_f64());
+                cursor = end;
+                WhisperSegment {
+                    id,
+                    start,
+                    end,
+                    text: &segment.text,
+                    confidence: segment.confidence,
+                    avg_logprob: segment.confidence.map(|c| f64::from(c).ln()),
+                }
+            })
+            .collect();
+        let words = self
+            .segments
+            .iter()
+            .flat_map(|segment| &segment.words)
+            .map(|word| WhisperWord {
+                word: &word.token,
+                start: word.start as f64 / 1000.0,
+                end: word.end as f64 / 1000.0,
+                probability: word.confidence,
+            })
+            .collect();
+        let export = WhisperTranscript {
+            task: "transcribe",
+            language: self.locale.as_ref(),
+            duration: self.duration().map_or(cursor, |d| d.as_secs_f64()),
+            text: self.text(),
+            segments,
+            words,
+        };
+        serde_json::to_value(export).unwrap_or(Value::Null)
+    }
+}
+
+/// A rolling view over a speech or dictation stream, for captioning and
+/// assistants: the finalised segments so far plus the line still being
+/// recognised.
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptBuilder {
+    transcript: Transcript,
+    partial: Option<String>,
+}
+
+impl TranscriptBuilder {
+    /// Creates an empty builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an event. A final event closes the current line, a partial
+    /// one replaces it.
+    pub fn push(&mut self, event: &impl TranscriptionEvent) {
+        self.transcript.push(event);
+        self.partial =
+            (!event.is_final() && !event.text().is_empty()).then(|| event.text().to_string());
+    }
+
+    /// Records every event of a stream until it ends, stopping at the first
+    /// error.
+    pub async fn consume<E: TranscriptionEvent>(
+        &mut self,
+        mut events: impl Stream<Item = Result<E, ApiError>> + Unpin,
+    ) -> Result<(), ApiError> {
+        while let Some(event) = events.next().await {
+            self.push(&event?);
+        }
+        Ok(())
+    }
+
+    /// The line still being recognised, `None` right after a final event.
+    pub fn partial(&self) -> Option<&str> {
+        self.partial.as_deref()
+    }
+
+    /// The finalised segments so far.
+    pub fn segments(&self) -> &[TranscriptSegment] {
+        &self.transcript.segments
+    }
+
+    /// The finalised text followed by the line still being recognised, as a
+    /// caption would show it.
+    pub fn text(&self) -> String {
+        let mut text = self.transcript.text();
+        if let Some(partial) = &self.partial {
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(partial);
+        }
+        text
+    }
+
+    /// The confidence of the finalised segments, averaged with each segment
+    /// weighted by its length. `None` until a segment reports a confidence.
+    pub fn confidence(&self) -> Option<f32> {
+        let (weighted, length) = self
+            .segments()
+            .iter()
+            .filter_map(|s| Some((s.confidence?, s.text.chars().count() as f32)))
+            .fold((0.0, 0.0), |(weighted, length), (confidence, len)| {
+                (weighted + confidence * len, length + len)
+            });
+        (length > 0.0).then(|| weighted / length)
+    }
+
+    /// When the first finalised word starts, `None` without timings.
+    pub fn start(&self) -> Option<Duration> {
+        self.segments()
+            .iter()
+            .filter_map(TranscriptSegment::start)
+            .min()
+    }
+
+    /// When the last finalised word ends, `None` without timings.
+    pub fn end(&self) -> Option<Duration> {
+        self.transcript.duration()
+    }
+
+    /// The finalised transcript, once the stream has ended. A line that was
+    /// never finalised is left out.
+    pub fn final_transcript(self) -> Transcript {
+        self.transcript
+    }
+}
+
+impl<E: TranscriptionEvent> FromIterator<E> for Transcript {
+    fn from_iter<I: IntoIterator<Item = E>>(iter: I) -> Self {
+        let mut transcript = Self::new();
+        for event in iter {
+            transcript.push(&event);
+        }
+        transcript
+    }
+}
+
+#[derive(Serialize)]
+struct WhisperTranscript<'a> {
+    task: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<&'a Locale>,
+    duration: f64,
+    text: String,
+    segments: Vec<WhisperSegment<'a>>,
+    words: Vec<WhisperWord<'a>>,
+}
+
+#[derive(Serialize)]
+struct WhisperSegment<'a> {
+    id: usize,
+    start: f64,
+    end: f64,
+    text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    avg_logprob: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct WhisperWord<'a> {
+    word: &'a str,
+    start: f64,
+    end: f64,
+    probability: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A segment whose words start and end at the given milliseconds.
+    fn segment(words: &[(&str, u64, u64)]) -> TranscriptSegment {
+        let words: Vec<Token> = words
+            .iter()
+            .map(|(token, start, end)| Token {
+                token: token.to_string(),
+                start: *start,
+                end: *end,
+                confidence: 0.5,
+            })
+            .collect();
+        let texts: Vec<&str> = words.iter().map(|w| w.token.as_str()).collect();
+        TranscriptSegment {
+            text: texts.join(" "),
+            confidence: Some(0.5),
+            words,
+        }
+    }
+
+    fn transcript(segments: Vec<TranscriptSegment>) -> Transcript {
+        Transcript {
+            segments,
+            locale: None,
+        }
+    }
+
+    fn starts(transcript: &Transcript) -> Vec<u64> {
+        transcript
+            .segments
+            .iter()
+            .flat_map(|segment| segment.words.iter().map(|word| word.start))
+            .collect()
+    }
+
+    #[test]
+    fn words_heard_twice_are_kept_once() {
+        // Pieces of 4s overlapping by 1s: the cut falls at 3.5s.
+        let pieces = vec![
+            (
+                Duration::ZERO,
+                transcript(vec![segment(&[
+                    ("the", 2_800, 3_100),
+                    ("quick", 3_200, 3_450),
+                    ("brown", 3_600, 3_900),
+                ])]),
+            ),
+            (
+                Duration::from_secs(3),
+                transcript(vec![segment(&[
+                    ("quick", 200, 450),
+                    ("brown", 600, 900),
+                    ("fox", 1_000, 1_300),
+                ])]),
+            ),
+        ];
+        let stitched = Transcript::stitch(pieces, Duration::from_secs(1));
+        assert_eq!(stitched.text(), "the quick brown fox");
+        assert_eq!(starts(&stitched), [2_800, 3_200, 3_600, 4_000]);
+        assert_eq!(stitched.duration(), Some(Duration::from_millis(4_300)));
+    }
+
+    #[test]
+    fn segments_heard_only_in_the_overlap_are_dropped() {
+        let pieces = vec![
+            (
+                Duration::ZERO,
+                transcript(vec![segment(&[("one", 100, 400)])]),
+            ),
+            (
+                Duration::from_secs(3),
+                transcript(vec![
+                    segment(&[("echo", 100, 300)]),
+                    segment(&[("two", 800, 1_000)]),
+                ]),
+            ),
+        ];
+        let stitched = Transcript::stitch(pieces, Duration::from_secs(1));
+        assert_eq!(stitched.segments.len(), 2);
+        assert_eq!(stitched.text(), "one two");
+    }
+
+    #[test]
+    fn untimed_segments_and_locales_are_kept() {
+        let untimed = TranscriptSegment {
+            text: "no timings".to_string(),
+            confidence: None,
+            words: Vec::new(),
+        };
+        let mut later = transcript(vec![untimed]);
+        later.locale = Some("en_US".parse().unwrap());
+        let pieces = vec![
+            (Duration::ZERO, transcript(vec![segment(&[("hi", 0, 200)])])),
+            (Duration::from_secs(3), later),
+        ];
+        let stitched = Transcript::stitch(pieces, Duration::from_secs(1));
+        assert_eq!(stitched.text(), "hi no timings");
+        assert_eq!(stitched.locale, Some("en_US".parse().unwrap()));
+    }
+}
@@ -0,0 +1,78 @@
+//! A common view over the events streamed by `/speech` and `/dictation`.
+
+use crate::model::dictation::Dictation;
+use crate::model::locale::Locale;
+use crate::model::speech::{SpeechResponse, Token};
+
+/// The transcription carried by a streamed event, whichever endpoint it came from.
+///
+/// This lets code consuming transcriptions be written once for both
+/// [`WitClient::post_speech`](crate::model::client::WitClient::post_speech) and
+/// [`WitClient::post_dictation`](crate::model::client::WitClient::post_dictation).
+pub trait TranscriptionEvent {
+    /// The transcribed text.
+    fn text(&self) -> &str;
+
+    /// Whether the transcription is final.
+    fn is_final(&self) -> bool;
+
+    /// How confident the recogniser is about the transcription, when reported.
+    fn confidence(&self) -> Option<f32>;
+
+    /// The recognised words, empty when Wit.ai did not send them.
+    fn tokens(&self) -> &[Token];
+
+    /// The locale the recogniser used, when reported.
+    fn locale(&self) -> Option<&Locale> {
+        None
+    }
+}
+
+impl TranscriptionEvent for Dictation {
+    fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn is_final(&self) -> bool {
+        self.is_final
+    }
+
+    fn confidence(&self) -> Option<f32> {
+        self.speech.as_ref().map(|speech| speech.confidence)
+    }
+
+    fn tokens(&self) -> &[Token] {
+        self.speech
+            .as_ref()
+            .map(|speech| speech.tokens.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn locale(&self) -> Option<&Locale> {
+        self.locale.as_ref()
+    }
+}
+
+impl TranscriptionEvent for SpeechResponse {
+    fn text(&self) -> &str {
+        SpeechResponse::text(self)
+    }
+
+    fn is_final(&self) -> bool {
+        SpeechResponse::is_final(self)
+    }
+
+    fn confidence(&self) -> Option<f32> {
+        self.speech().map(|speech| speech.confidence)
+    }
+
+    fn tokens(&self) -> &[Token] {
+        self.speech()
+            .map(|speech| speech.tokens.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn locale(&self) -> Option<&Locale> {
+        SpeechResponse::locale(self)
+    }
+}
@@ -0,0 +1,50 @@
+//! End-of-speech detection settings of the streaming audio endpoints.
+
+use std::time::Duration;
+
+use crate::model::params::{Param, QueryParams};
+
+/// How Wit.ai decides that the user stopped speaking.
+///
+/// By default Wit.ai ends the utterance after a short silence, which cuts off
+/// push-to-talk apps whose users pause mid-sentence. Those should either
+/// disable detection, letting the end of the upload end the utterance, or
+/// lengthen the silence it waits for. The settings are sent as the `vad` and
+/// `vad_silence_ms` query parameters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VadConfig {
+    pub(crate) disabled: bool,
+    pub(crate) silence: Option<Duration>,
+}
+
+impl VadConfig {
+    /// Server-side detection with its default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// No server-side detection, the utterance ends with the upload.
+    pub fn disabled() -> Self {
+        Self {
+            disabled: true,
+            silence: None,
+        }
+    }
+
+    /// Sets how long a silence ends the utterance.
+    pub fn with_silence_duration(mut self, silence: Duration) -> Self {
+        self.silence = Some(silence);
+        self
+    }
+
+    /// Adds the parameters describing this configuration.
+    pub(crate) fn apply(&self, params: QueryParams) -> QueryParams {
+        if self.disabled {
+            return params.with(Param::Vad, "off");
+        }
+        params.with_opt(
+            Param::VadSilence,
+            self.silence.map(|silence| silence.as_millis()),
+        )
+    }
+}
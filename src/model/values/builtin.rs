@@ -0,0 +1,406 @@
+//! Typed resolution of the `wit$*` built-in entities.
+
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::model::builtin_names::*;
+use crate::model::message::Entity;
+use crate::model::values::datetime::WitDatetime;
+
+/// An amount of money and its currency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Money {
+    /// The amount, in units of `currency`.
+    pub amount: f64,
+    /// The currency, such as `EUR`, when Wit.ai could tell.
+    pub currency: Option<String>,
+}
+
+/// The scale of a temperature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemperatureUnit {
+    /// Degrees Celsius.
+    Celsius,
+    /// Degrees Fahrenheit.
+    Fahrenheit,
+    /// A temperature given in "degrees" without any scale.
+    Degree,
+}
+
+/// A temperature and its scale.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Temperature {
+    /// The temperature, in degrees of `unit`.
+    pub value: f64,
+    /// The scale of the temperature, when Wit.ai could tell.
+    pub unit: Option<TemperatureUnit>,
+}
+
+/// A measured value, such as a distance or a volume, and its unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measure {
+    /// The measured value, in units of `unit`.
+    pub value: f64,
+    /// The unit, such as `kilometre` or `litre`, when Wit.ai could tell.
+    pub unit: Option<String>,
+}
+
+/// A resolved location.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    /// The name of the place.
+    pub name: String,
+    /// The latitude and longitude of the place, when Wit.ai resolved it.
+    pub coords: Option<(f64, f64)>,
+    /// The timezone of the place, when Wit.ai resolved it.
+    pub timezone: Option<String>,
+}
+
+/// The typed value of a `wit$*` built-in entity.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuiltinEntity {
+    /// A `wit$datetime`, such as "tomorrow at 8" or "from 8 to 10".
+    Datetime(WitDatetime),
+    /// A `wit$duration`, such as "for 20 minutes".
+    Duration(Duration),
+    /// A `wit$temperature`, such as "21 degrees".
+    Temperature(Temperature),
+    /// A `wit$amount_of_money`, such as "10 euros".
+    AmountOfMoney(Money),
+    /// A `wit$distance`, such as "5 km".
+    Distance(Measure),
+    /// A `wit$volume`, such as "2 litres".
+    Volume(Measure),
+    /// A `wit$quantity`, such as "3 cups".
+    Quantity(Measure),
+    /// A `wit$number`, such as "forty-two".
+    Number(f64),
+    /// A `wit$ordinal`, such as "third".
+    Ordinal(i64),
+    /// A `wit$location`, such as "Paris".
+    Location(Location),
+    /// A `wit$contact`, the name of a person.
+    Contact(String),
+    /// A `wit$email` address.
+    Email(String),
+    /// A `wit$phone_number`.
+    PhoneNumber(String),
+    /// A `wit$url`.
+    Url(String),
+}
+
+impl Entity {
+    /// Resolves the entity as one of the `wit$*` built-in entities.
+    ///
+    /// Returns `None` for custom entities, as well as built-in entities whose
+    /// value is not in the shape Wit.ai documents.
+    pub fn builtin(&self) -> Option<BuiltinEntity> {
+        Some(match self.name.as_str() {
+            WIT_DATETIME => BuiltinEntity::Datetime(self.as_datetime()?),
+            WIT_DURATION => {
+                let seconds = self.normalized.as_ref()?.get("value")?.as_f64()?;
+                BuiltinEntity::Duration(Duration::try_from_secs_f64(seconds).ok()?)
+            }
+            WIT_TEMPERATURE => BuiltinEntity::Temperature(Temperature {
+                value: self.number()?,
+                unit: match self.unit.as_deref() {
+                    Some("celsius") => Some(TemperatureUnit::Celsius),
+                    Some("fahrenheit") => Some(TemperatureUnit::Fahrenheit),
+                    Some("degree") => Some(TemperatureUnit::Degree),
+                    _ => None,
+                },
+            }),
+            WIT_AMOUNT_OF_MONEY => BuiltinEntity::AmountOfMoney(Money {
+                amount: self.number()?,
+                currency: self.unit.clone(),
+            }),
+            WIT_DISTANCE => BuiltinEntity::Distance(self.measure()?),
+            WIT_VOLUME => BuiltinEntity::Volume(self.measure()?),
+            WIT_QUANTITY => BuiltinEntity::Quantity(self.measure()?),
+            WIT_NUMBER => BuiltinEntity::Number(self.number()?),
+            WIT_ORDINAL => BuiltinEntity::Ordinal(self.value.as_ref()?.as_i64()?),
+            WIT_LOCATION => BuiltinEntity::Location(self.location()),
+            WIT_CONTACT => BuiltinEntity::Contact(self.text().to_string()),
+            WIT_EMAIL => BuiltinEntity::Email(self.text().to_string()),
+            WIT_PHONE_NUMBER => BuiltinEntity::PhoneNumber(self.text().to_string()),
+            WIT_URL => BuiltinEntity::Url(self.text().to_string()),
+            _ => return None,
+        })
+    }
+
+    fn number(&self) -> Option<f64> {
+        self.value.as_ref()?.as_f64()
+    }
+
+    /// The resolved text value, or the words it was resolved from.
+    fn text(&self) -> &str {
+        match &self.value {
+            Some(Value::String(value)) => value,
+            _ => &self.body,
+        }
+    }
+
+    fn measure(&self) -> Option<Measure> {
+        Some(Measure {
+            value: self.number()?,
+            unit: self.unit.clone(),
+        })
+    }
+
+    fn location(&self) -> Location {
+        let resolved = self
+            .resolved
+            .as_ref()
+            .and_then(|r| r.get("values"))
+            .and_then(|v| v.get(0));
+        let coords = resolved
+            .and_then(|r| r.get("coords"))
+            .and_then(|c| Some((c.get("lat")?.as_f64()?, c.get("long")?.as_f64()?)));
+        Location {
+            name: resolved
+                .and_then(|r| r.get("name"))
+                .and_then(Value::as_str)
+                .unwrap_or(&self.body)
+                .to_string(),
+            coords,
+            timezone: resolved
+                .and_then(|r| r.get("timezone"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// An entity as Wit.ai sends it in a `/message` response, `resolution`
+    /// holding the fields specific to the built-in.
+    fn entity(name: &str, body: &str, resolution: Value) -> Entity {
+        let mut entity = json!({
+            "id": "1",
+            "name": name,
+            "role": name.trim_start_matches("wit$"),
+            "start": 0,
+            "end": body.len(),
+            "body": body,
+            "confidence": 0.9,
+            "entities": {},
+        });
+        entity
+            .as_object_mut()
+            .unwrap()
+            .extend(resolution.as_object().unwrap().clone());
+        serde_json::from_value(entity).unwrap()
+    }
+
+    fn measure(value: f64, unit: &str) -> Measure {
+        Measure {
+            value,
+            unit: Some(unit.to_string()),
+        }
+    }
+
+    #[test]
+    fn measures_keep_their_value_and_unit() {
+        let cases = [
+            (
+                entity(
+                    WIT_DISTANCE,
+                    "5 km",
+                    json!({ "type": "value", "value": 5, "unit": "kilometre" }),
+                ),
+                BuiltinEntity::Distance(measure(5.0, "kilometre")),
+            ),
+            (
+                entity(
+                    WIT_VOLUME,
+                    "2.5 litres",
+                    json!({ "type": "value", "value": 2.5, "unit": "litre" }),
+                ),
+                BuiltinEntity::Volume(measure(2.5, "litre")),
+            ),
+            (
+                entity(
+                    WIT_QUANTITY,
+                    "3 cups of sugar",
+                    json!({ "type": "value", "value": 3, "unit": "cup", "product": "sugar" }),
+                ),
+                BuiltinEntity::Quantity(measure(3.0, "cup")),
+            ),
+            (
+                entity(WIT_DISTANCE, "5", json!({ "type": "value", "value": 5 })),
+                BuiltinEntity::Distance(Measure {
+                    value: 5.0,
+                    unit: None,
+                }),
+            ),
+        ];
+        for (entity, expected) in cases {
+            assert_eq!(entity.builtin(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn numbers_money_and_temperatures_are_resolved() {
+        let cases = [
+            (
+                entity(
+                    WIT_NUMBER,
+                    "forty-two",
+                    json!({ "type": "value", "value": 42 }),
+                ),
+                BuiltinEntity::Number(42.0),
+            ),
+            (
+                entity(WIT_ORDINAL, "third", json!({ "type": "value", "value": 3 })),
+                BuiltinEntity::Ordinal(3),
+            ),
+            (
+                entity(
+                    WIT_AMOUNT_OF_MONEY,
+                    "10 euros",
+                    json!({ "type": "value", "value": 10.5, "unit": "EUR" }),
+                ),
+                BuiltinEntity::AmountOfMoney(Money {
+                    amount: 10.5,
+                    currency: Some("EUR".to_string()),
+                }),
+            ),
+            (
+                entity(
+                    WIT_TEMPERATURE,
+                    "21 degrees celsius",
+                    json!({ "type": "value", "value": 21, "unit": "celsius" }),
+                ),
+                BuiltinEntity::Temperature(Temperature {
+                    value: 21.0,
+                    unit: Some(TemperatureUnit::Celsius),
+                }),
+            ),
+            (
+                entity(
+                    WIT_TEMPERATURE,
+                    "70 degrees",
+                    json!({ "type": "value", "value": 70, "unit": "degree" }),
+                ),
+                BuiltinEntity::Temperature(Temperature {
+                    value: 70.0,
+                    unit: Some(TemperatureUnit::Degree),
+                }),
+            ),
+        ];
+        for (entity, expected) in cases {
+            assert_eq!(entity.builtin(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn durations_use_their_normalized_seconds() {
+        let entity = entity(
+            WIT_DURATION,
+            "20 minutes",
+            json!({
+                "type": "value",
+                "value": 20,
+                "unit": "minute",
+                "minute": 20,
+                "normalized": { "value": 1200, "unit": "second" },
+            }),
+        );
+        assert_eq!(
+            entity.builtin(),
+            Some(BuiltinEntity::Duration(Duration::from_secs(1200)))
+        );
+    }
+
+    #[test]
+    fn locations_are_read_from_their_resolution() {
+        let resolved = entity(
+            WIT_LOCATION,
+            "paris",
+            json!({
+                "resolved": {
+                    "values": [{
+                        "name": "Paris",
+                        "domain": "locality",
+                        "coords": { "lat": 48.5, "long": 2.25 },
+                        "timezone": "Europe/Paris",
+                        "external": { "wikidata": "Q90" },
+                    }]
+                }
+            }),
+        );
+        assert_eq!(
+            resolved.builtin(),
+            Some(BuiltinEntity::Location(Location {
+                name: "Paris".to_string(),
+                coords: Some((48.5, 2.25)),
+                timezone: Some("Europe/Paris".to_string()),
+            }))
+        );
+
+        let unresolved = entity(WIT_LOCATION, "paris", json!({}));
+        assert_eq!(
+            unresolved.builtin(),
+            Some(BuiltinEntity::Location(Location {
+                name: "paris".to_string(),
+                coords: None,
+                timezone: None,
+            }))
+        );
+    }
+
+    #[test]
+    fn text_entities_prefer_their_value_over_their_body() {
+        let cases = [
+            (
+                entity(
+                    WIT_EMAIL,
+                    "me at example dot com",
+                    json!({ "type": "value", "value": "me@example.com" }),
+                ),
+                BuiltinEntity::Email("me@example.com".to_string()),
+            ),
+            (
+                entity(
+                    WIT_URL,
+                    "wit.ai",
+                    json!({ "type": "value", "value": "https://wit.ai", "domain": "wit.ai" }),
+                ),
+                BuiltinEntity::Url("https://wit.ai".to_string()),
+            ),
+            (
+                entity(
+                    WIT_PHONE_NUMBER,
+                    "555 0100",
+                    json!({ "type": "value", "value": "5550100" }),
+                ),
+                BuiltinEntity::PhoneNumber("5550100".to_string()),
+            ),
+            (
+                entity(WIT_CONTACT, "Alice", json!({ "suggested": true })),
+                BuiltinEntity::Contact("Alice".to_string()),
+            ),
+        ];
+        for (entity, expected) in cases {
+            assert_eq!(entity.builtin(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn custom_and_malformed_entities_are_not_resolved() {
+        let custom = entity("color", "blue", json!({ "type": "value", "value": "blue" }));
+        assert_eq!(custom.builtin(), None);
+        let malformed = entity(
+            WIT_NUMBER,
+            "many",
+            json!({ "type": "value", "value": "many" }),
+        );
+        assert_eq!(malformed.builtin(), None);
+    }
+}
@@ -0,0 +1,208 @@
+//! Typed resolution of `wit$datetime` entities.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, FixedOffset};
+use serde_json::Value;
+
+use crate::error::ParseGrainError;
+use crate::model::message::Entity;
+
+/// The precision of a resolved date-time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Grain {
+    /// Precise to the second.
+    Second,
+    /// Precise to the minute.
+    Minute,
+    /// Precise to the hour, such as "at 8".
+    Hour,
+    /// A whole day, such as "tomorrow".
+    Day,
+    /// A whole week, such as "next week".
+    Week,
+    /// A whole month, such as "in March".
+    Month,
+    /// A whole quarter, such as "Q3".
+    Quarter,
+    /// A whole year, such as "in 2025".
+    Year,
+}
+
+impl FromStr for Grain {
+    type Err = ParseGrainError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "second" => Grain::Second,
+            "minute" => Grain::Minute,
+            "hour" => Grain::Hour,
+            "day" => Grain::Day,
+            "week" => Grain::Week,
+            "month" => Grain::Month,
+            "quarter" => Grain::Quarter,
+            "year" => Grain::Year,
+            _ => return Err(ParseGrainError(s.to_string())),
+        })
+    }
+}
+
+/// A single point in time and its precision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatetimeValue {
+    /// The resolved point in time, in the offset Wit.ai resolved it in.
+    pub value: DateTime<FixedOffset>,
+    /// How precise the value is, `Day` for "tomorrow" for instance.
+    pub grain: Grain,
+}
+
+impl DatetimeValue {
+    /// Parses a `{ "value": ..., "grain": ... }` object.
+    pub(crate) fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            value: parse_datetime(value.get("value")?.as_str()?)?,
+            grain: value.get("grain")?.as_str()?.parse().ok()?,
+        })
+    }
+}
+
+/// A resolved `wit$datetime` entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WitDatetime {
+    /// A point in time, such as "tomorrow at 8".
+    Value(DatetimeValue),
+    /// A range of time, such as "from 8 to 10"; either end may be open.
+    Interval {
+        /// The start of the range, `None` when open.
+        from: Option<DatetimeValue>,
+        /// The end of the range, `None` when open.
+        to: Option<DatetimeValue>,
+    },
+}
+
+/// Parses the ISO 8601 date-times sent by Wit.ai, such as `2024-01-01T08:00:00.000-08:00`.
+pub fn parse_datetime(value: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(value).ok()
+}
+
+impl Entity {
+    /// Resolves the entity as a `wit$datetime`.
+    ///
+    /// Returns `None` when the entity is not a date-time or its value could not be parsed.
+    pub fn as_datetime(&self) -> Option<WitDatetime> {
+        match self.type_.as_deref()? {
+            "value" => Some(WitDatetime::Value(DatetimeValue {
+                value: parse_datetime(self.value.as_ref()?.as_str()?)?,
+                grain: self.grain.as_deref()?.parse().ok()?,
+            })),
+            "interval" => {
+                let from = self.from.as_ref().and_then(DatetimeValue::from_json);
+                let to = self.to.as_ref().and_then(DatetimeValue::from_json);
+                if from.is_none() && to.is_none() {
+                    return None;
+                }
+                Some(WitDatetime::Interval { from, to })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn entity(resolved: Value) -> Entity {
+        let mut entity = json!({
+            "id": "1",
+            "name": "wit$datetime",
+            "role": "datetime",
+            "start": 0,
+            "end": 8,
+            "body": "tomorrow",
+            "confidence": 0.9,
+        });
+        entity
+            .as_object_mut()
+            .unwrap()
+            .extend(resolved.as_object().unwrap().clone());
+        serde_json::from_value(entity).unwrap()
+    }
+
+    fn at(value: &str, grain: Grain) -> DatetimeValue {
+        DatetimeValue {
+            value: parse_datetime(value).unwrap(),
+            grain,
+        }
+    }
+
+    #[test]
+    fn grains_are_parsed_by_name() {
+        assert_eq!("hour".parse(), Ok(Grain::Hour));
+        assert_eq!("quarter".parse(), Ok(Grain::Quarter));
+        assert_eq!(
+            "fortnight".parse::<Grain>(),
+            Err(ParseGrainError("fortnight".to_string()))
+        );
+    }
+
+    #[test]
+    fn values_keep_their_grain() {
+        let entity = entity(json!({
+            "type": "value",
+            "value": "2024-01-02T00:00:00.000-08:00",
+            "grain": "day",
+        }));
+        assert_eq!(
+            entity.as_datetime(),
+            Some(WitDatetime::Value(at(
+                "2024-01-02T00:00:00.000-08:00",
+                Grain::Day
+            )))
+        );
+    }
+
+    #[test]
+    fn intervals_resolve_both_ends() {
+        let entity = entity(json!({
+            "type": "interval",
+            "from": { "value": "2024-01-02T08:00:00.000-08:00", "grain": "hour" },
+            "to": { "value": "2024-01-02T10:00:00.000-08:00", "grain": "hour" },
+        }));
+        assert_eq!(
+            entity.as_datetime(),
+            Some(WitDatetime::Interval {
+                from: Some(at("2024-01-02T08:00:00.000-08:00", Grain::Hour)),
+                to: Some(at("2024-01-02T10:00:00.000-08:00", Grain::Hour)),
+            })
+        );
+    }
+
+    #[test]
+    fn intervals_may_be_open_ended() {
+        let entity = entity(json!({
+            "type": "interval",
+            "from": { "value": "2024-01-02T08:00:00.000-08:00", "grain": "minute" },
+        }));
+        assert_eq!(
+            entity.as_datetime(),
+            Some(WitDatetime::Interval {
+                from: Some(at("2024-01-02T08:00:00.000-08:00", Grain::Minute)),
+                to: None,
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_grains_are_not_resolved() {
+        let unknown = entity(json!({
+            "type": "value",
+            "value": "2024-01-02T00:00:00.000-08:00",
+            "grain": "fortnight",
+        }));
+        assert_eq!(unknown.as_datetime(), None);
+        assert_eq!(entity(json!({ "type": "interval" })).as_datetime(), None);
+    }
+}
@@ -0,0 +1,287 @@
+//! The voices available to the `/synthesize` endpoint.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+use crate::model::client::{WitClient, path_segment};
+use crate::model::locale::Locale;
+
+/// The name of a synthesis voice, such as `wit$Rebecca`.
+///
+/// Any string converts into a voice name, an unknown one is only rejected by
+/// Wit.ai. Look names up with [`WitClient::find_voice`] to catch typos early.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct VoiceName(String);
+
+impl VoiceName {
+    /// The name as sent to Wit.ai.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for VoiceName {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl From<&str> for VoiceName {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl From<String> for VoiceName {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&VoiceName> for VoiceName {
+    fn from(value: &VoiceName) -> Self {
+        value.clone()
+    }
+}
+
+impl From<VoiceName> for String {
+    fn from(value: VoiceName) -> Self {
+        value.0
+    }
+}
+
+impl AsRef<str> for VoiceName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for VoiceName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl PartialEq<str> for VoiceName {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for VoiceName {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+/// A voice `/synthesize` can speak with.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+pub struct Voice {
+    /// The name to pass to the synthesis query, such as `wit$Rebecca`.
+    pub name: VoiceName,
+    /// The locale the voice speaks, such as `en_US`.
+    pub locale: Locale,
+    /// The gender of the voice.
+    pub gender: String,
+    /// The styles the voice can be spoken in.
+    #[serde(default)]
+    pub styles: Vec<String>,
+    /// The SSML and synthesis features the voice supports.
+    #[serde(default)]
+    pub supported_features: Vec<String>,
+    /// Where to download a short sample of the voice, when Wit.ai provides one.
+    #[serde(default)]
+    pub preview_url: Option<String>,
+}
+
+impl Voice {
+    /// Downloads the sample of this voice, `None` when it has no preview URL.
+    ///
+    /// The token of `client` is only sent when the sample is hosted under the
+    /// client's base URL.
+    pub async fn fetch_preview(&self, client: &WitClient) -> Result<Option<Bytes>, ApiError> {
+        let Some(url) = &self.preview_url else {
+            return Ok(None);
+        };
+        let response = if url.starts_with(&client.base_url) {
+            client.send(client.prepare_get_request(url)).await?
+        } else {
+            client.client.get(url).send().await?.error_for_status()?
+        };
+        Ok(Some(response.bytes().await?))
+    }
+}
+
+/// A gender a voice can have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceGender {
+    /// A female voice.
+    Female,
+    /// A male voice.
+    Male,
+}
+
+impl VoiceGender {
+    /// The value of [`Voice::gender`] for this gender.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VoiceGender::Female => "female",
+            VoiceGender::Male => "male",
+        }
+    }
+}
+
+/// The voices returned by `/voices`, grouped by the locale they speak, such
+/// as `en_US`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
+#[serde(transparent)]
+pub struct VoicesResponse(pub BTreeMap<String, Vec<Voice>>);
+
+impl VoicesResponse {
+    /// Every voice, grouped by locale in alphabetical order.
+    pub fn iter_all(&self) -> impl Iterator<Item = &Voice> {
+        self.0.values().flatten()
+    }
+
+    /// The voices speaking `locale`.
+    pub fn iter_locale(&self, locale: &Locale) -> impl Iterator<Item = &Voice> {
+        self.0.get(&locale.to_string()).into_iter().flatten()
+    }
+
+    /// The voices of the given gender, whatever their locale.
+    pub fn by_gender(&self, gender: VoiceGender) -> impl Iterator<Item = &Voice> {
+        self.iter_all()
+            .filter(move |voice| voice.gender.eq_ignore_ascii_case(gender.as_str()))
+    }
+
+    /// The locales at least one voice speaks.
+    pub fn locales(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    /// Every voice, without the grouping.
+    pub fn into_voices(self) -> Vec<Voice> {
+        self.0.into_values().flatten().collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a VoicesResponse {
+    type Item = &'a Voice;
+    type IntoIter = std::iter::Flatten<std::collections::btree_map::Values<'a, String, Vec<Voice>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.values().flatten()
+    }
+}
+
+/// The voices fetched by [`WitClient::get_voices_cached`], shared by every
+/// clone of a client.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct VoiceCache(Arc<Mutex<Option<CachedVoices>>>);
+
+/// The voices and when they were fetched.
+type CachedVoices = (Instant, Arc<[Voice]>);
+
+impl VoiceCache {
+    fn fresh(&self, ttl: Duration) -> Option<Arc<[Voice]>> {
+        let cache = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match &*cache {
+            Some((fetched, voices)) if fetched.elapsed() < ttl => Some(voices.clone()),
+            _ => None,
+        }
+    }
+
+    fn store(&self, voices: Arc<[Voice]>) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), voices));
+    }
+}
+
+impl WitClient {
+    /// Lists every voice available for synthesis, grouped by locale in the
+    /// response and flattened here.
+    pub async fn get_voices(&self) -> Result<Vec<Voice>, ApiError> {
+        Ok(self.get_voices_by_locale().await?.into_voices())
+    }
+
+    /// Lists every voice available for synthesis, grouped by locale.
+    pub async fn get_voices_by_locale(&self) -> Result<VoicesResponse, ApiError> {
+        let request = self.prepare_get_request(self.url("voices"));
+        self.send_json(request).await
+    }
+
+    /// Fetches a single voice by name, such as `wit$Rebecca`.
+    pub async fn get_voice(&self, name: impl Into<VoiceName>) -> Result<Voice, ApiError> {
+        let name = name.into();
+        let request =
+            self.prepare_get_request(self.url(&format!("voices/{}", path_segment(name.as_ref()))));
+        self.send_json(request).await
+    }
+
+    /// Like [`WitClient::get_voices`], but reuses the voices fetched by a
+    /// previous call if they are younger than `ttl`.
+    ///
+    /// The list of voices rarely changes, so applications looking up a voice
+    /// before every synthesis should go through this method to save a round
+    /// trip. Concurrent calls with a stale cache may each fetch the list.
+    pub async fn get_voices_cached(&self, ttl: Duration) -> Result<Arc<[Voice]>, ApiError> {
+        if let Some(voices) = self.voice_cache.fresh(ttl) {
+            return Ok(voices);
+        }
+        let voices: Arc<[Voice]> = self.get_voices().await?.into();
+        self.voice_cache.store(voices.clone());
+        Ok(voices)
+    }
+
+    /// Returns the first voice matching `predicate`, looked up through
+    /// [`WitClient::get_voices_cached`].
+    pub async fn find_voice(
+        &self,
+        ttl: Duration,
+        predicate: impl Fn(&Voice) -> bool,
+    ) -> Result<Option<Voice>, ApiError> {
+        let voices = self.get_voices_cached(ttl).await?;
+        Ok(voices.iter().find(|voice| predicate(voice)).cloned())
+    }
+}
+
+#[cfg(all(test, feature = "serde-serialize"))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn voices_round_trip() {
+        let payload = json!({
+            "name": "wit$Rebecca",
+            "locale": "en_US",
+            "gender": "female",
+            "styles": ["default", "soft"],
+            "supported_features": ["pitch"],
+            "preview_url": "https://example.com/rebecca.mp3"
+        });
+        let voice: Voice = serde_json::from_value(payload.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&voice).unwrap(), payload);
+
+        let voices = VoicesResponse(BTreeMap::from([("en_US".to_string(), vec![voice])]));
+        let encoded = serde_json::to_value(&voices).unwrap();
+        assert_eq!(encoded["en_US"][0], payload);
+        assert_eq!(
+            serde_json::from_value::<VoicesResponse>(encoded).unwrap(),
+            voices
+        );
+    }
+}
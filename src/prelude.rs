@@ -0,0 +1,45 @@
+pub use crate::error::{ApiError, BatchError, ErrorKind};
+#[cfg(feature = "tokio")]
+pub use crate::model::aggregate::{Completion, Partial, TraitMergePolicy};
+#[cfg(feature = "tokio")]
+pub use crate::model::assistant::{Assistant, AssistantTurn};
+pub use crate::model::batch::{BatchFailure, BatchOutcome};
+pub use crate::model::chunking::{ChunkMetrics, ChunkStrategy};
+pub use crate::model::client::WitClient;
+pub use crate::model::composer::{ComposerEvent, ComposerResponse, ComposerSession};
+pub use crate::model::context::{Context, ConversationContext};
+pub use crate::model::dictation::{Alternative, Dictation, DictationQuery};
+pub use crate::model::entities::{DynamicEntity, EntityValue, Keyword, split_entity_key};
+pub use crate::model::id::Id;
+pub use crate::model::intents::{Intent, IntentEntity, IntentRef};
+#[allow(deprecated)]
+pub use crate::model::legacy::Client;
+pub use crate::model::locale::Locale;
+pub use crate::model::message::{
+    Entity, EntityLookup, IntentMatch, IntentThresholds, Message, MessageQuery, Trait, TraitLookup,
+    TraitSet, TraitThresholds,
+};
+pub use crate::model::quota::QuotaInfo;
+#[cfg(feature = "websocket")]
+pub use crate::model::realtime::SpeechSender;
+pub use crate::model::registry::WitRegistry;
+#[cfg(feature = "tokio")]
+pub use crate::model::resume::{Resumable, ResumableStream, ResumePolicy};
+pub use crate::model::router::{IntentRouter, Utterance};
+pub use crate::model::server::ServerClient;
+pub use crate::model::speech::{
+    AudioSource, Encoding, Endian, RawEncoding, SpeechQuery, SpeechResponse, SpeechUnderstanding,
+};
+pub use crate::model::ssml::Ssml;
+pub use crate::model::stream::{
+    CancelHandle, DictationStream, SpeechOutcome, SpeechStream, SpeechStreamExt, StatsHandle,
+    StreamStats,
+};
+pub use crate::model::synthesize::SynthesizedAudio;
+pub use crate::model::synthesize::{SynthesisCache, SynthesizeCodec, SynthesizeQuery};
+pub use crate::model::token::TokenStatus;
+pub use crate::model::transcript::{Transcript, TranscriptBuilder, TranscriptSegment};
+pub use crate::model::transcription::TranscriptionEvent;
+pub use crate::model::vad::VadConfig;
+pub use crate::model::voice::{Voice, VoiceGender, VoiceName, VoicesResponse};
+pub use crate::utils::audio::Normalization;
@@ -0,0 +1,599 @@
+//! Helpers to test code using this crate against a local mock of Wit.ai,
+//! behind the `test-util` feature.
+//!
+//! [`MockWit`] starts a [`wiremock`] server and hands out clients pointed at
+//! it, so tests run offline and without a Wit.ai token. Canned responses are
+//! registered with its `mock_*` methods, or directly on
+//! [`MockWit::server`] for anything more specific.
+//!
+//! Real Wit.ai traffic can be recorded too: a client built with
+//! [`WitClient::with_recorder`] writes its exchanges to a JSON fixtures file,
+//! or to any [`BufferStorage`], and [`MockWit::replay`] serves them back
+//! offline. Audio is left out of the fixtures unless asked for, see
+//! [`Recorder::with_audio`].
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE, HeaderMap, HeaderName, TRANSFER_ENCODING};
+use reqwest::{Request, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use wiremock::matchers::{body_string, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use crate::error::ApiError;
+use crate::model::client::WitClient;
+use crate::model::server::ServerClient;
+use crate::utils::storage::BufferStorage;
+
+pub mod payloads;
+
+/// The token sent by the clients of a [`MockWit`].
+pub const MOCK_TOKEN: &str = "mock-token";
+
+/// A local mock of the Wit.ai API.
+pub struct MockWit {
+    server: MockServer,
+}
+
+impl MockWit {
+    /// Starts a mock server on a random local port.
+    pub async fn start() -> Self {
+        Self {
+            server: MockServer::start().await,
+        }
+    }
+
+    /// The underlying server, to register custom mocks or inspect the
+    /// requests it received.
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    /// A client sending its requests to this server.
+    pub fn client(&self) -> WitClient {
+        WitClient::new(MOCK_TOKEN).with_base_url(&self.server.uri())
+    }
+
+    /// A management client sending its requests to this server.
+    pub fn server_client(&self) -> ServerClient {
+        ServerClient::from(self.client())
+    }
+
+    /// Answers `method` requests to `/{endpoint}` with a JSON body.
+    pub async fn mock_json(&self, http_method: &str, endpoint: &str, body: Value) {
+        self.mock(
+            http_method,
+            endpoint,
+            ResponseTemplate::new(200).set_body_json(body),
+        )
+        .await;
+    }
+
+    /// Answers `/message` with the given understanding.
+    pub async fn mock_message(&self, message: Value) {
+        self.mock_json("GET", "message", message).await;
+    }
+
+    /// Answers `/speech` with the given events, streamed back to back.
+    pub async fn mock_speech(&self, events: &[Value]) {
+        self.mock_stream("speech", events).await;
+    }
+
+    /// Answers `/dictation` with the given events, streamed back to back.
+    pub async fn mock_dictation(&self, events: &[Value]) {
+        self.mock_stream("dictation", events).await;
+    }
+
+    /// Answers `/synthesize` with the given audio.
+    pub async fn mock_synthesize(&self, audio: &[u8]) {
+        self.mock(
+            "POST",
+            "synthesize",
+            ResponseTemplate::new(200).set_body_bytes(audio.to_vec()),
+        )
+        .await;
+    }
+
+    /// Answers `/voices` with the given voices, grouped by locale as Wit.ai does.
+    pub async fn mock_voices(&self, voices: Value) {
+        self.mock_json("GET", "voices", voices).await;
+    }
+
+    /// Answers `method` requests to `/{endpoint}` with a Wit.ai error.
+    pub async fn mock_error(
+        &self,
+        http_method: &str,
+        endpoint: &str,
+        status: u16,
+        code: &str,
+        message: &str,
+    ) {
+        let body = json!({ "error": message, "code": code });
+        self.mock(
+            http_method,
+            endpoint,
+            ResponseTemplate::new(status).set_body_json(body),
+        )
+        .await;
+    }
+
+    /// Answers `method` requests to `/{endpoint}` with a 429 asking to retry
+    /// after `retry_after_secs` seconds.
+    pub async fn mock_rate_limit(&self, http_method: &str, endpoint: &str, retry_after_secs: u64) {
+        let body = json!({ "error": "Too many requests", "code": "rate-limit" });
+        self.mock(
+            http_method,
+            endpoint,
+            ResponseTemplate::new(429)
+                .insert_header("Retry-After", retry_after_secs.to_string())
+                .set_body_json(body),
+        )
+        .await;
+    }
+
+    /// Starts a mock server answering the exchanges recorded in the fixtures
+    /// file at `path`, see [`Recorder`].
+    pub async fn replay(path: impl AsRef<Path>) -> Result<Self, ApiError> {
+        let mock = Self::start().await;
+        mock.mount_fixtures(&load_fixtures(path)?).await;
+        Ok(mock)
+    }
+
+    /// Starts a mock server answering the exchanges a [`Recorder`] stored
+    /// under `key` in `storage`.
+    pub async fn replay_stored(storage: &dyn BufferStorage, key: &str) -> Result<Self, ApiError> {
+        let mock = Self::start().await;
+        mock.mount_fixtures(&load_stored_fixtures(storage, key)?)
+            .await;
+        Ok(mock)
+    }
+
+    /// Answers the requests of `fixtures` with their recorded responses.
+    ///
+    /// A request is answered when its method, path, query parameters and,
+    /// for text bodies, body match a fixture; the token it is sent with is
+    /// ignored. Identical requests are all answered with the first fixture
+    /// recorded for them.
+    pub async fn mount_fixtures(&self, fixtures: &[Fixture]) {
+        for fixture in fixtures {
+            let request = &fixture.request;
+            let mut mock = Mock::given(method(request.method.as_str()))
+                .and(path(format!("/{}", request.path)));
+            for (name, value) in &request.query {
+                mock = mock.and(query_param(name.as_str(), value.as_str()));
+            }
+            if let Some(FixtureBody::Text(body)) = &request.body {
+                mock = mock.and(body_string(body.as_str()));
+            }
+            let response = &fixture.response;
+            let mut template = ResponseTemplate::new(response.status);
+            for (name, value) in &response.headers {
+                template = template.insert_header(name.as_str(), value.as_str());
+            }
+            mock.respond_with(template.set_body_bytes(response.body.to_bytes()))
+                .mount(&self.server)
+                .await;
+        }
+    }
+
+    async fn mock_stream(&self, endpoint: &str, events: &[Value]) {
+        let body: String = events.iter().map(|event| format!("{event}\r\n")).collect();
+        self.mock(
+            "POST",
+            endpoint,
+            ResponseTemplate::new(200).set_body_raw(body, "application/json"),
+        )
+        .await;
+    }
+
+    async fn mock(&self, http_method: &str, endpoint: &str, response: ResponseTemplate) {
+        Mock::given(method(http_method))
+            .and(path(format!("/{endpoint}")))
+            .and(header("authorization", format!("Bearer {MOCK_TOKEN}")))
+            .respond_with(response)
+            .mount(&self.server)
+            .await;
+    }
+}
+
+/// One recorded exchange with Wit.ai.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fixture {
+    /// The request sent.
+    pub request: FixtureRequest,
+    /// The response Wit.ai sent back.
+    pub response: FixtureResponse,
+}
+
+/// A recorded request. Its headers, and so its token, are not recorded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FixtureRequest {
+    /// The HTTP method, such as `GET`.
+    pub method: String,
+    /// The path of the endpoint, relative to the base URL of the client,
+    /// such as `message` or `entities/color`.
+    pub path: String,
+    /// The query parameters, in the order they were sent.
+    #[serde(default)]
+    pub query: Vec<(String, String)>,
+    /// The body, `None` for requests without one and for streamed uploads.
+    /// Audio is redacted unless recorded with [`Recorder::with_audio`].
+    #[serde(default)]
+    pub body: Option<FixtureBody>,
+}
+
+/// A recorded response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FixtureResponse {
+    /// The HTTP status code.
+    pub status: u16,
+    /// The headers, such as the content type and rate-limit information.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// The whole body, including every event of a streamed response.
+    pub body: FixtureBody,
+}
+
+/// A recorded body, kept as text when it is valid UTF-8 so that JSON
+/// fixtures stay readable and editable.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "encoding", content = "data", rename_all = "lowercase")]
+pub enum FixtureBody {
+    /// A UTF-8 body, such as JSON.
+    Text(String),
+    /// A binary body, such as audio, encoded in base64.
+    Base64(String),
+    /// The length of an audio or binary body left out of the fixture. It is
+    /// replayed as that many zero bytes.
+    Redacted(usize),
+}
+
+impl FixtureBody {
+    /// Captures a body sent or received with the given headers, leaving it
+    /// out when it is audio or binary and `keep_audio` is not set.
+    fn new(bytes: &[u8], headers: &HeaderMap, keep_audio: bool) -> Self {
+        let audio = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("audio/"));
+        match std::str::from_utf8(bytes) {
+            _ if audio && !keep_audio => FixtureBody::Redacted(bytes.len()),
+            Ok(text) => FixtureBody::Text(text.to_string()),
+            Err(_) if keep_audio => FixtureBody::Base64(STANDARD.encode(bytes)),
+            Err(_) => FixtureBody::Redacted(bytes.len()),
+        }
+    }
+
+    /// The bytes of the body. A base64 body that does not decode, such as a
+    /// fixture edited by hand, is served as it is written.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            FixtureBody::Text(text) => text.clone().into_bytes(),
+            FixtureBody::Base64(data) => STANDARD
+                .decode(data)
+                .unwrap_or_else(|_| data.clone().into_bytes()),
+            FixtureBody::Redacted(len) => vec![0; *len],
+        }
+    }
+}
+
+impl FixtureRequest {
+    /// Reads a request about to be sent by a client whose base URL is
+    /// `base_url`, keeping its audio body only when `keep_audio` is set.
+    fn new(request: &Request, base_url: &str, keep_audio: bool) -> Self {
+        let url = request.url();
+        let path = url
+            .as_str()
+            .strip_prefix(base_url)
+            .unwrap_or_else(|| url.path().trim_start_matches('/'));
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        Self {
+            method: request.method().to_string(),
+            path: path.to_string(),
+            query: url.query_pairs().into_owned().collect(),
+            body: request
+                .body()
+                .and_then(|body| body.as_bytes())
+                .map(|body| FixtureBody::new(body, request.headers(), keep_audio)),
+        }
+    }
+}
+
+/// Response headers describing how the recorded body was framed, left out
+/// of fixtures so that their bodies can be edited by hand.
+const FRAMING_HEADERS: [HeaderName; 2] = [CONTENT_LENGTH, TRANSFER_ENCODING];
+
+/// Records the exchanges of a client, see [`WitClient::with_recorder`].
+///
+/// The exchanges are kept in memory and written once, by
+/// [`Recorder::finish`] or when the last clone of the recorder is dropped,
+/// including when a test panics. Cloned recorders share their fixtures.
+///
+/// Uploaded and synthesised audio is left out of the fixtures, so that
+/// recordings never put audio on disk. [`Recorder::with_audio`] keeps it,
+/// which is best paired with a storage encrypting it at rest, such as an
+/// [`EncryptedStorage`](crate::utils::storage::EncryptedStorage) given to
+/// [`Recorder::with_storage`].
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    keep_audio: bool,
+    recording: Arc<Recording>,
+}
+
+impl Recorder {
+    /// Creates a recorder writing to the fixtures file at `path`, replacing
+    /// it.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self::with_storage(FixturesFile(path.into()), "fixtures")
+    }
+
+    /// Creates a recorder writing its fixtures to `storage` under `key`, to
+    /// replay them with [`MockWit::replay_stored`].
+    pub fn with_storage(storage: impl BufferStorage + 'static, key: &str) -> Self {
+        Self {
+            keep_audio: false,
+            recording: Arc::new(Recording {
+                storage: Box::new(storage),
+                key: key.to_string(),
+                log: Mutex::default(),
+            }),
+        }
+    }
+
+    /// Keeps uploaded and synthesised audio in the fixtures, so that it is
+    /// replayed as recorded rather than as silence.
+    pub fn with_audio(mut self) -> Self {
+        self.keep_audio = true;
+        self
+    }
+
+    /// The exchanges recorded so far.
+    pub fn fixtures(&self) -> Vec<Fixture> {
+        self.recording.lock().fixtures.clone()
+    }
+
+    /// Writes the exchanges recorded so far, reporting the errors that
+    /// writing on drop has to ignore.
+    pub async fn finish(&self) -> Result<(), ApiError> {
+        let recording = self.recording.clone();
+        tokio::task::spawn_blocking(move || recording.flush())
+            .await
+            .map_err(io::Error::other)??;
+        Ok(())
+    }
+
+    /// Reads a request about to be sent by a client whose base URL is
+    /// `base_url`.
+    pub(crate) fn capture(&self, request: &Request, base_url: &str) -> FixtureRequest {
+        FixtureRequest::new(request, base_url, self.keep_audio)
+    }
+
+    /// Reads a whole response, records it and hands back an identical one.
+    ///
+    /// Streamed responses, such as those of `/speech`, are only handed back
+    /// once complete, so partial events all arrive at once while recording.
+    pub(crate) async fn record(
+        &self,
+        request: FixtureRequest,
+        response: Response,
+    ) -> Result<Response, ApiError> {
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+        let fixture = Fixture {
+            request,
+            response: FixtureResponse {
+                status: status.as_u16(),
+                headers: headers
+                    .iter()
+                    .filter(|(name, _)| !FRAMING_HEADERS.contains(name))
+                    .filter_map(|(name, value)| {
+                        Some((name.to_string(), value.to_str().ok()?.to_string()))
+                    })
+                    .collect(),
+                body: FixtureBody::new(&body, &headers, self.keep_audio),
+            },
+        };
+        self.recording.lock().fixtures.push(fixture);
+
+        let mut replayed = http::Response::new(body);
+        *replayed.status_mut() = status;
+        *replayed.headers_mut() = headers;
+        Ok(replayed.into())
+    }
+}
+
+/// The fixtures shared by the clones of a [`Recorder`], written when the
+/// last one is dropped.
+struct Recording {
+    storage: Box<dyn BufferStorage>,
+    key: String,
+    log: Mutex<Log>,
+}
+
+#[derive(Default)]
+struct Log {
+    fixtures: Vec<Fixture>,
+    written: Option<usize>,
+}
+
+impl Recording {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Log> {
+        self.log
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Writes the fixtures, unless they were written as they are already.
+    fn flush(&self) -> io::Result<()> {
+        let mut log = self.lock();
+        if log.written == Some(log.fixtures.len()) {
+            return Ok(());
+        }
+        let data = serde_json::to_vec_pretty(&log.fixtures)?;
+        self.storage.store(&self.key, &data)?;
+        log.written = Some(log.fixtures.len());
+        Ok(())
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        if !self.lock().fixtures.is_empty() {
+            let _ = self.flush();
+        }
+    }
+}
+
+impl Debug for Recording {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recording")
+            .field("key", &self.key)
+            .field("fixtures", &self.lock().fixtures.len())
+            .finish()
+    }
+}
+
+/// The fixtures file of [`Recorder::new`], which ignores keys.
+struct FixturesFile(PathBuf);
+
+impl BufferStorage for FixturesFile {
+    fn store(&self, _key: &str, data: &[u8]) -> io::Result<()> {
+        std::fs::write(&self.0, data)
+    }
+
+    fn load(&self, _key: &str) -> io::Result<Option<Vec<u8>>> {
+        match std::fs::read(&self.0) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn remove(&self, _key: &str) -> io::Result<()> {
+        std::fs::remove_file(&self.0)
+    }
+}
+
+/// Reads the fixtures file at `path`, as written by a [`Recorder`].
+pub fn load_fixtures(path: impl AsRef<Path>) -> Result<Vec<Fixture>, ApiError> {
+    let data = std::fs::read(path)?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Reads the fixtures a [`Recorder`] stored under `key` in `storage`.
+pub fn load_stored_fixtures(
+    storage: &dyn BufferStorage,
+    key: &str,
+) -> Result<Vec<Fixture>, ApiError> {
+    let data = storage.load(key)?.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("no fixtures under {key}"))
+    })?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::message::MessageQuery;
+    use crate::model::synthesize::SynthesizeQuery;
+    use crate::utils::storage::MemoryStorage;
+
+    const AUDIO: [u8; 4] = [0xff, 0xfe, 0x00, 0x01];
+
+    async fn record(recorder: &Recorder) -> MockWit {
+        let mock = MockWit::start().await;
+        mock.mock_message(payloads::message()).await;
+        mock.mock_synthesize(&AUDIO).await;
+        let client = mock.client().with_recorder(recorder.clone());
+        client
+            .get_message(&MessageQuery::new("hello"))
+            .await
+            .unwrap();
+        let query = SynthesizeQuery::new("hello", "Rebecca");
+        assert_eq!(client.post_synthesize(&query).await.unwrap(), AUDIO[..]);
+        mock
+    }
+
+    #[tokio::test]
+    async fn recordings_are_written_once_finished() {
+        let storage = Arc::new(MemoryStorage::new());
+        let recorder = Recorder::with_storage(storage.clone(), "session");
+        record(&recorder).await;
+        assert!(storage.load("session").unwrap().is_none());
+
+        recorder.finish().await.unwrap();
+        let fixtures = load_stored_fixtures(&*storage, "session").unwrap();
+        assert_eq!(fixtures, recorder.fixtures());
+        assert_eq!(fixtures.len(), 2);
+
+        let replay = MockWit::replay_stored(&*storage, "session").await.unwrap();
+        let message = replay
+            .client()
+            .get_message(&MessageQuery::new("hello"))
+            .await;
+        assert_eq!(message.unwrap().text, payloads::message()["text"]);
+    }
+
+    #[tokio::test]
+    async fn audio_is_redacted_unless_kept() {
+        let recorder = Recorder::with_storage(MemoryStorage::new(), "session");
+        record(&recorder).await;
+        let synthesized = &recorder.fixtures()[1].response.body;
+        assert_eq!(*synthesized, FixtureBody::Redacted(AUDIO.len()));
+        assert_eq!(synthesized.to_bytes(), [0; 4]);
+
+        let recorder = Recorder::with_storage(MemoryStorage::new(), "session").with_audio();
+        record(&recorder).await;
+        let synthesized = &recorder.fixtures()[1].response.body;
+        assert_eq!(synthesized.to_bytes(), AUDIO);
+    }
+
+    #[tokio::test]
+    async fn the_last_clone_writes_the_fixtures_file() {
+        let path =
+            std::env::temp_dir().join(format!("wit-owo-fixtures-{}.json", std::process::id()));
+        let recorder = Recorder::new(&path);
+        record(&recorder).await;
+        drop(recorder);
+        let fixtures = load_fixtures(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(fixtures.len(), 2);
+        assert!(matches!(
+            fixtures[1].response.body,
+            FixtureBody::Redacted(4)
+        ));
+    }
+
+    #[tokio::test]
+    async fn recorded_audio_is_encrypted_at_rest() {
+        use crate::utils::storage::EncryptedStorage;
+        use crate::utils::storage::tests::Flip;
+
+        let inner = Arc::new(MemoryStorage::new());
+        let storage = EncryptedStorage::new(inner.clone(), Flip);
+        let recorder = Recorder::with_storage(storage, "session").with_audio();
+        record(&recorder).await;
+        recorder.finish().await.unwrap();
+
+        let stored = inner.load("session").unwrap().unwrap();
+        let encoded = STANDARD.encode(AUDIO);
+        assert!(!stored.windows(AUDIO.len()).any(|window| window == AUDIO));
+        assert!(
+            !stored
+                .windows(encoded.len())
+                .any(|window| window == encoded.as_bytes())
+        );
+
+        let storage = EncryptedStorage::new(inner, Flip);
+        let fixtures = load_stored_fixtures(&storage, "session").unwrap();
+        assert_eq!(fixtures[1].response.body.to_bytes(), AUDIO);
+    }
+}
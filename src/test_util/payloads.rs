@@ -0,0 +1,337 @@
+//! Example Wit.ai payloads shaped like the responses of each endpoint, to
+//! feed a [`MockWit`](super::MockWit) or to check code reading the models.
+//!
+//! They follow the responses documented by Wit.ai, including the fields the
+//! models only keep loosely typed, such as the alternative `values` and the
+//! `resolved` locations of entities.
+
+use serde_json::{Value, json};
+
+/// The understanding of "Set an alarm for tomorrow at 7am in Paris" from
+/// `/message`, with a date-time, a resolved location and two traits.
+pub fn message() -> Value {
+    json!({
+        "text": "Set an alarm for tomorrow at 7am in Paris",
+        "intents": [
+            { "id": "1339541496634021", "name": "set_alarm", "confidence": 0.9871 },
+            { "id": "2206412979588316", "name": "set_reminder", "confidence": 0.0129 }
+        ],
+        "entities": {
+            "wit$datetime:datetime": [{
+                "id": "535a80f6-c3e2-4bb5-a6ed-1e5ef8e4c8a1",
+                "name": "wit$datetime",
+                "role": "datetime",
+                "start": 17,
+                "end": 32,
+                "body": "tomorrow at 7am",
+                "confidence": 0.9575,
+                "entities": {},
+                "type": "value",
+                "grain": "hour",
+                "value": "2024-03-05T07:00:00.000-08:00",
+                "values": [
+                    { "type": "value", "grain": "hour", "value": "2024-03-05T07:00:00.000-08:00" }
+                ]
+            }],
+            "wit$location:location": [{
+                "id": "2a36be10-a06e-46ef-a9a6-4fd6bbe1c8b8",
+                "name": "wit$location",
+                "role": "location",
+                "start": 36,
+                "end": 41,
+                "body": "Paris",
+                "confidence": 0.9388,
+                "entities": {},
+                "type": "resolved",
+                "value": "Paris",
+                "resolved": {
+                    "values": [{
+                        "name": "Paris",
+                        "domain": "locality",
+                        "coords": { "lat": 48.856_61, "long": 2.351_499 },
+                        "timezone": "Europe/Paris",
+                        "external": { "geonames": "2988507", "wikidata": "Q90" },
+                        "attributes": {}
+                    }]
+                }
+            }]
+        },
+        "traits": {
+            "wit$sentiment": [
+                { "id": "5ac2b50a-44e4-466e-9d49-bad6bd40092c", "value": "neutral", "confidence": 0.6738 }
+            ],
+            "wit$greetings": [
+                { "id": "5900cc2d-41b7-45b2-b21f-b950d3ae3c5c", "value": "false", "confidence": 0.9945 }
+            ]
+        }
+    })
+}
+
+/// The events of `/speech` for "What's the weather", one of each type in the
+/// order Wit.ai sends them: partial transcription, final transcription,
+/// partial understanding, final understanding.
+pub fn speech_events() -> Vec<Value> {
+    let tokens = json!([
+        { "token": "What's", "start": 240, "end": 600, "confidence": 0.9102 },
+        { "token": "the", "start": 600, "end": 720, "confidence": 0.9467 },
+        { "token": "weather", "start": 720, "end": 1200, "confidence": 0.9384 }
+    ]);
+    let understanding = |kind: &str, is_final: bool| {
+        json!({
+            "type": kind,
+            "text": "What's the weather",
+            "is_final": is_final,
+            "speech": { "confidence": 0.9318, "tokens": tokens },
+            "intents": [{ "id": "1149390802235582", "name": "get_weather", "confidence": 0.9952 }],
+            "entities": {},
+            "traits": {}
+        })
+    };
+    vec![
+        json!({
+            "type": "PARTIAL_TRANSCRIPTION",
+            "text": "What's the",
+            "is_final": false,
+            "speech": { "confidence": 0.8821, "tokens": tokens.as_array().map(|t| t[..2].to_vec()) }
+        }),
+        json!({
+            "type": "FINAL_TRANSCRIPTION",
+            "text": "What's the weather",
+            "is_final": true,
+            "speech": { "confidence": 0.9318, "tokens": tokens }
+        }),
+        understanding("PARTIAL_UNDERSTANDING", false),
+        understanding("FINAL_UNDERSTANDING", true),
+    ]
+}
+
+/// The events of `/dictation` for "Hello world": a partial transcription,
+/// then the final one.
+pub fn dictation_events() -> Vec<Value> {
+    let tokens = json!([
+        { "token": "Hello", "start": 300, "end": 720, "confidence": 0.9634 },
+        { "token": "world", "start": 720, "end": 1140, "confidence": 0.9511 }
+    ]);
+    vec![
+        json!({
+            "type": "PARTIAL_TRANSCRIPTION",
+            "text": "Hello",
+            "is_final": false,
+            "speech": { "confidence": 0.9634, "tokens": tokens.as_array().map(|t| t[..1].to_vec()) }
+        }),
+        json!({
+            "type": "FINAL_TRANSCRIPTION",
+            "text": "Hello world",
+            "is_final": true,
+            "speech": { "confidence": 0.9572, "tokens": tokens }
+        }),
+    ]
+}
+
+/// The voices of `/voices`, grouped by locale.
+pub fn voices() -> Value {
+    json!({
+        "en_US": [
+            {
+                "name": "wit$Rebecca",
+                "locale": "en_US",
+                "gender": "female",
+                "styles": ["default", "soft", "formal", "fast", "projected"],
+                "supported_features": ["pitch", "speed", "style", "sfx_characters", "sfx_environment"]
+            },
+            {
+                "name": "wit$Cooper",
+                "locale": "en_US",
+                "gender": "male",
+                "styles": ["default", "soft", "formal", "fast", "projected"],
+                "supported_features": ["pitch", "speed", "style"]
+            }
+        ],
+        "fr_FR": [
+            {
+                "name": "wit$Pauline",
+                "locale": "fr_FR",
+                "gender": "female",
+                "styles": ["default"],
+                "supported_features": ["pitch", "speed"]
+            }
+        ]
+    })
+}
+
+/// An entity of `/entities/:entity`, with roles, lookups and keywords.
+pub fn entity() -> Value {
+    json!({
+        "id": "571979db-f6ac-4820-bc28-a1e0787b98fc",
+        "name": "color",
+        "lookups": ["free-text", "keywords"],
+        "roles": [{ "id": "173616161059265", "name": "color" }],
+        "keywords": [
+            { "keyword": "blue", "synonyms": ["blue", "azure", "navy"] },
+            { "keyword": "red", "synonyms": ["red", "crimson"] }
+        ]
+    })
+}
+
+/// The intents of `/intents`.
+pub fn intents() -> Value {
+    json!([
+        { "id": "2690212494559269", "name": "buy_car" },
+        { "id": "254954985556896", "name": "get_weather" }
+    ])
+}
+
+/// An intent of `/intents/:intent`, with the entities it was trained with.
+pub fn intent() -> Value {
+    json!({
+        "id": "2690212494559269",
+        "name": "buy_car",
+        "entities": [
+            { "id": "254954985556896", "name": "wit$datetime:datetime" },
+            { "id": "233273197778131", "name": "car_model:model" }
+        ]
+    })
+}
+
+/// An app of `/apps/:app`, trained and idle.
+pub fn app() -> Value {
+    json!({
+        "id": "2802177596527671",
+        "name": "alarm-bot",
+        "lang": "en",
+        "private": false,
+        "created_at": "2024-03-04T10:00:00Z",
+        "training_status": "done",
+        "last_trained_at": "2024-03-04T10:05:41Z",
+        "last_training_duration_secs": 42,
+        "will_train_at": "2024-03-04T10:05:41Z"
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeOwned;
+
+    use super::*;
+    use crate::model::dictation::Dictation;
+    use crate::model::intents::IntentRef;
+    use crate::model::message::{EntityLookup, Message, TraitLookup};
+    use crate::model::server::apps::{App, TrainingStatus};
+    use crate::model::server::entities::DetailedEntity;
+    use crate::model::server::intents::DetailedIntent;
+    use crate::model::speech::SpeechResponse;
+    use crate::model::voice::VoicesResponse;
+
+    /// Deserialises `payload` as a `T` and, with `serde-serialize`, checks
+    /// that serialising it gives back a payload read as the same `T`.
+    #[cfg(feature = "serde-serialize")]
+    fn decode<T: DeserializeOwned + serde::Serialize>(payload: Value) -> T {
+        let decoded: T = serde_json::from_value(payload).unwrap();
+        let encoded = serde_json::to_value(&decoded).unwrap();
+        let again: T = serde_json::from_value(encoded.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&again).unwrap(), encoded);
+        decoded
+    }
+
+    #[cfg(not(feature = "serde-serialize"))]
+    fn decode<T: DeserializeOwned>(payload: Value) -> T {
+        serde_json::from_value(payload).unwrap()
+    }
+
+    #[test]
+    fn message() {
+        let message: Message = decode(super::message());
+        assert_eq!(message.text, "Set an alarm for tomorrow at 7am in Paris");
+        assert_eq!(message.top_intent().unwrap().name, "set_alarm");
+        let datetime = message.entity("wit$datetime:datetime").unwrap();
+        assert_eq!((datetime.start, datetime.end), (17, 32));
+        assert_eq!(datetime.body, "tomorrow at 7am");
+        let location = message.entity("wit$location:location").unwrap();
+        assert_eq!(location.type_.as_deref(), Some("resolved"));
+        assert_eq!(message.trait_value("wit$sentiment"), Some("neutral"));
+        assert_eq!(message.trait_bool("wit$greetings"), Some(false));
+    }
+
+    #[test]
+    fn speech_events() {
+        let events: Vec<SpeechResponse> = super::speech_events().into_iter().map(decode).collect();
+        match events.as_slice() {
+            [
+                SpeechResponse::PartialTranscription(partial),
+                SpeechResponse::FinalTranscription(transcription),
+                SpeechResponse::PartialUnderstanding(_),
+                SpeechResponse::FinalUnderstanding(understanding),
+            ] => {
+                assert_eq!(partial.text, "What's the");
+                assert_eq!(partial.speech.as_ref().unwrap().tokens.len(), 2);
+                assert_eq!(transcription.text, "What's the weather");
+                let speech = transcription.speech.as_ref().unwrap();
+                assert_eq!(speech.tokens[2].token, "weather");
+                assert_eq!((speech.tokens[2].start, speech.tokens[2].end), (720, 1200));
+                assert_eq!(understanding.intents[0].name, "get_weather");
+            }
+            other => panic!("unexpected events: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn dictation_events() {
+        let events: Vec<Dictation> = super::dictation_events().into_iter().map(decode).collect();
+        assert_eq!(events.len(), 2);
+        assert!(!events[0].is_final);
+        assert_eq!(events[0].text, "Hello");
+        assert!(events[1].is_final);
+        assert_eq!(events[1].text, "Hello world");
+        assert_eq!(events[1].speech.as_ref().unwrap().tokens.len(), 2);
+    }
+
+    #[test]
+    fn voices() {
+        let voices = decode::<VoicesResponse>(super::voices()).into_voices();
+        assert_eq!(voices.len(), 3);
+        let rebecca = voices
+            .iter()
+            .find(|voice| voice.name.as_str() == "wit$Rebecca")
+            .unwrap();
+        assert_eq!(rebecca.locale, "en_US");
+        assert_eq!(rebecca.gender, "female");
+        assert!(rebecca.styles.iter().any(|style| style == "soft"));
+        assert!(voices.iter().any(|voice| voice.locale == "fr_FR"));
+    }
+
+    #[test]
+    fn entity() {
+        let entity: DetailedEntity = decode(super::entity());
+        assert_eq!(entity.name, "color");
+        assert_eq!(entity.lookups, ["free-text", "keywords"]);
+        assert_eq!(entity.roles[0].name, "color");
+        assert_eq!(entity.keywords[0].keyword, "blue");
+        assert_eq!(entity.keywords[0].synonyms, ["blue", "azure", "navy"]);
+    }
+
+    #[test]
+    fn intents() {
+        let intents: Vec<IntentRef> = decode(super::intents());
+        let names: Vec<&str> = intents.iter().map(|intent| intent.name.as_str()).collect();
+        assert_eq!(names, ["buy_car", "get_weather"]);
+    }
+
+    #[test]
+    fn intent() {
+        let intent: DetailedIntent = decode(super::intent());
+        assert_eq!(intent.name, "buy_car");
+        assert_eq!(intent.entities.len(), 2);
+        assert!(intent.has_entity("car_model"));
+        assert!(intent.has_entity("wit$datetime"));
+    }
+
+    #[test]
+    fn app() {
+        let app: App = decode(super::app());
+        assert_eq!(app.name, "alarm-bot");
+        assert_eq!(app.lang, "en");
+        assert_eq!(app.training_status, TrainingStatus::Done);
+        assert_eq!(app.last_training_duration_secs, Some(42));
+        assert!(app.last_trained_at.is_some());
+    }
+}
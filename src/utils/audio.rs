@@ -0,0 +1,616 @@
+//! Helpers to work with raw audio.
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::error::WavError;
+
+/// Wraps headerless little-endian PCM samples in a WAV container.
+pub fn pcm_to_wav(samples: &Bytes, sample_rate: u32, bits: u16, channels: u16) -> Bytes {
+    let block_align = channels * bits.div_ceil(8);
+    let byte_rate = sample_rate * block_align as u32;
+    let data_len = samples.len() as u32;
+
+    let mut wav = BytesMut::with_capacity(44 + samples.len());
+    wav.put_slice(b"RIFF");
+    wav.put_u32_le(36 + data_len);
+    wav.put_slice(b"WAVE");
+
+    wav.put_slice(b"fmt ");
+    wav.put_u32_le(16);
+    wav.put_u16_le(1); // PCM
+    wav.put_u16_le(channels);
+    wav.put_u32_le(sample_rate);
+    wav.put_u32_le(byte_rate);
+    wav.put_u16_le(block_align);
+    wav.put_u16_le(bits);
+
+    wav.put_slice(b"data");
+    wav.put_u32_le(data_len);
+    wav.put_slice(samples);
+    wav.freeze()
+}
+
+/// A loudness target for [`normalize_pcm16`], in decibels relative to full
+/// scale, so `0.0` is the loudest a sample can be and `-3.0` leaves some
+/// headroom.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+    /// Scales the audio so that its loudest sample reaches the target.
+    Peak(f32),
+    /// Scales the audio so that its average loudness reaches the target.
+    /// Samples pushed past full scale are clipped.
+    Rms(f32),
+}
+
+/// Scales signed 16-bit samples to the given loudness target, such as quiet
+/// microphone input that would otherwise transcribe poorly.
+///
+/// Silent audio is returned unchanged.
+pub fn normalize_pcm16(samples: &[u8], big_endian: bool, target: Normalization) -> Bytes {
+    let read = |bytes: &[u8]| {
+        let bytes = [bytes[0], bytes[1]];
+        f64::from(if big_endian {
+            i16::from_be_bytes(bytes)
+        } else {
+            i16::from_le_bytes(bytes)
+        })
+    };
+    let frames = samples.chunks_exact(2);
+    let (level, dbfs) = match target {
+        Normalization::Peak(dbfs) => (frames.map(|s| read(s).abs()).fold(0.0, f64::max), dbfs),
+        Normalization::Rms(dbfs) => {
+            let count = (samples.len() / 2).max(1) as f64;
+            let sum: f64 = frames.map(|s| read(s).powi(2)).sum();
+            ((sum / count).sqrt(), dbfs)
+        }
+    };
+    if level == 0.0 {
+        return Bytes::copy_from_slice(samples);
+    }
+    let gain = 10f64.powf(f64::from(dbfs) / 20.0) * f64::from(i16::MAX) / level;
+
+    let mut output = BytesMut::with_capacity(samples.len());
+    for sample in samples.chunks_exact(2) {
+        let scaled = (read(sample) * gain)
+            .round()
+            .clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16;
+        if big_endian {
+            output.put_i16(scaled);
+        } else {
+            output.put_i16_le(scaled);
+        }
+    }
+    output.put_slice(samples.chunks_exact(2).remainder());
+    output.freeze()
+}
+
+/// The `fmt ` chunk format code of integer PCM samples.
+const WAVE_FORMAT_PCM: u16 = 1;
+/// The `fmt ` chunk format code of IEEE floating point samples.
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+/// The `fmt ` chunk format code of A-law samples.
+const WAVE_FORMAT_ALAW: u16 = 6;
+/// The `fmt ` chunk format code of μ-law samples.
+const WAVE_FORMAT_MULAW: u16 = 7;
+/// The `fmt ` chunk format code deferring to a sub-format GUID, whose first
+/// two bytes hold one of the codes above.
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// How the samples of a WAV file are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavSampleFormat {
+    /// Integer samples, unsigned at 8 bits and signed above.
+    Pcm,
+    /// IEEE floating point samples.
+    Float,
+    /// A-law companded samples.
+    ALaw,
+    /// μ-law companded samples.
+    MuLaw,
+}
+
+/// The layout of a WAV file, read from its header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WavHeader {
+    /// How the samples are encoded.
+    pub format: WavSampleFormat,
+    /// The number of interleaved channels.
+    pub channels: u16,
+    /// The number of frames per second.
+    pub sample_rate: u32,
+    /// The size of each sample, in bits.
+    pub bits: u16,
+    /// Whether the samples are big-endian, as in `RIFX` files.
+    pub big_endian: bool,
+    /// The byte range of the samples within the file.
+    pub data: std::ops::Range<usize>,
+}
+
+/// Reads the header of a RIFF (little-endian) or RIFX (big-endian) WAV file.
+///
+/// A `data` chunk whose declared size runs past the end of the file, as
+/// written by recorders that never went back to patch it, is taken to extend
+/// to the end of the file.
+pub fn parse_wav_header(wav: &[u8]) -> Result<WavHeader, WavError> {
+    let invalid = |reason: &str| WavError(reason.to_string());
+    let big_endian = match wav.get(..4) {
+        Some(b"RIFF") => false,
+        Some(b"RIFX") => true,
+        _ => return Err(invalid("missing RIFF signature")),
+    };
+    if wav.get(8..12) != Some(b"WAVE") {
+        return Err(invalid("missing WAVE form type"));
+    }
+    let u16_at = |offset: usize| {
+        let bytes = [wav[offset], wav[offset + 1]];
+        if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    };
+    let u32_at = |offset: usize| {
+        let bytes = [
+            wav[offset],
+            wav[offset + 1],
+            wav[offset + 2],
+            wav[offset + 3],
+        ];
+        if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    };
+
+    let mut fmt = None;
+    let mut offset = 12;
+    while offset + 8 <= wav.len() {
+        let id = &wav[offset..offset + 4];
+        let size = u32_at(offset + 4) as usize;
+        let start = offset + 8;
+        match id {
+            b"fmt " => {
+                if size < 16 || start + size > wav.len() {
+                    return Err(invalid("truncated fmt chunk"));
+                }
+                let mut code = u16_at(start);
+                if code == WAVE_FORMAT_EXTENSIBLE {
+                    if size < 40 {
+                        return Err(invalid("truncated extensible fmt chunk"));
+                    }
+                    code = u16_at(start + 24);
+                }
+                let format = match code {
+                    WAVE_FORMAT_PCM => WavSampleFormat::Pcm,
+                    WAVE_FORMAT_IEEE_FLOAT => WavSampleFormat::Float,
+                    WAVE_FORMAT_ALAW => WavSampleFormat::ALaw,
+                    WAVE_FORMAT_MULAW => WavSampleFormat::MuLaw,
+                    _ => return Err(WavError(format!("unsupported sample format {code:#06x}"))),
+                };
+                fmt = Some((
+                    format,
+                    u16_at(start + 2),
+                    u32_at(start + 4),
+                    u16_at(start + 14),
+                ));
+            }
+            b"data" => {
+                let Some((format, channels, sample_rate, bits)) = fmt else {
+                    return Err(invalid("data chunk before the fmt chunk"));
+                };
+                if channels == 0 || sample_rate == 0 || bits == 0 {
+                    return Err(invalid("zero channels, sample rate or sample size"));
+                }
+                return Ok(WavHeader {
+                    format,
+                    channels,
+                    sample_rate,
+                    bits,
+                    big_endian,
+                    data: start..start.saturating_add(size).min(wav.len()),
+                });
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even size.
+        offset = start.saturating_add(size).saturating_add(size % 2);
+    }
+    Err(invalid("missing data chunk"))
+}
+
+/// The sample rate audio is converted to by [`resample`], the one Wit.ai
+/// recommends.
+#[cfg(feature = "audio-dsp")]
+pub const TARGET_SAMPLE_RATE: u32 = 16_000;
+
+/// How interleaved little-endian PCM samples are stored.
+#[cfg(feature = "audio-dsp")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcmSample {
+    /// Signed 16-bit integers.
+    I16,
+    /// 32-bit floats between -1 and 1.
+    F32,
+}
+
+#[cfg(feature = "audio-dsp")]
+impl PcmSample {
+    fn size(self) -> usize {
+        match self {
+            PcmSample::I16 => 2,
+            PcmSample::F32 => 4,
+        }
+    }
+
+    fn read(self, bytes: &[u8]) -> f32 {
+        match self {
+            PcmSample::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32_768.0,
+            PcmSample::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+}
+
+/// Converts interleaved PCM audio to mono, signed 16-bit little-endian
+/// samples at [`TARGET_SAMPLE_RATE`], ready to be sent as
+/// [`Encoding::Raw`](crate::model::speech::Encoding::Raw) audio.
+///
+/// Channels are averaged. Downsampling averages the input samples covered by
+/// each output sample, which filters out most of the aliasing, and
+/// upsampling interpolates linearly. A trailing partial frame is dropped.
+#[cfg(feature = "audio-dsp")]
+pub fn resample(pcm: &[u8], sample: PcmSample, channels: u16, sample_rate: u32) -> Bytes {
+    let mut resampler = Resampler::new(sample, channels, sample_rate);
+    let mut output = resampler.push(pcm);
+    output.extend_from_slice(&resampler.finish());
+    output.freeze()
+}
+
+/// Incrementally converts PCM audio, carrying partial frames and the
+/// resampling position from one chunk to the next.
+#[cfg(feature = "audio-dsp")]
+pub(crate) struct Resampler {
+    sample: PcmSample,
+    channels: usize,
+    /// Input samples per output sample.
+    ratio: f64,
+    /// The bytes of a frame split across chunks.
+    pending: Vec<u8>,
+    /// Downmixed input frames not consumed yet.
+    frames: Vec<f32>,
+    /// Where the next output sample starts, in frames from `frames[0]`.
+    position: f64,
+}
+
+#[cfg(feature = "audio-dsp")]
+impl Resampler {
+    pub(crate) fn new(sample: PcmSample, channels: u16, sample_rate: u32) -> Self {
+        Self {
+            sample,
+            channels: usize::from(channels.max(1)),
+            ratio: f64::from(sample_rate.max(1)) / f64::from(TARGET_SAMPLE_RATE),
+            pending: Vec::new(),
+            frames: Vec::new(),
+            position: 0.0,
+        }
+    }
+
+    /// Converts the next chunk of input, returning the output it completes.
+    pub(crate) fn push(&mut self, pcm: &[u8]) -> BytesMut {
+        self.pending.extend_from_slice(pcm);
+        let frame_size = self.sample.size() * self.channels;
+        let complete = self.pending.len() - self.pending.len() % frame_size;
+        for frame in self.pending[..complete].chunks_exact(frame_size) {
+            let sum: f32 = frame
+                .chunks_exact(self.sample.size())
+                .map(|bytes| self.sample.read(bytes))
+                .sum();
+            self.frames.push(sum / self.channels as f32);
+        }
+        self.pending.drain(..complete);
+        self.drain(false)
+    }
+
+    /// Converts the input left once the audio is over.
+    pub(crate) fn finish(&mut self) -> BytesMut {
+        self.drain(true)
+    }
+
+    fn drain(&mut self, finished: bool) -> BytesMut {
+        let mut output = BytesMut::new();
+        let last = self.frames.len();
+        loop {
+            let start = self.position.floor() as usize;
+            let value = if self.ratio > 1.0 {
+                let end = ((self.position + self.ratio).floor() as usize).max(start + 1);
+                if end > last && !(finished && start < last) {
+                    break;
+                }
+                let window = &self.frames[start..end.min(last)];
+                window.iter().sum::<f32>() / window.len() as f32
+            } else {
+                if start + 1 >= last && !(finished && start < last) {
+                    break;
+                }
+                let next = self.frames.get(start + 1).unwrap_or(&self.frames[start]);
+                let weight = (self.position - start as f64) as f32;
+                self.frames[start] + (next - self.frames[start]) * weight
+            };
+            output.put_i16_le((value.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16);
+            self.position += self.ratio;
+        }
+        let consumed = (self.position.floor() as usize).min(last);
+        self.frames.drain(..consumed);
+        self.position -= consumed as f64;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pcm_round_trips_through_wav() {
+        let samples = Bytes::from_static(&[1, 0, 2, 0, 3, 0, 4, 0]);
+        let wav = pcm_to_wav(&samples, 22_050, 16, 2);
+        assert_eq!(wav.len(), 44 + samples.len());
+        assert_eq!(&wav[4..8], (36 + 8u32).to_le_bytes());
+        // The byte rate and block align of 16-bit stereo.
+        assert_eq!(&wav[28..32], (22_050u32 * 4).to_le_bytes());
+        assert_eq!(&wav[32..34], 4u16.to_le_bytes());
+
+        let header = parse_wav_header(&wav).unwrap();
+        assert_eq!(header.format, WavSampleFormat::Pcm);
+        assert_eq!(
+            (header.channels, header.sample_rate, header.bits),
+            (2, 22_050, 16)
+        );
+        assert!(!header.big_endian);
+        assert_eq!(&wav[header.data], &samples[..]);
+    }
+
+    /// A WAV file made of `chunks`, little-endian unless `big_endian`.
+    fn wav(big_endian: bool, chunks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let u32_bytes = |n: u32| {
+            if big_endian {
+                n.to_be_bytes()
+            } else {
+                n.to_le_bytes()
+            }
+        };
+        let mut wav = Vec::new();
+        wav.extend_from_slice(if big_endian { b"RIFX" } else { b"RIFF" });
+        wav.extend_from_slice(&[0; 4]);
+        wav.extend_from_slice(b"WAVE");
+        for (id, body) in chunks {
+            wav.extend_from_slice(*id);
+            wav.extend_from_slice(&u32_bytes(body.len() as u32));
+            wav.extend_from_slice(body);
+            if body.len() % 2 == 1 {
+                wav.push(0);
+            }
+        }
+        wav
+    }
+
+    /// A 16-byte `fmt ` chunk body.
+    fn fmt(big_endian: bool, code: u16, channels: u16, sample_rate: u32, bits: u16) -> Vec<u8> {
+        let u16_bytes = |n: u16| {
+            if big_endian {
+                n.to_be_bytes()
+            } else {
+                n.to_le_bytes()
+            }
+        };
+        let u32_bytes = |n: u32| {
+            if big_endian {
+                n.to_be_bytes()
+            } else {
+                n.to_le_bytes()
+            }
+        };
+        [
+            &u16_bytes(code)[..],
+            &u16_bytes(channels),
+            &u32_bytes(sample_rate),
+            &u32_bytes(0),
+            &u16_bytes(0),
+            &u16_bytes(bits),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn big_endian_files_are_read() {
+        let wav = wav(
+            true,
+            &[(b"fmt ", fmt(true, 1, 1, 8_000, 16)), (b"data", vec![1, 2])],
+        );
+        let header = parse_wav_header(&wav).unwrap();
+        assert!(header.big_endian);
+        assert_eq!((header.channels, header.sample_rate), (1, 8_000));
+        assert_eq!(&wav[header.data], [1, 2]);
+    }
+
+    #[test]
+    fn odd_sized_chunks_are_skipped_with_their_padding() {
+        let wav = wav(
+            false,
+            &[
+                (b"LIST", vec![1, 2, 3]),
+                (b"fmt ", fmt(false, 7, 1, 8_000, 8)),
+                (b"data", vec![9; 4]),
+            ],
+        );
+        let header = parse_wav_header(&wav).unwrap();
+        assert_eq!(header.format, WavSampleFormat::MuLaw);
+        assert_eq!(&wav[header.data], [9; 4]);
+    }
+
+    #[test]
+    fn extensible_formats_use_their_sub_format() {
+        let mut extensible = fmt(false, WAVE_FORMAT_EXTENSIBLE, 2, 48_000, 32);
+        extensible.extend_from_slice(&22u16.to_le_bytes());
+        extensible.extend_from_slice(&[0; 6]);
+        extensible.extend_from_slice(&WAVE_FORMAT_IEEE_FLOAT.to_le_bytes());
+        extensible.extend_from_slice(&[0; 14]);
+        let wav = wav(false, &[(b"fmt ", extensible), (b"data", vec![0; 8])]);
+        let header = parse_wav_header(&wav).unwrap();
+        assert_eq!(header.format, WavSampleFormat::Float);
+        assert_eq!((header.channels, header.bits), (2, 32));
+    }
+
+    #[test]
+    fn unpatched_data_sizes_extend_to_the_end_of_the_file() {
+        let mut wav = wav(
+            false,
+            &[(b"fmt ", fmt(false, 1, 1, 16_000, 16)), (b"data", vec![])],
+        );
+        let size = wav.len() - 4;
+        wav[size..].copy_from_slice(&u32::MAX.to_le_bytes());
+        wav.extend_from_slice(&[5; 6]);
+        let header = parse_wav_header(&wav).unwrap();
+        assert_eq!(&wav[header.data], [5; 6]);
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        let reason = |wav: &[u8]| parse_wav_header(wav).unwrap_err().0;
+        let fmt_16 = || fmt(false, 1, 1, 16_000, 16);
+        assert_eq!(reason(b"OggS"), "missing RIFF signature");
+        assert_eq!(reason(b"RIFF\0\0\0\0AVI "), "missing WAVE form type");
+        assert_eq!(
+            reason(&wav(false, &[(b"data", vec![0; 2])])),
+            "data chunk before the fmt chunk"
+        );
+        assert_eq!(
+            reason(&wav(false, &[(b"fmt ", fmt_16())])),
+            "missing data chunk"
+        );
+        assert_eq!(
+            reason(&wav(false, &[(b"fmt ", vec![0; 8]), (b"data", vec![])])),
+            "truncated fmt chunk"
+        );
+        assert_eq!(
+            reason(&wav(
+                false,
+                &[(b"fmt ", fmt(false, 2, 1, 16_000, 4)), (b"data", vec![])]
+            )),
+            "unsupported sample format 0x0002"
+        );
+        assert_eq!(
+            reason(&wav(
+                false,
+                &[(b"fmt ", fmt(false, 1, 0, 16_000, 16)), (b"data", vec![])]
+            )),
+            "zero channels, sample rate or sample size"
+        );
+    }
+
+    #[cfg(feature = "audio-dsp")]
+    fn pcm16(samples: impl IntoIterator<Item = i16>) -> Vec<u8> {
+        samples.into_iter().flat_map(i16::to_le_bytes).collect()
+    }
+
+    #[cfg(feature = "audio-dsp")]
+    #[test]
+    fn resampling_scales_the_length_by_the_rate() {
+        let tenth = |rate: u32| pcm16((0..rate / 10).map(|i| (i % 100) as i16));
+        for rate in [8_000, 16_000, 22_050, 44_100, 48_000] {
+            let output = resample(&tenth(rate), PcmSample::I16, 1, rate);
+            // A tenth of a second at 16 kHz, give or take the last sample.
+            let samples = output.len() / 2;
+            assert!((1_599..=1_601).contains(&samples), "{rate} Hz: {samples}");
+        }
+    }
+
+    #[cfg(feature = "audio-dsp")]
+    #[test]
+    fn resampling_downmixes_and_drops_partial_frames() {
+        let stereo: Vec<u8> = [0.5f32, -0.5, 0.25, 0.25]
+            .into_iter()
+            .flat_map(f32::to_le_bytes)
+            .chain([1, 2, 3])
+            .collect();
+        let output = resample(&stereo, PcmSample::F32, 2, 16_000);
+        assert_eq!(&output[..], pcm16([0, (0.25 * f32::from(i16::MAX)) as i16]));
+    }
+
+    #[cfg(feature = "audio-dsp")]
+    #[test]
+    fn chunked_resampling_matches_the_whole_closely() {
+        let pcm = pcm16((0..4_410).map(|i| (i * 7 % 2_000) as i16));
+        let whole = resample(&pcm, PcmSample::I16, 1, 44_100);
+        let mut resampler = Resampler::new(PcmSample::I16, 1, 44_100);
+        let mut chunked = BytesMut::new();
+        // Odd chunk sizes split samples across chunks.
+        for chunk in pcm.chunks(333) {
+            chunked.extend_from_slice(&resampler.push(chunk));
+        }
+        chunked.extend_from_slice(&resampler.finish());
+        // Window edges may land a frame apart where the position was rebased.
+        assert_eq!(chunked.len(), whole.len());
+        for (chunked, whole) in chunked.chunks(2).zip(whole.chunks(2)) {
+            let (chunked, whole) = (
+                i16::from_le_bytes([chunked[0], chunked[1]]),
+                i16::from_le_bytes([whole[0], whole[1]]),
+            );
+            assert!(chunked.abs_diff(whole) <= 8, "{chunked} != {whole}");
+        }
+    }
+
+    fn samples(bytes: &[u8], big_endian: bool) -> Vec<i16> {
+        bytes
+            .chunks_exact(2)
+            .map(|s| {
+                let s = [s[0], s[1]];
+                if big_endian {
+                    i16::from_be_bytes(s)
+                } else {
+                    i16::from_le_bytes(s)
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn peak_normalization_brings_the_loudest_sample_to_the_target() {
+        let quiet: Vec<u8> = [1_000i16, -2_000]
+            .into_iter()
+            .flat_map(i16::to_le_bytes)
+            .collect();
+        let loud = normalize_pcm16(&quiet, false, Normalization::Peak(0.0));
+        assert_eq!(samples(&loud, false), [16_384, -32_767]);
+
+        let halved = normalize_pcm16(&quiet, false, Normalization::Peak(-20.0 * 2f32.log10()));
+        assert_eq!(samples(&halved, false), [8_192, -16_383]);
+    }
+
+    #[test]
+    fn rms_normalization_clips_what_it_pushes_past_full_scale() {
+        let pcm: Vec<u8> = [100i16, -100, 100, 4_000]
+            .into_iter()
+            .flat_map(i16::to_be_bytes)
+            .collect();
+        let normalized = normalize_pcm16(&pcm, true, Normalization::Rms(0.0));
+        let normalized = samples(&normalized, true);
+        assert_eq!(normalized[3], i16::MAX);
+        assert_eq!(normalized[0], -normalized[1]);
+        assert!(normalized[0] > 100);
+    }
+
+    #[test]
+    fn silence_and_trailing_bytes_are_kept() {
+        let silence = [0u8; 6];
+        assert_eq!(
+            normalize_pcm16(&silence, false, Normalization::Peak(0.0)),
+            &silence[..]
+        );
+
+        let odd = [0x10, 0x00, 0x7f];
+        let normalized = normalize_pcm16(&odd, false, Normalization::Peak(0.0));
+        assert_eq!(samples(&normalized, false), [i16::MAX]);
+        assert_eq!(normalized[2], 0x7f);
+    }
+}
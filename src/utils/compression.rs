@@ -0,0 +1,138 @@
+//! Compressing uploaded audio, behind the `compression` feature.
+//!
+//! Compression pays off for uncompressed audio, such as WAV or raw PCM sent
+//! from a device on a slow link. Encoded formats such as MP3 or Opus barely
+//! shrink and are better sent as they are.
+
+use std::io::{self, Write};
+
+use bytes::Bytes;
+use flate2::write::{GzEncoder, ZlibEncoder};
+
+/// How uploaded audio is compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// gzip, sent as `Content-Encoding: gzip`.
+    Gzip,
+    /// zlib-wrapped deflate, sent as `Content-Encoding: deflate`.
+    Deflate,
+}
+
+impl Compression {
+    /// The value of the `Content-Encoding` header of compressed uploads.
+    pub fn content_encoding(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Deflate => "deflate",
+        }
+    }
+
+    /// Compresses a whole buffer.
+    pub fn compress(&self, data: &[u8]) -> io::Result<Bytes> {
+        let mut compressor = Compressor::new(*self);
+        let mut output = compressor.push(data)?.to_vec();
+        output.extend_from_slice(&compressor.finish()?);
+        Ok(output.into())
+    }
+}
+
+/// Compresses a stream chunk by chunk, flushing after each chunk so that
+/// Wit.ai can start decoding before the stream ends.
+pub(crate) enum Compressor {
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+}
+
+impl Compressor {
+    pub(crate) fn new(compression: Compression) -> Self {
+        let level = flate2::Compression::default();
+        match compression {
+            Compression::Gzip => Compressor::Gzip(GzEncoder::new(Vec::new(), level)),
+            Compression::Deflate => Compressor::Deflate(ZlibEncoder::new(Vec::new(), level)),
+        }
+    }
+
+    /// Compresses a chunk, returning the compressed bytes available so far.
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> io::Result<Bytes> {
+        let output = match self {
+            Compressor::Gzip(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+            Compressor::Deflate(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+        };
+        Ok(std::mem::take(output).into())
+    }
+
+    /// Ends the stream, returning the remaining compressed bytes.
+    pub(crate) fn finish(self) -> io::Result<Bytes> {
+        let output = match self {
+            Compressor::Gzip(encoder) => encoder.finish()?,
+            Compressor::Deflate(encoder) => encoder.finish()?,
+        };
+        Ok(output.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::{GzDecoder, ZlibDecoder};
+
+    use super::*;
+
+    fn decompress(compression: Compression, data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        match compression {
+            Compression::Gzip => GzDecoder::new(data).read_to_end(&mut output),
+            Compression::Deflate => ZlibDecoder::new(data).read_to_end(&mut output),
+        }
+        .unwrap();
+        output
+    }
+
+    fn audio() -> Vec<u8> {
+        (0..4_096u32)
+            .flat_map(|i| ((i % 64) as i16).to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn buffers_round_trip() {
+        for compression in [Compression::Gzip, Compression::Deflate] {
+            let compressed = compression.compress(&audio()).unwrap();
+            assert!(compressed.len() < audio().len());
+            assert_eq!(decompress(compression, &compressed), audio());
+        }
+        let gzip = Compression::Gzip.compress(b"").unwrap();
+        assert_eq!(&gzip[..2], [0x1f, 0x8b]);
+        assert!(decompress(Compression::Gzip, &gzip).is_empty());
+    }
+
+    #[test]
+    fn streams_round_trip_and_flush_each_chunk() {
+        for compression in [Compression::Gzip, Compression::Deflate] {
+            let mut compressor = Compressor::new(compression);
+            let mut compressed = Vec::new();
+            for chunk in audio().chunks(1_000) {
+                let flushed = compressor.push(chunk).unwrap();
+                assert!(!flushed.is_empty());
+                compressed.extend_from_slice(&flushed);
+            }
+            compressed.extend_from_slice(&compressor.finish().unwrap());
+            assert_eq!(decompress(compression, &compressed), audio());
+        }
+    }
+
+    #[test]
+    fn content_encodings() {
+        assert_eq!(Compression::Gzip.content_encoding(), "gzip");
+        assert_eq!(Compression::Deflate.content_encoding(), "deflate");
+    }
+}
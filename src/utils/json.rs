@@ -0,0 +1,197 @@
+//! Helpers for the concatenated JSON objects streamed by the audio endpoints.
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt, stream};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::error::ApiError;
+
+/// Removes the first complete JSON object from `buffer` and returns it.
+///
+/// Anything before the opening brace, such as the whitespace Wit.ai puts
+/// between objects, is discarded. Braces inside strings, such as a dictated
+/// "curly brace", are not counted. Returns `None` and leaves the buffer
+/// untouched when no complete object is available yet.
+pub fn extract_complete_json(buffer: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let start = buffer.iter().position(|&b| b == b'{')?;
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, &byte) in buffer.iter().enumerate().skip(start) {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    let object = buffer[start..=i].to_vec();
+                    buffer.drain(..=i);
+                    return Some(object);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Deserialises a streamed JSON object.
+pub(crate) fn parse_json<T: DeserializeOwned>(object: &[u8]) -> Result<T, ApiError> {
+    Ok(serde_json::from_slice(object)?)
+}
+
+/// Deserialises a streamed event, tagged with its `type`.
+///
+/// An object without a string `type` breaks the streaming protocol rather
+/// than being a malformed event, and is reported as
+/// [`ApiError::ProtocolError`] with the offending payload.
+pub(crate) fn parse_event<T: DeserializeOwned>(object: &[u8]) -> Result<T, ApiError> {
+    let value: Value = serde_json::from_slice(object)?;
+    if !value.get("type").is_some_and(Value::is_string) {
+        let payload = String::from_utf8_lossy(object).into_owned();
+        tracing::warn!(%payload, "streamed event without a type");
+        return Err(ApiError::ProtocolError { payload });
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Empties the buffer of a finished stream, returning the
+/// [`ApiError::ProtocolError`] of the object it ended in the middle of, if
+/// any.
+pub(crate) fn truncated_object(buffer: &mut Vec<u8>) -> Option<ApiError> {
+    if buffer.iter().all(u8::is_ascii_whitespace) {
+        return None;
+    }
+    let payload = String::from_utf8_lossy(buffer).into_owned();
+    tracing::warn!(%payload, "event stream ended in the middle of an object");
+    buffer.clear();
+    Some(ApiError::ProtocolError { payload })
+}
+
+/// Turns a chunked response body into a stream of JSON objects deserialised
+/// with `parse`.
+///
+/// A body ending in the middle of an object yields an
+/// [`ApiError::ProtocolError`] with what was received of it.
+pub(crate) fn json_stream<T, S>(
+    body: S,
+    parse: fn(&[u8]) -> Result<T, ApiError>,
+) -> impl Stream<Item = Result<T, ApiError>>
+where
+    S: Stream<Item = reqwest::Result<Bytes>> + Unpin,
+{
+    stream::unfold(
+        (body, Vec::new(), false),
+        move |(mut body, mut buffer, mut finished)| async move {
+            loop {
+                if let Some(object) = extract_complete_json(&mut buffer) {
+                    let item = parse(&object);
+                    return Some((item, (body, buffer, finished)));
+                }
+                if finished {
+                    let error = truncated_object(&mut buffer)?;
+                    return Some((Err(error), (body, buffer, finished)));
+                }
+                match body.next().await {
+                    Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                    Some(Err(e)) => {
+                        // The transport error already says why the object is cut.
+                        buffer.clear();
+                        return Some((Err(e.into()), (body, buffer, true)));
+                    }
+                    None => finished = true,
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    fn extract_all(chunks: &[&str]) -> Vec<String> {
+        let mut buffer = Vec::new();
+        let mut objects = Vec::new();
+        for chunk in chunks {
+            buffer.extend_from_slice(chunk.as_bytes());
+            while let Some(object) = extract_complete_json(&mut buffer) {
+                objects.push(String::from_utf8(object).unwrap());
+            }
+        }
+        objects
+    }
+
+    #[test]
+    fn braces_inside_strings_are_not_counted() {
+        let objects = extract_all(&[r#"{"text":"a } and a {"} {"n":1}"#]);
+        assert_eq!(objects, [r#"{"text":"a } and a {"}"#, r#"{"n":1}"#]);
+    }
+
+    #[test]
+    fn escaped_quotes_do_not_end_strings() {
+        let objects = extract_all(&[r#"{"text":"say \"}\" please"}"#]);
+        assert_eq!(objects, [r#"{"text":"say \"}\" please"}"#]);
+    }
+
+    #[test]
+    fn escaped_backslashes_before_a_quote_end_strings() {
+        let objects = extract_all(&[r#"{"path":"C:\\"}{"n":2}"#]);
+        assert_eq!(objects, [r#"{"path":"C:\\"}"#, r#"{"n":2}"#]);
+    }
+
+    #[test]
+    fn objects_split_across_chunks_are_reassembled() {
+        let objects = extract_all(&[
+            r#"{"text":"a"#,
+            r#" \"#,
+            r#""}"}"#,
+            "\r\n",
+            r#"{"n""#,
+            ":3}",
+        ]);
+        assert_eq!(objects, [r#"{"text":"a \"}"}"#, r#"{"n":3}"#]);
+    }
+
+    #[test]
+    fn incomplete_objects_stay_buffered() {
+        let mut buffer = br#"{"text":"}"#.to_vec();
+        assert_eq!(extract_complete_json(&mut buffer), None);
+        assert_eq!(buffer, br#"{"text":"}"#);
+    }
+
+    fn collect(chunks: &[&'static str]) -> Vec<Result<Value, ApiError>> {
+        let body = stream::iter(chunks.iter().map(|chunk| Ok(Bytes::from(*chunk))));
+        block_on(json_stream(body, parse_json::<Value>).collect())
+    }
+
+    #[test]
+    fn streams_end_cleanly_after_trailing_whitespace() {
+        let items = collect(&[r#"{"n":1}"#, "\r\n"]);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].as_ref().unwrap()["n"], 1);
+    }
+
+    #[test]
+    fn truncated_trailing_objects_are_protocol_errors() {
+        let items = collect(&[r#"{"n":1}{"n":"#]);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_ref().unwrap()["n"], 1);
+        match &items[1] {
+            Err(ApiError::ProtocolError { payload }) => assert_eq!(payload, r#"{"n":"#),
+            other => panic!("unexpected item: {other:?}"),
+        }
+    }
+}
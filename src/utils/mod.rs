@@ -0,0 +1,5 @@
+pub mod audio;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod json;
+pub mod storage;
@@ -0,0 +1,269 @@
+//! Storage backends for temporary audio buffers.
+//!
+//! Any feature of this crate that needs to hold audio outside of the request
+//! body itself (spilling to disk, replaying a recording, queueing uploads while
+//! offline) goes through the [`BufferStorage`] trait. Wrapping a backend in an
+//! [`EncryptedStorage`] guarantees that the bytes handed to it are encrypted by
+//! a user-supplied [`Cipher`] before they ever reach the underlying storage.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// A place where audio buffers can be stored temporarily, keyed by a string.
+///
+/// Implementations must be safe to share between threads, as buffers may be
+/// written by an upload task while another task reads them back.
+pub trait BufferStorage: Send + Sync {
+    /// Stores `data` under `key`, replacing any previous buffer with that key.
+    fn store(&self, key: &str, data: &[u8]) -> io::Result<()>;
+
+    /// Loads the buffer stored under `key`, if any.
+    fn load(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// Removes the buffer stored under `key`. Removing a missing key is not an error.
+    fn remove(&self, key: &str) -> io::Result<()>;
+}
+
+impl<S: BufferStorage + ?Sized> BufferStorage for Arc<S> {
+    fn store(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        (**self).store(key, data)
+    }
+
+    fn load(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        (**self).load(key)
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()> {
+        (**self).remove(key)
+    }
+}
+
+/// A hook used by [`EncryptedStorage`] to encrypt buffers at rest.
+///
+/// The crate does not ship any cryptography of its own; bring whichever
+/// primitive your compliance requirements mandate.
+pub trait Cipher: Send + Sync {
+    /// Encrypts a plaintext buffer.
+    fn encrypt(&self, plaintext: &[u8]) -> io::Result<Vec<u8>>;
+
+    /// Decrypts a buffer previously produced by [`Cipher::encrypt`].
+    fn decrypt(&self, ciphertext: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+/// Keeps buffers in memory. Nothing is ever written to disk.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    buffers: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    /// Creates an empty in-memory storage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BufferStorage for MemoryStorage {
+    fn store(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        self.buffers
+            .lock()
+            .map_err(|_| io::Error::other("memory storage lock poisoned"))?
+            .insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn load(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(self
+            .buffers
+            .lock()
+            .map_err(|_| io::Error::other("memory storage lock poisoned"))?
+            .get(key)
+            .cloned())
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()> {
+        self.buffers
+            .lock()
+            .map_err(|_| io::Error::other("memory storage lock poisoned"))?
+            .remove(key);
+        Ok(())
+    }
+}
+
+/// Spills buffers to files inside a directory, one file per key.
+///
+/// On its own this writes plaintext audio; wrap it in an [`EncryptedStorage`]
+/// when the data is sensitive.
+#[derive(Debug, Clone)]
+pub struct DiskStorage {
+    directory: PathBuf,
+}
+
+impl DiskStorage {
+    /// Creates a disk storage rooted at `directory`, creating it if needed.
+    pub fn new(directory: impl AsRef<Path>) -> io::Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        fs::create_dir_all(&directory)?;
+        Ok(Self { directory })
+    }
+
+    /// The directory buffers are written to.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        // Keys are user-controlled, so keep them from escaping the directory.
+        // Percent-encoding keeps distinct keys in distinct files, even on
+        // case-insensitive file systems since only lowercase letters are kept.
+        let mut file_name = String::with_capacity(key.len());
+        for byte in key.bytes() {
+            match byte {
+                b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' => file_name.push(char::from(byte)),
+                _ => file_name.push_str(&format!("%{byte:02X}")),
+            }
+        }
+        self.directory.join(format!("{file_name}.buf"))
+    }
+}
+
+impl BufferStorage for DiskStorage {
+    fn store(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        fs::write(self.path_for(key), data)
+    }
+
+    fn load(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.path_for(key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path_for(key)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Encrypts every buffer with a [`Cipher`] before handing it to the inner storage.
+#[derive(Debug, Clone)]
+pub struct EncryptedStorage<S, C> {
+    inner: S,
+    cipher: C,
+}
+
+impl<S: BufferStorage, C: Cipher> EncryptedStorage<S, C> {
+    /// Wraps `inner` so that it only ever sees data encrypted by `cipher`.
+    pub fn new(inner: S, cipher: C) -> Self {
+        Self { inner, cipher }
+    }
+
+    /// Returns the wrapped storage.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: BufferStorage, C: Cipher> BufferStorage for EncryptedStorage<S, C> {
+    fn store(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        let encrypted = self.cipher.encrypt(data)?;
+        self.inner.store(key, &encrypted)
+    }
+
+    fn load(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match self.inner.load(key)? {
+            Some(encrypted) => self.cipher.decrypt(&encrypted).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()> {
+        self.inner.remove(key)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    /// A toy cipher flipping every bit and appending a marker, enough to tell
+    /// encrypted bytes from the plaintext.
+    pub(crate) struct Flip;
+
+    impl Cipher for Flip {
+        fn encrypt(&self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+            let mut encrypted: Vec<u8> = plaintext.iter().map(|byte| !byte).collect();
+            encrypted.push(0xaa);
+            Ok(encrypted)
+        }
+
+        fn decrypt(&self, ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+            match ciphertext.split_last() {
+                Some((0xaa, data)) => Ok(data.iter().map(|byte| !byte).collect()),
+                _ => Err(io::Error::other("not encrypted by Flip")),
+            }
+        }
+    }
+
+    #[test]
+    fn memory_storage_round_trips() {
+        let storage = MemoryStorage::new();
+        assert_eq!(storage.load("a").unwrap(), None);
+        storage.store("a", b"audio").unwrap();
+        storage.store("a", b"other").unwrap();
+        assert_eq!(storage.load("a").unwrap().unwrap(), b"other");
+        storage.remove("a").unwrap();
+        storage.remove("a").unwrap();
+        assert_eq!(storage.load("a").unwrap(), None);
+    }
+
+    #[test]
+    fn disk_storage_round_trips() {
+        let directory = std::env::temp_dir().join(format!("wit-owo-disk-{}", std::process::id()));
+        let storage = DiskStorage::new(&directory).unwrap();
+        storage.store("a/b", b"audio").unwrap();
+        assert_eq!(storage.load("a/b").unwrap().unwrap(), b"audio");
+        assert_eq!(storage.load("a_b").unwrap(), None);
+        storage.remove("a/b").unwrap();
+        storage.remove("a/b").unwrap();
+        assert_eq!(storage.load("a/b").unwrap(), None);
+        fs::remove_dir(&directory).unwrap();
+    }
+
+    #[test]
+    fn encrypted_storage_never_hands_over_plaintext() {
+        let inner = Arc::new(MemoryStorage::new());
+        let storage = EncryptedStorage::new(inner.clone(), Flip);
+        let audio = b"RIFF plaintext audio";
+        storage.store("clip", audio).unwrap();
+
+        let stored = inner.load("clip").unwrap().unwrap();
+        assert!(!stored.windows(audio.len()).any(|window| window == audio));
+        assert_eq!(storage.load("clip").unwrap().unwrap(), audio);
+        assert!(Flip.decrypt(audio).is_err());
+    }
+
+    #[test]
+    fn disk_keys_map_to_distinct_files() {
+        let storage = DiskStorage {
+            directory: PathBuf::from("cache"),
+        };
+        let keys = ["a.b", "a/b", "a_b", "a%2Eb", "A", "a", "../a"];
+        let paths: HashSet<PathBuf> = keys.iter().map(|key| storage.path_for(key)).collect();
+        assert_eq!(paths.len(), keys.len());
+        assert_eq!(
+            storage.path_for("synthesize-0f"),
+            Path::new("cache/synthesize-0f.buf")
+        );
+        assert_eq!(storage.path_for("a/b"), Path::new("cache/a%2Fb.buf"));
+        assert_eq!(storage.path_for("../a"), Path::new("cache/%2E%2E%2Fa.buf"));
+    }
+}